@@ -0,0 +1,214 @@
+//! # Sequence Analysis
+//! Flags the segments of a connection the way Wireshark's `tcp.analysis` does:
+//! retransmissions (and which of them are fast or spurious), out-of-order
+//! segments, duplicate ACKs, zero window probes, full windows and keep-alives.
+//! Each direction remembers the highest sequence number it sent, with when it
+//! was sent, and its last acknowledgement and window. Windows are scaled by the
+//! shifts the handshake negotiated, as a `WindowTracker` follows them, and taken
+//! as they appear in the header when the handshake was not seen.
+//!
+//! # Example
+//! ```rust
+//! use tcp_parser::TcpSegment;
+//! use tcp_parser::stream::Direction;
+//! use tcp_parser::analysis::{SequenceAnalyzer, AnalysisConfig, RETRANSMISSION};
+//! let mut analyzer = SequenceAnalyzer::new(AnalysisConfig::default());
+//! let segment = TcpSegment::parse(&[0x30, 0x39, 0, 80, 0, 0, 0, 1, 0, 0, 0, 1, 0x50, 0x18, 1, 0, 0, 0, 0, 0,
+//!                                   b'h', b'i']);
+//! assert!(analyzer.push(Direction::ClientToServer, &segment, 0).flags.is_empty());
+//! assert_eq!(analyzer.push(Direction::ClientToServer, &segment, 200).flags, RETRANSMISSION);
+//! ```
+
+use super::{TcpSegment, SeqNum, SYN, FIN, RST, ACK};
+use stream::Direction;
+use window::WindowTracker;
+
+bitflags! {
+    /// What the analysis found about a segment
+    flags AnalysisFlags : u16 {
+        /// Data, SYN or FIN sent again
+        const RETRANSMISSION          = 0b000000001,
+        /// A retransmission of data the receiver had already acknowledged
+        const SPURIOUS_RETRANSMISSION = 0b000000010,
+        /// A retransmission of the data duplicate ACKs asked for
+        const FAST_RETRANSMISSION     = 0b000000100,
+        /// Data below the highest sequence number, sent too soon after it to be a retransmission
+        const OUT_OF_ORDER            = 0b000001000,
+        /// An ACK repeating the previous one, with the same window and no data
+        const DUPLICATE_ACK           = 0b000010000,
+        /// One byte sent into a zero window
+        const ZERO_WINDOW_PROBE       = 0b000100000,
+        /// Data filling up the window the receiver advertised
+        const WINDOW_FULL             = 0b001000000,
+        /// An empty or one byte segment one before the next sequence number
+        const KEEP_ALIVE              = 0b010000000
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Thresholds of a sequence analyzer
+pub struct AnalysisConfig {
+    /// How soon after the highest sequence number, in milliseconds, a segment
+    /// filling in below it counts as out of order rather than a retransmission
+    pub out_of_order_threshold : u64
+}
+
+impl Default for AnalysisConfig {
+    fn default() -> AnalysisConfig {
+        AnalysisConfig {
+            out_of_order_threshold : 3
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// The analysis of one segment
+pub struct SegmentAnalysis {
+    /// Retransmissions are also flagged `RETRANSMISSION` when they are fast or spurious
+    pub flags           : AnalysisFlags,
+    /// How many duplicates of the same ACK were sent so far, counting this one
+    pub dup_ack_count   : u32
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// Number of segments given each flag
+pub struct AnalysisStats {
+    pub retransmissions         : u64,
+    pub spurious_retransmissions: u64,
+    pub fast_retransmissions    : u64,
+    pub out_of_order            : u64,
+    pub duplicate_acks          : u64,
+    pub zero_window_probes      : u64,
+    pub window_full             : u64,
+    pub keep_alives             : u64
+}
+
+impl AnalysisStats {
+    fn count(&mut self, flags : AnalysisFlags) {
+        let mut counters = [(RETRANSMISSION, &mut self.retransmissions),
+                            (SPURIOUS_RETRANSMISSION, &mut self.spurious_retransmissions),
+                            (FAST_RETRANSMISSION, &mut self.fast_retransmissions),
+                            (OUT_OF_ORDER, &mut self.out_of_order),
+                            (DUPLICATE_ACK, &mut self.duplicate_acks),
+                            (ZERO_WINDOW_PROBE, &mut self.zero_window_probes),
+                            (WINDOW_FULL, &mut self.window_full),
+                            (KEEP_ALIVE, &mut self.keep_alives)];
+        for pair in counters.iter_mut() {
+            if flags.contains(pair.0) {
+                *pair.1 += 1;
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+/// What one direction sent so far
+struct Flow {
+    /// The sequence number after the highest one sent
+    next_seq        : Option<SeqNum>,
+    /// When `next_seq` last moved forward
+    next_seq_time   : u64,
+    last_ack        : Option<SeqNum>,
+    /// The last window advertised, scaled
+    window          : Option<u32>,
+    /// Duplicates of `last_ack` sent so far
+    dup_acks        : u32
+}
+
+/// Analyzes the segments of one connection, in capture order
+pub struct SequenceAnalyzer {
+    config  : AnalysisConfig,
+    flows   : [Flow; 2],
+    stats   : [AnalysisStats; 2],
+    windows : WindowTracker
+}
+
+impl SequenceAnalyzer {
+    pub fn new(config : AnalysisConfig) -> SequenceAnalyzer {
+        SequenceAnalyzer {
+            config  : config,
+            flows   : [Flow::default(); 2],
+            stats   : [AnalysisStats::default(); 2],
+            windows : WindowTracker::new()
+        }
+    }
+
+    /// Counts of the flags given to the segments sent in a direction
+    pub fn stats(&self, direction : Direction) -> AnalysisStats {
+        self.stats[direction.index()]
+    }
+
+    /// Analyze a segment sent in the given direction at time `now`, in milliseconds
+    pub fn push(&mut self, direction : Direction, segment : &TcpSegment, now : u64) -> SegmentAnalysis {
+        let (fwd, rev) = (self.flows[direction.index()], self.flows[direction.reverse().index()]);
+        let seq = segment.seq();
+        let end = segment.end_seq();
+        let seg_len = segment.data.len() as u32;
+        self.windows.push(direction, segment, now);
+        let window = self.windows.effective_window(direction, segment);
+        let control = segment.ctrl_flags.intersects(SYN | FIN | RST);
+        let mut flags = AnalysisFlags::empty();
+        let mut dup_ack_count = 0;
+
+        if seg_len == 1 && fwd.next_seq == Some(seq) && rev.window == Some(0) {
+            flags.insert(ZERO_WINDOW_PROBE);
+        } else if seg_len <= 1 && !control && fwd.next_seq == Some(seq + 1) {
+            flags.insert(KEEP_ALIVE);
+        }
+
+        if let (Some(ack), Some(rev_window)) = (rev.last_ack, rev.window) {
+            if seg_len > 0 && !control && rev_window != 0 && end == ack + rev_window {
+                flags.insert(WINDOW_FULL);
+            }
+        }
+
+        let ack = if segment.ctrl_flags.contains(ACK) { Some(segment.ack()) } else { None };
+        if ack.is_some() && ack == fwd.last_ack {
+            if seg_len == 0 && !control && !flags.contains(KEEP_ALIVE) && Some(window) == fwd.window {
+                dup_ack_count = fwd.dup_acks + 1;
+                flags.insert(DUPLICATE_ACK);
+            }
+        }
+
+        let advances = match fwd.next_seq {
+            Some(next) => !seq.lt(next),
+            None => true
+        };
+        let carries = seg_len > 0 || segment.ctrl_flags.intersects(SYN | FIN);
+        if carries && !advances && !segment.is_rst() && !flags.intersects(KEEP_ALIVE | ZERO_WINDOW_PROBE) {
+            flags.insert(if rev.dup_acks >= 2 && rev.last_ack == Some(seq) {
+                RETRANSMISSION | FAST_RETRANSMISSION
+            } else if now.saturating_sub(fwd.next_seq_time) < self.config.out_of_order_threshold &&
+                      fwd.next_seq != Some(end) {
+                OUT_OF_ORDER
+            } else {
+                match rev.last_ack {
+                    Some(acked) if seg_len > 0 && end.le(acked) => RETRANSMISSION | SPURIOUS_RETRANSMISSION,
+                    _ => RETRANSMISSION
+                }
+            });
+        }
+
+        let flow = &mut self.flows[direction.index()];
+        if !segment.is_rst() && fwd.next_seq.map_or(true, |next| end.gt(next)) {
+            flow.next_seq = Some(end);
+            flow.next_seq_time = now;
+        }
+        if ack.is_some() {
+            if ack != flow.last_ack {
+                flow.dup_acks = 0;
+            }
+            if flags.contains(DUPLICATE_ACK) {
+                flow.dup_acks = dup_ack_count;
+            }
+            flow.last_ack = ack;
+        }
+        flow.window = Some(window);
+
+        self.stats[direction.index()].count(flags);
+        SegmentAnalysis {
+            flags           : flags,
+            dup_ack_count   : dup_ack_count
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! # Header Anomalies
+//! Flags segments whose control flags or header fields no legitimate stack sends,
+//! such as the probes of NULL, FIN and Xmas port scans, and combinations that make
+//! no sense such as SYN with FIN. Each anomaly belongs to a category, so that scans
+//! can be told apart from merely malformed headers.
+//!
+//! `detect` checks a segment on its own. A Maimon scan probe is a FIN-ACK, which is
+//! also how connections close, so it is only reported by an `AnomalyDetector`, when
+//! the FIN-ACK is the first segment of its connection. `detect_bytes` parses a raw
+//! segment leniently, so that non-zero reserved bits are reported rather than rejected.
+//!
+//! # Example
+//! ```rust
+//! use tcp_parser::TcpSegment;
+//! use tcp_parser::anomaly::{self, Anomaly, AnomalyCategory};
+//! let xmas = TcpSegment::parse(&[0x30, 0x39, 0, 80, 0, 0, 0, 1, 0, 0, 0, 0, 0x50, 0x29, 4, 0, 0, 0, 0, 0]);
+//! let anomalies = anomaly::detect(&xmas);
+//! assert_eq!(anomalies, vec![Anomaly::XmasScan]);
+//! assert_eq!(anomalies[0].category(), AnomalyCategory::Scan);
+//! ```
+
+use std::vec::Vec;
+use std::fmt;
+use super::{TcpSegment, TcpParseError, SeqNum, URG, ACK, PSH, RST, SYN, FIN};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// The kinds of anomalies
+pub enum AnomalyCategory {
+    /// The probe of a port scan
+    Scan,
+    /// Control flags that contradict each other
+    IllegalFlags,
+    /// A header field that does not fit the flags, or should be zero
+    HeaderField,
+    /// Data where none is expected
+    Payload
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Something suspicious about a segment
+pub enum Anomaly {
+    /// None of URG, ACK, PSH, RST, SYN and FIN, whatever the ECN flags
+    NullScan,
+    /// FIN alone
+    FinScan,
+    /// FIN, PSH and URG, without SYN, RST or ACK
+    XmasScan,
+    /// A FIN-ACK opening a connection
+    MaimonScan,
+    /// SYN and FIN together
+    SynFin,
+    /// SYN and RST together
+    SynRst,
+    /// Data on a SYN without a Fast Open cookie
+    DataOnSyn,
+    /// A non-zero urgent pointer without the URG flag
+    UrgentPointerWithoutUrg,
+    /// Non-zero reserved bits, as a 3 bit value
+    ReservedBits(u8),
+    /// Port 0 as the source
+    ZeroSourcePort,
+    /// Port 0 as the destination
+    ZeroDestPort,
+    /// A non-zero acknowledgement number without the ACK flag
+    AckNumberWithoutAck
+}
+
+impl Anomaly {
+    pub fn category(&self) -> AnomalyCategory {
+        match *self {
+            Anomaly::NullScan | Anomaly::FinScan | Anomaly::XmasScan | Anomaly::MaimonScan => AnomalyCategory::Scan,
+            Anomaly::SynFin | Anomaly::SynRst => AnomalyCategory::IllegalFlags,
+            Anomaly::DataOnSyn => AnomalyCategory::Payload,
+            Anomaly::UrgentPointerWithoutUrg | Anomaly::ReservedBits(_) | Anomaly::ZeroSourcePort |
+            Anomaly::ZeroDestPort | Anomaly::AckNumberWithoutAck => AnomalyCategory::HeaderField
+        }
+    }
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Anomaly::NullScan                => write!(f, "NULL scan"),
+            Anomaly::FinScan                 => write!(f, "FIN scan"),
+            Anomaly::XmasScan                => write!(f, "Xmas scan"),
+            Anomaly::MaimonScan              => write!(f, "Maimon scan"),
+            Anomaly::SynFin                  => write!(f, "SYN with FIN"),
+            Anomaly::SynRst                  => write!(f, "SYN with RST"),
+            Anomaly::DataOnSyn               => write!(f, "data on SYN without Fast Open"),
+            Anomaly::UrgentPointerWithoutUrg => write!(f, "urgent pointer without URG"),
+            Anomaly::ReservedBits(bits)      => write!(f, "reserved bits {:#05b}", bits),
+            Anomaly::ZeroSourcePort          => write!(f, "source port 0"),
+            Anomaly::ZeroDestPort            => write!(f, "destination port 0"),
+            Anomaly::AckNumberWithoutAck     => write!(f, "acknowledgement number without ACK")
+        }
+    }
+}
+
+/// Check a segment on its own
+pub fn detect(segment : &TcpSegment) -> Vec<Anomaly> {
+    let mut anomalies = Vec::new();
+    let flags = segment.ctrl_flags & (URG | ACK | PSH | RST | SYN | FIN);
+
+    if flags.is_empty() {
+        anomalies.push(Anomaly::NullScan);
+    } else if flags == FIN {
+        anomalies.push(Anomaly::FinScan);
+    } else if flags.contains(FIN | PSH | URG) && !flags.intersects(SYN | RST | ACK) {
+        anomalies.push(Anomaly::XmasScan);
+    }
+    if flags.contains(SYN | FIN) {
+        anomalies.push(Anomaly::SynFin);
+    }
+    if flags.contains(SYN | RST) {
+        anomalies.push(Anomaly::SynRst);
+    }
+    if flags.contains(SYN) && !segment.data.is_empty() && segment.fast_open_cookie().is_none() {
+        anomalies.push(Anomaly::DataOnSyn);
+    }
+    if segment.urg_ptr != 0 && !flags.contains(URG) {
+        anomalies.push(Anomaly::UrgentPointerWithoutUrg);
+    }
+    if segment.reserved != 0 {
+        anomalies.push(Anomaly::ReservedBits(segment.reserved));
+    }
+    if segment.src_port == 0 {
+        anomalies.push(Anomaly::ZeroSourcePort);
+    }
+    if segment.dest_port == 0 {
+        anomalies.push(Anomaly::ZeroDestPort);
+    }
+    if segment.ack() != SeqNum(0) && !flags.contains(ACK) {
+        anomalies.push(Anomaly::AckNumberWithoutAck);
+    }
+    anomalies
+}
+
+/// Parse a raw segment, accepting non-zero reserved bits, and check it
+pub fn detect_bytes(segment : &[u8]) -> Result<Vec<Anomaly>, TcpParseError> {
+    let parsed = try!(TcpSegment::try_parse_lenient(segment));
+    Ok(detect(&parsed))
+}
+
+/// Checks the segments of one connection, in capture order
+pub struct AnomalyDetector {
+    started : bool
+}
+
+impl Default for AnomalyDetector {
+    fn default() -> AnomalyDetector {
+        AnomalyDetector::new()
+    }
+}
+
+impl AnomalyDetector {
+    pub fn new() -> AnomalyDetector {
+        AnomalyDetector {
+            started : false
+        }
+    }
+
+    /// Check a segment sent in either direction
+    pub fn push(&mut self, segment : &TcpSegment) -> Vec<Anomaly> {
+        let mut anomalies = detect(segment);
+        let flags = segment.ctrl_flags & (URG | ACK | PSH | RST | SYN | FIN);
+        if !self.started && flags == FIN | ACK && segment.data.is_empty() {
+            anomalies.insert(0, Anomaly::MaimonScan);
+        }
+        self.started = true;
+        anomalies
+    }
+}
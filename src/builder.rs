@@ -0,0 +1,179 @@
+use std::vec::Vec;
+use super::{TcpSegment, TcpOpts, TcpCTRL, PseudoHeader};
+
+/// Maximum length of the options area: a data offset of 15 words less the fixed header
+pub const MAX_OPTIONS_LEN : usize = 40;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// The reasons a TcpSegmentBuilder can refuse to build a segment
+pub enum TcpBuildError {
+    /// The encoded options, before padding, exceed 40 bytes
+    OptionsTooLong(usize),
+    /// The segment is longer than the 65535 bytes an IP packet can carry
+    SegmentTooLong(usize)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// Builds a TcpSegment, filling in the data offset, the padding
+/// of the options to a 32-bit boundary and the checksum.
+///
+/// # Example
+/// ```rust
+/// use tcp_parser::{TcpSegmentBuilder, TcpOpts, IPv4PseudoHeader, SYN};
+/// let header = IPv4PseudoHeader {
+///     source_addr : 0xC0A8021D,
+///     dest_addr   : 0xB896BA5D,
+///     protocol    : 6,
+///     tcp_len     : 0
+/// };
+/// let segment = TcpSegmentBuilder::new()
+///     .src_port(38772)
+///     .dest_port(80)
+///     .seq_num(0x040cb9a0)
+///     .flags(SYN)
+///     .window(24800)
+///     .option(TcpOpts::MSS(1240))
+///     .option(TcpOpts::WindowScale(7))
+///     .build(header.clone())
+///     .unwrap();
+/// assert_eq!(segment.data_off, 7);
+/// assert_eq!(segment.checksum, segment.calculate_checksum(IPv4PseudoHeader { tcp_len: 28, ..header }));
+/// ```
+pub struct TcpSegmentBuilder {
+    src_port    : u16,
+    dest_port   : u16,
+    seq_num     : u32,
+    ack_num     : u32,
+    flags       : TcpCTRL,
+    window      : u16,
+    urg_ptr     : u16,
+    options     : Vec<TcpOpts>,
+    data        : Vec<u8>
+}
+
+impl Default for TcpSegmentBuilder {
+    fn default() -> TcpSegmentBuilder {
+        TcpSegmentBuilder::new()
+    }
+}
+
+impl TcpSegmentBuilder {
+    /// A builder for an empty segment with no flags set
+    pub fn new() -> TcpSegmentBuilder {
+        TcpSegmentBuilder {
+            src_port    : 0,
+            dest_port   : 0,
+            seq_num     : 0,
+            ack_num     : 0,
+            flags       : TcpCTRL::empty(),
+            window      : 0,
+            urg_ptr     : 0,
+            options     : Vec::new(),
+            data        : Vec::new()
+        }
+    }
+
+    /// Source port
+    pub fn src_port(mut self, port : u16) -> TcpSegmentBuilder {
+        self.src_port = port;
+        self
+    }
+
+    /// Destination port
+    pub fn dest_port(mut self, port : u16) -> TcpSegmentBuilder {
+        self.dest_port = port;
+        self
+    }
+
+    /// Sequence number
+    pub fn seq_num(mut self, seq : u32) -> TcpSegmentBuilder {
+        self.seq_num = seq;
+        self
+    }
+
+    /// Acknowledgement number
+    pub fn ack_num(mut self, ack : u32) -> TcpSegmentBuilder {
+        self.ack_num = ack;
+        self
+    }
+
+    /// Control flags, replacing any set before
+    pub fn flags(mut self, flags : TcpCTRL) -> TcpSegmentBuilder {
+        self.flags = flags;
+        self
+    }
+
+    /// TCP Window size, as sent
+    pub fn window(mut self, window : u16) -> TcpSegmentBuilder {
+        self.window = window;
+        self
+    }
+
+    /// Offset from the sequence number of the last urgent data byte
+    pub fn urg_ptr(mut self, urg_ptr : u16) -> TcpSegmentBuilder {
+        self.urg_ptr = urg_ptr;
+        self
+    }
+
+    /// Append a single option
+    pub fn option(mut self, opt : TcpOpts) -> TcpSegmentBuilder {
+        self.options.push(opt);
+        self
+    }
+
+    /// Replace all options
+    pub fn options(mut self, opts : Vec<TcpOpts>) -> TcpSegmentBuilder {
+        self.options = opts;
+        self
+    }
+
+    /// Application layer data
+    pub fn data<T : AsRef<[u8]>>(mut self, data : T) -> TcpSegmentBuilder {
+        self.data = data.as_ref().iter().cloned().collect();
+        self
+    }
+
+    /// Build the segment. Options are padded to a 32-bit boundary with an END
+    /// option followed by zeroes, and the checksum is calculated over the provided
+    /// pseudo header with its length replaced by the actual segment length.
+    pub fn build<P : PseudoHeader>(self, mut pseudo_header : P) -> Result<TcpSegment, TcpBuildError> {
+        let mut options = self.options;
+        // Summed before encoding, whose one byte length fields would overflow
+        let opts_len = options.iter().fold(0, |len, opt| len + opt.encoded_len());
+        if opts_len > MAX_OPTIONS_LEN {
+            return Err(TcpBuildError::OptionsTooLong(opts_len));
+        }
+
+        let pad = (4 - opts_len % 4) % 4;
+        if pad > 0 {
+            options.push(TcpOpts::END);
+        }
+        if pad > 1 {
+            options.push(TcpOpts::Padding((1..pad).map(|_| 0).collect()));
+        }
+
+        let header_len = 20 + opts_len + pad;
+        let tcp_len = header_len + self.data.len();
+        if tcp_len > 0xFFFF {
+            return Err(TcpBuildError::SegmentTooLong(tcp_len));
+        }
+
+        let mut segment = TcpSegment {
+            src_port    : self.src_port,
+            dest_port   : self.dest_port,
+            seq_num     : self.seq_num,
+            ack_num     : self.ack_num,
+            data_off    : (header_len / 4) as u8,
+            reserved    : 0,
+            ctrl_flags  : self.flags,
+            window      : self.window,
+            checksum    : 0,
+            urg_ptr     : self.urg_ptr,
+            options     : options,
+            data        : self.data
+        };
+        pseudo_header.set_tcp_len(tcp_len);
+        segment.checksum = segment.calculate_checksum(pseudo_header);
+        Ok(segment)
+    }
+}
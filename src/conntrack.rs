@@ -0,0 +1,279 @@
+//! # Connection Tracking
+//! Groups segments into connections by their 4-tuple. The two endpoints of a
+//! connection are normalized so both directions share one entry, which gets a
+//! connection ID that stays the same for as long as the entry lives. A connection
+//! closed by FINs from both ends or by a RST lingers for the TIME_WAIT period, so
+//! late segments are still attributed to it, unless a new SYN reuses its ports:
+//! the old connection then closes and the SYN opens a new one with a new ID. Idle
+//! connections expire.
+//!
+//! # Example
+//! ```rust
+//! use tcp_parser::TcpSegment;
+//! use tcp_parser::ip::IpAddress;
+//! use tcp_parser::conntrack::{ConnectionTable, ConnectionConfig};
+//! use tcp_parser::stream::Direction;
+//! let mut table = ConnectionTable::new(ConnectionConfig::default());
+//! let (client, server) = (IpAddress::V4(0x0A000001), IpAddress::V4(0x0A000002));
+//! let syn = TcpSegment::parse(&[0x30, 0x39, 0, 80, 0, 0, 0, 1, 0, 0, 0, 0, 0x50, 0x02, 0, 0, 0, 0, 0, 0]);
+//! let syn_ack = TcpSegment::parse(&[0, 80, 0x30, 0x39, 0, 0, 0, 9, 0, 0, 0, 2, 0x50, 0x12, 0, 0, 0, 0, 0, 0]);
+//! let (id, direction) = table.push(&syn, client, server, 0);
+//! assert_eq!(direction, Direction::ClientToServer);
+//! assert_eq!(table.push(&syn_ack, server, client, 5), (id, Direction::ServerToClient));
+//! assert_eq!(table.get(id).unwrap().counters(Direction::ServerToClient).packets, 1);
+//! ```
+
+use std::vec::Vec;
+use std::mem;
+use std::slice;
+use std::collections::{btree_map, BTreeMap};
+use super::TcpSegment;
+use ip::IpAddress;
+use stream::Direction;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+/// One end of a connection
+pub struct Endpoint {
+    pub addr    : IpAddress,
+    pub port    : u16
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Timeouts of a connection table, in milliseconds
+pub struct ConnectionConfig {
+    /// How long an open connection may go without a segment before it expires
+    pub idle_timeout    : u64,
+    /// How long a closed connection is kept for late segments, i.e. 2 MSL
+    pub time_wait       : u64
+}
+
+impl Default for ConnectionConfig {
+    fn default() -> ConnectionConfig {
+        ConnectionConfig {
+            idle_timeout    : 3600 * 1000,
+            time_wait       : 2 * 60 * 1000
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Why a connection ended
+pub enum CloseReason {
+    /// Both ends sent a FIN
+    Fin,
+    /// Either end sent a RST
+    Reset,
+    /// No segment was seen for the idle timeout
+    Expired
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// Counters of one direction of a connection
+pub struct FlowCounters {
+    /// Segments sent
+    pub packets : u64,
+    /// Payload bytes sent, counting retransmissions
+    pub bytes   : u64
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// A tracked connection
+pub struct Connection {
+    pub id           : u64,
+    /// The endpoint that sent the SYN, or when the handshake was not captured,
+    /// the one that appears to have opened the connection
+    pub client       : Endpoint,
+    pub server       : Endpoint,
+    pub first_seen   : u64,
+    pub last_seen    : u64,
+    /// Why the connection ended, or None while it is open
+    pub close_reason : Option<CloseReason>,
+    counters         : [FlowCounters; 2],
+    fin              : [bool; 2],
+    /// When the connection was closed by FINs or a RST
+    closed_at        : Option<u64>
+}
+
+impl Connection {
+    fn new(id : u64, client : Endpoint, server : Endpoint, now : u64) -> Connection {
+        Connection {
+            id           : id,
+            client       : client,
+            server       : server,
+            first_seen   : now,
+            last_seen    : now,
+            close_reason : None,
+            counters     : [FlowCounters::default(); 2],
+            fin          : [false; 2],
+            closed_at    : None
+        }
+    }
+
+    /// The direction of a segment sent from `src`
+    pub fn direction(&self, src : &Endpoint) -> Direction {
+        if *src == self.client {
+            Direction::ClientToServer
+        } else {
+            Direction::ServerToClient
+        }
+    }
+
+    /// Counters of the segments sent in a direction
+    pub fn counters(&self, direction : Direction) -> FlowCounters {
+        self.counters[direction.index()]
+    }
+
+    /// Whether a FIN was sent in a direction
+    pub fn fin_sent(&self, direction : Direction) -> bool {
+        self.fin[direction.index()]
+    }
+
+    /// When the connection leaves the table unless another segment arrives
+    fn deadline(&self, config : &ConnectionConfig) -> u64 {
+        match self.closed_at {
+            Some(closed_at) => closed_at.saturating_add(config.time_wait),
+            None => self.last_seen.saturating_add(config.idle_timeout)
+        }
+    }
+
+    fn update(&mut self, direction : Direction, segment : &TcpSegment, now : u64) {
+        self.last_seen = now;
+        let counters = &mut self.counters[direction.index()];
+        counters.packets += 1;
+        counters.bytes += segment.data.len() as u64;
+
+        if segment.is_fin() {
+            self.fin[direction.index()] = true;
+        }
+        if self.closed_at.is_none() {
+            if segment.is_rst() {
+                self.closed_at = Some(now);
+                self.close_reason = Some(CloseReason::Reset);
+            } else if self.fin[0] && self.fin[1] {
+                self.closed_at = Some(now);
+                self.close_reason = Some(CloseReason::Fin);
+            }
+        }
+    }
+}
+
+/// Connections by their normalized 4-tuple
+pub struct ConnectionTable {
+    config      : ConnectionConfig,
+    active      : BTreeMap<(Endpoint, Endpoint), Connection>,
+    /// The keys of active connections by ID
+    ids         : BTreeMap<u64, (Endpoint, Endpoint)>,
+    /// The keys of active connections by deadline and ID, earliest first
+    deadlines   : BTreeMap<(u64, u64), (Endpoint, Endpoint)>,
+    closed      : Vec<Connection>,
+    next_id     : u64
+}
+
+impl ConnectionTable {
+    pub fn new(config : ConnectionConfig) -> ConnectionTable {
+        ConnectionTable {
+            config      : config,
+            active      : BTreeMap::new(),
+            ids         : BTreeMap::new(),
+            deadlines   : BTreeMap::new(),
+            closed      : Vec::new(),
+            next_id     : 0
+        }
+    }
+
+    /// Attribute a segment sent from `src` to `dest` at time `now` (in milliseconds)
+    /// to its connection, creating one if needed. Returns the connection ID and
+    /// the direction of the segment within the connection.
+    pub fn push(&mut self, segment : &TcpSegment, src : IpAddress, dest : IpAddress, now : u64) -> (u64, Direction) {
+        self.expire(now);
+        let src = Endpoint { addr: src, port: segment.src_port };
+        let dest = Endpoint { addr: dest, port: segment.dest_port };
+        let key = if src <= dest { (src, dest) } else { (dest, src) };
+
+        // A new SYN reusing the ports of a closed connection opens another one
+        let reused = match self.active.get(&key) {
+            Some(conn) => segment.is_syn() && conn.closed_at.is_some(),
+            None => false
+        };
+        if reused {
+            let conn = self.remove(&key).unwrap();
+            self.closed.push(conn);
+        }
+
+        let mut conn = match self.active.remove(&key) {
+            Some(conn) => {
+                self.deadlines.remove(&(conn.deadline(&self.config), conn.id));
+                conn
+            },
+            None => {
+                // A SYN-ACK seen first was sent by the server
+                let (client, server) = if segment.is_syn_ack() { (dest, src) } else { (src, dest) };
+                let conn = Connection::new(self.next_id, client, server, now);
+                self.next_id += 1;
+                self.ids.insert(conn.id, key);
+                conn
+            }
+        };
+        let direction = conn.direction(&src);
+        conn.update(direction, segment, now);
+        let id = conn.id;
+        self.deadlines.insert((conn.deadline(&self.config), id), key);
+        self.active.insert(key, conn);
+        (id, direction)
+    }
+
+    /// Remove an active connection from the table and its indexes
+    fn remove(&mut self, key : &(Endpoint, Endpoint)) -> Option<Connection> {
+        let conn = self.active.remove(key);
+        if let Some(ref conn) = conn {
+            self.ids.remove(&conn.id);
+            self.deadlines.remove(&(conn.deadline(&self.config), conn.id));
+        }
+        conn
+    }
+
+    /// Close the connections that were idle for too long and remove those
+    /// closed for longer than the TIME_WAIT period
+    pub fn expire(&mut self, now : u64) {
+        loop {
+            let key = match self.deadlines.iter().next() {
+                Some((&(deadline, _), key)) if deadline <= now => *key,
+                _ => break
+            };
+            let mut conn = self.remove(&key).unwrap();
+            if conn.close_reason.is_none() {
+                conn.close_reason = Some(CloseReason::Expired);
+            }
+            self.closed.push(conn);
+        }
+    }
+
+    /// The connection with this ID, if it is still in the table
+    pub fn get(&self, id : u64) -> Option<&Connection> {
+        self.ids.get(&id).and_then(|key| self.active.get(key))
+    }
+
+    /// Connections still in the table, including closed ones in TIME_WAIT
+    pub fn active<'a>(&'a self) -> btree_map::Values<'a, (Endpoint, Endpoint), Connection> {
+        self.active.values()
+    }
+
+    /// Connections removed from the table, in the order they were removed
+    pub fn closed<'a>(&'a self) -> slice::Iter<'a, Connection> {
+        self.closed.iter()
+    }
+
+    /// Take the connections removed from the table so far
+    pub fn take_closed(&mut self) -> Vec<Connection> {
+        mem::replace(&mut self.closed, Vec::new())
+    }
+
+    /// Number of connections in the table
+    pub fn len(&self) -> usize {
+        self.active.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.active.is_empty()
+    }
+}
@@ -0,0 +1,107 @@
+//! MD5 message digest (RFC 1321), as required by the TCP MD5 signature option
+
+use std::vec::Vec;
+
+const S : [u32; 64] = [
+    7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22,
+    5,  9, 14, 20, 5,  9, 14, 20, 5,  9, 14, 20, 5,  9, 14, 20,
+    4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23,
+    6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21
+];
+
+const K : [u32; 64] = [
+    0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee, 0xf57c0faf, 0x4787c62a, 0xa8304613, 0xfd469501,
+    0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be, 0x6b901122, 0xfd987193, 0xa679438e, 0x49b40821,
+    0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa, 0xd62f105d, 0x02441453, 0xd8a1e681, 0xe7d3fbc8,
+    0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed, 0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a,
+    0xfffa3942, 0x8771f681, 0x6d9d6122, 0xfde5380c, 0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70,
+    0x289b7ec6, 0xeaa127fa, 0xd4ef3085, 0x04881d05, 0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665,
+    0xf4292244, 0x432aff97, 0xab9423a7, 0xfc93a039, 0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+    0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb, 0xeb86d391
+];
+
+/// Incremental MD5 hasher
+pub struct Md5 {
+    state   : [u32; 4],
+    buffer  : Vec<u8>,
+    length  : u64
+}
+
+impl Md5 {
+    pub fn new() -> Md5 {
+        Md5 {
+            state   : [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476],
+            buffer  : Vec::with_capacity(64),
+            length  : 0
+        }
+    }
+
+    /// Feed more data into the digest
+    pub fn update(&mut self, data : &[u8]) {
+        self.length = self.length.wrapping_add(data.len() as u64);
+        self.buffer.extend(data.iter());
+
+        let blocks = self.buffer.len() / 64;
+        for i in 0..blocks {
+            let mut block = [0u8; 64];
+            block.copy_from_slice(&self.buffer[i*64..(i+1)*64]);
+            self.process(&block);
+        }
+        self.buffer.drain(..blocks*64);
+    }
+
+    /// Pad the message and return the 16 byte digest
+    pub fn finish(mut self) -> [u8; 16] {
+        let bit_len = self.length.wrapping_mul(8);
+
+        let mut padding = Vec::with_capacity(72);
+        padding.push(0x80u8);
+        while (self.buffer.len() + padding.len()) % 64 != 56 {
+            padding.push(0);
+        }
+        for i in 0..8 {
+            padding.push((bit_len >> (8*i)) as u8);
+        }
+        let length = self.length;
+        self.update(&padding);
+        self.length = length;
+
+        let mut digest = [0u8; 16];
+        for (i, word) in self.state.iter().enumerate() {
+            for j in 0..4 {
+                digest[4*i + j] = (word >> (8*j)) as u8;
+            }
+        }
+        digest
+    }
+
+    fn process(&mut self, block : &[u8; 64]) {
+        let mut m = [0u32; 16];
+        for i in 0..16 {
+            m[i] = (block[4*i] as u32)              |
+                   ((block[4*i + 1] as u32) << 8)   |
+                   ((block[4*i + 2] as u32) << 16)  |
+                   ((block[4*i + 3] as u32) << 24);
+        }
+
+        let (mut a, mut b, mut c, mut d) = (self.state[0], self.state[1], self.state[2], self.state[3]);
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5*i + 1) % 16),
+                2 => (b ^ c ^ d, (3*i + 5) % 16),
+                _ => (c ^ (b | !d), (7*i) % 16)
+            };
+            let rotated = a.wrapping_add(f).wrapping_add(K[i]).wrapping_add(m[g]).rotate_left(S[i]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(rotated);
+        }
+
+        self.state[0] = self.state[0].wrapping_add(a);
+        self.state[1] = self.state[1].wrapping_add(b);
+        self.state[2] = self.state[2].wrapping_add(c);
+        self.state[3] = self.state[3].wrapping_add(d);
+    }
+}
@@ -0,0 +1,14 @@
+//! Digests and MACs needed to verify TCP authentication options. Kept in-crate so
+//! that they are available when building against `libcore`.
+
+pub mod md5;
+pub mod sha1;
+pub mod aes;
+
+/// Compare two MACs without leaking the position of the first difference
+pub fn constant_time_eq(a : &[u8], b : &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -0,0 +1,118 @@
+//! SHA-1 message digest (FIPS 180-4), used by HMAC-SHA-1-96 in TCP-AO
+
+use std::vec::Vec;
+
+/// Incremental SHA-1 hasher
+pub struct Sha1 {
+    state   : [u32; 5],
+    buffer  : Vec<u8>,
+    length  : u64
+}
+
+impl Sha1 {
+    pub fn new() -> Sha1 {
+        Sha1 {
+            state   : [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0],
+            buffer  : Vec::with_capacity(64),
+            length  : 0
+        }
+    }
+
+    /// Feed more data into the digest
+    pub fn update(&mut self, data : &[u8]) {
+        self.length = self.length.wrapping_add(data.len() as u64);
+        self.buffer.extend(data.iter());
+
+        let blocks = self.buffer.len() / 64;
+        for i in 0..blocks {
+            let mut block = [0u8; 64];
+            block.copy_from_slice(&self.buffer[i*64..(i+1)*64]);
+            self.process(&block);
+        }
+        self.buffer.drain(..blocks*64);
+    }
+
+    /// Pad the message and return the 20 byte digest
+    pub fn finish(mut self) -> [u8; 20] {
+        let bit_len = self.length.wrapping_mul(8);
+
+        let mut padding = Vec::with_capacity(72);
+        padding.push(0x80u8);
+        while (self.buffer.len() + padding.len()) % 64 != 56 {
+            padding.push(0);
+        }
+        for i in 0..8 {
+            padding.push((bit_len >> (56 - 8*i)) as u8);
+        }
+        self.update(&padding);
+
+        let mut digest = [0u8; 20];
+        for (i, word) in self.state.iter().enumerate() {
+            for j in 0..4 {
+                digest[4*i + j] = (word >> (24 - 8*j)) as u8;
+            }
+        }
+        digest
+    }
+
+    fn process(&mut self, block : &[u8; 64]) {
+        let mut w = [0u32; 80];
+        for i in 0..16 {
+            w[i] = ((block[4*i] as u32) << 24)      |
+                   ((block[4*i + 1] as u32) << 16)  |
+                   ((block[4*i + 2] as u32) << 8)   |
+                   (block[4*i + 3] as u32);
+        }
+        for i in 16..80 {
+            w[i] = (w[i-3] ^ w[i-8] ^ w[i-14] ^ w[i-16]).rotate_left(1);
+        }
+
+        let (mut a, mut b, mut c, mut d, mut e) =
+            (self.state[0], self.state[1], self.state[2], self.state[3], self.state[4]);
+        for i in 0..80 {
+            let (f, k) = match i / 20 {
+                0 => ((b & c) | (!b & d), 0x5a827999),
+                1 => (b ^ c ^ d, 0x6ed9eba1),
+                2 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6)
+            };
+            let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(w[i]);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        self.state[0] = self.state[0].wrapping_add(a);
+        self.state[1] = self.state[1].wrapping_add(b);
+        self.state[2] = self.state[2].wrapping_add(c);
+        self.state[3] = self.state[3].wrapping_add(d);
+        self.state[4] = self.state[4].wrapping_add(e);
+    }
+}
+
+/// HMAC-SHA-1 (RFC 2104) over the concatenation of `parts`
+pub fn hmac_sha1(key : &[u8], parts : &[&[u8]]) -> [u8; 20] {
+    let mut block = [0u8; 64];
+    if key.len() > 64 {
+        let mut sha = Sha1::new();
+        sha.update(key);
+        block[..20].copy_from_slice(&sha.finish());
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner = Sha1::new();
+    let ipad : Vec<u8> = block.iter().map(|b| b ^ 0x36).collect();
+    inner.update(&ipad);
+    for part in parts.iter() {
+        inner.update(part);
+    }
+
+    let mut outer = Sha1::new();
+    let opad : Vec<u8> = block.iter().map(|b| b ^ 0x5c).collect();
+    outer.update(&opad);
+    outer.update(&inner.finish());
+    outer.finish()
+}
@@ -0,0 +1,564 @@
+//! # IP Defragmentation
+//! Reassembly of fragmented IPv4 and IPv6 datagrams ahead of TCP parsing. Fragments
+//! are buffered per (source, destination, protocol, identification) for IPv4, and per
+//! (source, destination, identification) for IPv6, until their datagram is complete,
+//! times out, or is evicted to stay within the memory limit.
+//!
+//! # Example
+//! ```rust
+//! use tcp_parser::ip;
+//! use tcp_parser::defrag::{IPv4Defragmenter, DefragConfig};
+//! let mut defrag = IPv4Defragmenter::new(DefragConfig::default());
+//! let first : Vec<u8> = vec![0x45, 0, 0, 28, 0, 7, 0x20, 0, 64, 6, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2,
+//!                            0, 1, 2, 3, 4, 5, 6, 7];
+//! let last : Vec<u8> = vec![0x45, 0, 0, 24, 0, 7, 0, 1, 64, 6, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2,
+//!                           8, 9, 10, 11];
+//! assert_eq!(defrag.push(&ip::parse_ip(&first).unwrap(), 0), None);
+//! let datagram = defrag.push(&ip::parse_ip(&last).unwrap(), 10).unwrap();
+//! assert_eq!(datagram.payload, vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]);
+//! ```
+
+use std::vec::Vec;
+use std::cmp;
+use std::collections::BTreeMap;
+use ip::{IpPacket, IpHeader};
+
+pub use overlap::OverlapPolicy;
+
+/// Largest datagram, counting the IPv4 header or the IPv6 extension headers
+const MAX_DATAGRAM_LEN : usize = 65535;
+/// Length of the IPv6 Fragment header
+const IPV6_FRAGMENT_LEN : usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Limits and policy of a defragmenter
+pub struct DefragConfig {
+    /// How long a datagram may wait for its missing fragments, in milliseconds
+    pub timeout     : u64,
+    /// Fragment data buffered across all datagrams before the oldest are evicted, in bytes
+    pub max_memory  : usize,
+    pub policy      : OverlapPolicy
+}
+
+impl Default for DefragConfig {
+    fn default() -> DefragConfig {
+        DefragConfig {
+            timeout     : 30000,
+            max_memory  : 4 * 1024 * 1024,
+            policy      : OverlapPolicy::First
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// Counters kept by a defragmenter
+pub struct DefragStats {
+    /// Fragments received
+    pub fragments   : u64,
+    /// Datagrams completed
+    pub reassembled : u64,
+    /// Fragments overlapping data already received
+    pub overlaps    : u64,
+    /// Datagrams dropped because their fragments stopped arriving
+    pub timed_out   : u64,
+    /// Datagrams dropped to stay within the memory limit
+    pub evicted     : u64,
+    /// Fragments, or whole datagrams, dropped as malformed
+    pub invalid     : u64,
+    /// Datagrams discarded because their fragments overlapped (IPv6 only)
+    pub rejected    : u64
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// A complete datagram. For reassembled datagrams the header is that of the
+/// first fragment, with the length and fragment fields updated.
+pub struct Datagram {
+    pub header      : IpHeader,
+    pub protocol    : u8,
+    pub payload     : Vec<u8>
+}
+
+impl Datagram {
+    fn from_packet(packet : &IpPacket) -> Datagram {
+        Datagram {
+            header      : packet.header.clone(),
+            protocol    : packet.protocol,
+            payload     : packet.payload.iter().cloned().collect()
+        }
+    }
+
+    /// Borrow this datagram as a packet, e.g. to extract its TCP segment
+    pub fn as_packet<'a>(&'a self) -> IpPacket<'a> {
+        IpPacket {
+            header      : self.header.clone(),
+            protocol    : self.protocol,
+            payload     : &self.payload
+        }
+    }
+}
+
+/// A run of fragment data, remembering the span of the fragment it came from
+struct Piece {
+    start   : usize,
+    origin  : (u64, u64),
+    data    : Vec<u8>
+}
+
+impl Piece {
+    fn end(&self) -> usize {
+        self.start + self.data.len()
+    }
+}
+
+/// The fragments received so far for one datagram, kept as sorted disjoint pieces
+struct FragmentBuffer {
+    first_seen  : u64,
+    pieces      : Vec<Piece>,
+    total_len   : Option<usize>,
+    header      : Option<IpHeader>,
+    /// Length of the header of the first fragment, which the datagram is rebuilt with
+    header_len  : Option<usize>,
+    protocol    : u8,
+    size        : usize,
+    /// Set once the datagram has been discarded for overlapping fragments
+    rejected    : bool
+}
+
+impl FragmentBuffer {
+    fn new(now : u64) -> FragmentBuffer {
+        FragmentBuffer {
+            first_seen  : now,
+            pieces      : Vec::new(),
+            total_len   : None,
+            header      : None,
+            header_len  : None,
+            protocol    : 0,
+            size        : 0,
+            rejected    : false
+        }
+    }
+
+    fn max_end(&self) -> usize {
+        self.pieces.last().map_or(0, |piece| piece.end())
+    }
+
+    /// Insert fragment data at `start`, resolving overlaps with `policy`.
+    /// Returns whether the fragment overlapped data already received.
+    fn insert(&mut self, start : usize, data : &[u8], policy : OverlapPolicy) -> bool {
+        let end = start + data.len();
+        let mut ranges = Vec::new();
+        ranges.push((start, end));
+        let origin = (start as u64, end as u64);
+        let mut overlapped = false;
+
+        let mut pieces = Vec::with_capacity(self.pieces.len() + 1);
+        for piece in self.pieces.drain(..) {
+            if piece.end() <= start || piece.start >= end {
+                pieces.push(piece);
+                continue;
+            }
+            overlapped = true;
+            if policy.new_wins(origin, piece.origin) {
+                if piece.start < start {
+                    pieces.push(Piece {
+                        start   : piece.start,
+                        origin  : piece.origin,
+                        data    : piece.data[..start - piece.start].iter().cloned().collect()
+                    });
+                }
+                if piece.end() > end {
+                    pieces.push(Piece {
+                        start   : end,
+                        origin  : piece.origin,
+                        data    : piece.data[end - piece.start..].iter().cloned().collect()
+                    });
+                }
+            } else {
+                ranges = subtract(ranges, piece.start, piece.end());
+                pieces.push(piece);
+            }
+        }
+
+        for &(from, to) in ranges.iter() {
+            pieces.push(Piece {
+                start   : from,
+                origin  : origin,
+                data    : data[from - start..to - start].iter().cloned().collect()
+            });
+        }
+        pieces.sort_by(|a, b| a.start.cmp(&b.start));
+
+        self.size = pieces.iter().fold(0, |size, piece| size + piece.data.len());
+        self.pieces = pieces;
+        overlapped
+    }
+
+    fn is_complete(&self) -> bool {
+        let total_len = match self.total_len {
+            Some(len) => len,
+            None => return false
+        };
+        let mut covered = 0;
+        for piece in self.pieces.iter() {
+            if piece.start != covered {
+                return false;
+            }
+            covered = piece.end();
+        }
+        covered == total_len
+    }
+
+    /// The header and protocol of the first fragment, and the reassembled data
+    fn assemble(self) -> (IpHeader, u8, Vec<u8>) {
+        let mut data = Vec::with_capacity(self.size);
+        for piece in self.pieces.iter() {
+            data.extend(piece.data.iter());
+        }
+        (self.header.unwrap(), self.protocol, data)
+    }
+}
+
+/// Remove `[from, to)` from a list of disjoint ranges
+fn subtract(ranges : Vec<(usize, usize)>, from : usize, to : usize) -> Vec<(usize, usize)> {
+    let mut result = Vec::with_capacity(ranges.len() + 1);
+    for (start, end) in ranges {
+        if end <= from || start >= to {
+            result.push((start, end));
+            continue;
+        }
+        if start < from {
+            result.push((start, from));
+        }
+        if end > to {
+            result.push((to, end));
+        }
+    }
+    result
+}
+
+/// The fields of a fragment that reassembly needs, whichever IP version carried it
+struct Fragment<'a> {
+    /// Byte offset of the data in the datagram
+    start       : usize,
+    /// Whether more fragments follow
+    more        : bool,
+    /// Length of the header, which counts towards the 65535 byte limit
+    header_len  : usize,
+    payload     : &'a [u8]
+}
+
+/// Fragment buffers and accounting shared by the IPv4 and IPv6 defragmenters
+struct Reassembler<K> {
+    config          : DefragConfig,
+    reject_overlaps : bool,
+    buffers         : BTreeMap<K, FragmentBuffer>,
+    memory          : usize,
+    stats           : DefragStats
+}
+
+impl<K : Ord + Copy> Reassembler<K> {
+    fn new(config : DefragConfig, reject_overlaps : bool) -> Reassembler<K> {
+        Reassembler {
+            config          : config,
+            reject_overlaps : reject_overlaps,
+            buffers         : BTreeMap::new(),
+            memory          : 0,
+            stats           : DefragStats::default()
+        }
+    }
+
+    /// Add a fragment. `first` is the header and protocol to reassemble with,
+    /// present only on the first fragment. Returns the buffer once the datagram
+    /// is complete.
+    fn add(&mut self, key : K, fragment : Fragment, first : Option<(IpHeader, u8)>,
+           now : u64) -> Option<FragmentBuffer> {
+        self.stats.fragments += 1;
+
+        let Fragment { start, more, header_len, payload } = fragment;
+        let end = start + payload.len();
+        if header_len + end > MAX_DATAGRAM_LEN
+            || (more && (payload.is_empty() || payload.len() % 8 != 0)) {
+            self.stats.invalid += 1;
+            return None;
+        }
+
+        let policy = self.config.policy;
+        let reject_overlaps = self.reject_overlaps;
+        let (valid, overlapped, complete) = {
+            let buffer = self.buffers.entry(key).or_insert_with(|| FragmentBuffer::new(now));
+            if buffer.rejected {
+                return None;
+            }
+            let valid = match buffer.total_len {
+                Some(total_len) => end <= total_len && (more || end == total_len),
+                None => more || end >= buffer.max_end()
+            };
+            // Fragments may carry headers of other lengths than the first
+            let first_len = if first.is_some() { Some(header_len) } else { buffer.header_len };
+            let valid = valid && match first_len {
+                Some(len) => len + cmp::max(end, buffer.max_end()) <= MAX_DATAGRAM_LEN,
+                None => true
+            };
+            let mut overlapped = false;
+            if valid {
+                if !more {
+                    buffer.total_len = Some(end);
+                }
+                if let Some((header, protocol)) = first {
+                    buffer.header = Some(header);
+                    buffer.header_len = Some(header_len);
+                    buffer.protocol = protocol;
+                }
+                let before = buffer.size;
+                overlapped = buffer.insert(start, payload, policy);
+                self.memory = self.memory - before + buffer.size;
+            }
+            (valid, overlapped, buffer.is_complete())
+        };
+
+        if !valid {
+            self.stats.invalid += 1;
+            self.remove(&key);
+            return None;
+        }
+        if overlapped {
+            self.stats.overlaps += 1;
+            if reject_overlaps {
+                self.reject(&key);
+                return None;
+            }
+        }
+        if complete {
+            self.stats.reassembled += 1;
+            return self.remove(&key);
+        }
+
+        while self.memory > self.config.max_memory {
+            let oldest = self.buffers.iter()
+                .filter(|&(_, buffer)| buffer.size > 0)
+                .min_by_key(|&(_, buffer)| buffer.first_seen)
+                .map(|(key, _)| *key);
+            match oldest {
+                Some(key) => {
+                    self.remove(&key);
+                    self.stats.evicted += 1;
+                },
+                None => break
+            }
+        }
+        None
+    }
+
+    /// Drop a datagram's data, keeping its key until it times out so that
+    /// fragments still in flight are dropped as well
+    fn reject(&mut self, key : &K) {
+        if let Some(buffer) = self.buffers.get_mut(key) {
+            self.memory -= buffer.size;
+            buffer.pieces.clear();
+            buffer.size = 0;
+            buffer.rejected = true;
+        }
+        self.stats.rejected += 1;
+    }
+
+    fn expire(&mut self, now : u64) {
+        let timeout = self.config.timeout;
+        let expired : Vec<K> = self.buffers.iter()
+            .filter(|&(_, buffer)| now.saturating_sub(buffer.first_seen) >= timeout)
+            .map(|(key, _)| *key)
+            .collect();
+        for key in expired.iter() {
+            if let Some(buffer) = self.remove(key) {
+                if !buffer.rejected {
+                    self.stats.timed_out += 1;
+                }
+            }
+        }
+    }
+
+    fn remove(&mut self, key : &K) -> Option<FragmentBuffer> {
+        let buffer = self.buffers.remove(key);
+        if let Some(ref buffer) = buffer {
+            self.memory -= buffer.size;
+        }
+        buffer
+    }
+}
+
+/// Reassembles IPv4 datagrams from their fragments
+pub struct IPv4Defragmenter {
+    inner : Reassembler<(u32, u32, u8, u16)>
+}
+
+impl IPv4Defragmenter {
+    pub fn new(config : DefragConfig) -> IPv4Defragmenter {
+        IPv4Defragmenter {
+            inner : Reassembler::new(config, false)
+        }
+    }
+
+    /// Counters since this defragmenter was created
+    pub fn stats(&self) -> DefragStats {
+        self.inner.stats
+    }
+
+    /// Bytes of fragment data currently buffered
+    pub fn memory(&self) -> usize {
+        self.inner.memory
+    }
+
+    /// Number of datagrams waiting for more fragments
+    pub fn pending(&self) -> usize {
+        self.inner.buffers.len()
+    }
+
+    /// Feed a packet received at `now` milliseconds. Packets that are not IPv4
+    /// fragments are returned as they are; fragments are buffered and the
+    /// datagram is returned once its last missing fragment arrives.
+    pub fn push(&mut self, packet : &IpPacket, now : u64) -> Option<Datagram> {
+        self.inner.expire(now);
+
+        let header = match packet.header {
+            IpHeader::V4(ref header) if packet.is_fragment() => header,
+            _ => return Some(Datagram::from_packet(packet))
+        };
+
+        let key = (header.source_addr, header.dest_addr, header.protocol, header.id);
+        let start = 8 * header.fragment_offset as usize;
+        let first = if start == 0 { Some((packet.header.clone(), packet.protocol)) } else { None };
+        let fragment = Fragment {
+            start       : start,
+            more        : header.more_fragments,
+            header_len  : 4 * header.ihl as usize,
+            payload     : packet.payload
+        };
+        let buffer = match self.inner.add(key, fragment, first, now) {
+            Some(buffer) => buffer,
+            None => return None
+        };
+
+        let (header, protocol, payload) = buffer.assemble();
+        let mut header = match header {
+            IpHeader::V4(header) => header,
+            IpHeader::V6(_) => unreachable!()
+        };
+        let total_len = 4 * header.ihl as usize + payload.len();
+        if total_len > MAX_DATAGRAM_LEN {
+            self.inner.stats.invalid += 1;
+            return None;
+        }
+        header.total_len = total_len as u16;
+        header.more_fragments = false;
+        header.fragment_offset = 0;
+        Some(Datagram {
+            header      : IpHeader::V4(header),
+            protocol    : protocol,
+            payload     : payload
+        })
+    }
+
+    /// Drop datagrams that have waited longer than the timeout at `now`
+    pub fn expire(&mut self, now : u64) {
+        self.inner.expire(now)
+    }
+}
+
+/// Reassembles IPv6 packets from their Fragment headers. Following RFC 5722,
+/// a datagram with overlapping fragments is discarded along with any of its
+/// fragments that arrive later, so the overlap policy is not used. Atomic
+/// fragments (RFC 6946) are returned as they are, without touching any
+/// datagram being reassembled with the same identification.
+pub struct IPv6Defragmenter {
+    inner : Reassembler<([u8; 16], [u8; 16], u32)>
+}
+
+impl IPv6Defragmenter {
+    pub fn new(config : DefragConfig) -> IPv6Defragmenter {
+        IPv6Defragmenter {
+            inner : Reassembler::new(config, true)
+        }
+    }
+
+    /// Counters since this defragmenter was created
+    pub fn stats(&self) -> DefragStats {
+        self.inner.stats
+    }
+
+    /// Bytes of fragment data currently buffered
+    pub fn memory(&self) -> usize {
+        self.inner.memory
+    }
+
+    /// Number of datagrams waiting for more fragments, or discarded and waiting to time out
+    pub fn pending(&self) -> usize {
+        self.inner.buffers.len()
+    }
+
+    /// Feed a packet received at `now` milliseconds. Packets that are not IPv6
+    /// fragments are returned as they are; fragments are buffered and the
+    /// packet is returned once its last missing fragment arrives, without the
+    /// Fragment header and with any extension headers that followed it walked.
+    pub fn push(&mut self, packet : &IpPacket, now : u64) -> Option<Datagram> {
+        self.inner.expire(now);
+
+        let (header, fragment) = match packet.header {
+            IpHeader::V6(ref header) if packet.is_fragment() => match header.fragment {
+                Some(fragment) => (header, fragment),
+                None => return Some(Datagram::from_packet(packet))
+            },
+            _ => return Some(Datagram::from_packet(packet))
+        };
+
+        // Everything between the fixed header and the Fragment header
+        let unfragmentable = header.payload_len as usize - IPV6_FRAGMENT_LEN - packet.payload.len();
+        let key = (header.source_addr, header.dest_addr, fragment.id);
+        let start = 8 * fragment.offset as usize;
+        let first = if start == 0 {
+            let mut first = header.clone();
+            first.payload_len = unfragmentable as u16;
+            first.fragment = None;
+            first.extension_headers.pop();
+            Some((IpHeader::V6(first), packet.protocol))
+        } else {
+            None
+        };
+        let received = Fragment {
+            start       : start,
+            more        : fragment.more_fragments,
+            header_len  : unfragmentable,
+            payload     : packet.payload
+        };
+        let buffer = match self.inner.add(key, received, first, now) {
+            Some(buffer) => buffer,
+            None => return None
+        };
+
+        let (header, protocol, data) = buffer.assemble();
+        let mut header = match header {
+            IpHeader::V6(header) => header,
+            IpHeader::V4(_) => unreachable!()
+        };
+        let payload_len = header.payload_len as usize + data.len();
+        if payload_len > MAX_DATAGRAM_LEN {
+            self.inner.stats.invalid += 1;
+            return None;
+        }
+        header.payload_len = payload_len as u16;
+        match header.walk_extensions(protocol, &data) {
+            Ok((protocol, offset)) => Some(Datagram {
+                header      : IpHeader::V6(header),
+                protocol    : protocol,
+                payload     : data[offset..].iter().cloned().collect()
+            }),
+            Err(_) => {
+                self.inner.stats.invalid += 1;
+                None
+            }
+        }
+    }
+
+    /// Drop datagrams that have waited longer than the timeout at `now`
+    pub fn expire(&mut self, now : u64) {
+        self.inner.expire(now)
+    }
+}
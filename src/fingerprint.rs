@@ -0,0 +1,670 @@
+//! # Passive OS Fingerprinting
+//! Identifies the operating system that sent a SYN or SYN-ACK the way p0f v3 does:
+//! the initial TTL, IP options length, MSS, window size and scale, option layout and
+//! quirks of the segment form a signature, which is looked up in a database in the
+//! `p0f.fp` format. Only its `[tcp:request]` (SYN) and `[tcp:response]` (SYN-ACK)
+//! sections are used.
+//!
+//! Without the IP header of the segment, the TTL, IP options length and the quirks
+//! of the IP header are not compared. Over IPv6, the quirks of the IPv4 header are
+//! not compared, so that signatures for both versions match, and the IP options
+//! length is 0 whatever the extension headers.
+//!
+//! # Example
+//! ```rust
+//! use tcp_parser::TcpSegment;
+//! use tcp_parser::fingerprint::{Fingerprint, SignatureDatabase};
+//! let database = SignatureDatabase::parse("[tcp:request]\n\
+//!                                          label = s:unix:Linux:3.11 and newer\n\
+//!                                          sig   = *:64:0:*:mss*20,10:mss,sok,ts,nop,ws:df,id+:0\n\
+//!                                          sig   = *:64:0:*:mss*20,7:mss,sok,ts,nop,ws:df,id+:0\n").unwrap();
+//! let syn = TcpSegment::parse(&[151, 116, 0, 80, 4, 12, 185, 160, 0, 0, 0, 0, 160, 2, 96, 224, 81, 40, 0, 0,
+//!                               2, 4, 4, 216, 4, 2, 8, 10, 1, 49, 10, 120, 0, 0, 0, 0, 1, 3, 3, 7]);
+//! let fingerprint = Fingerprint::new(&syn, None).unwrap();
+//! assert_eq!(database.identify(&fingerprint).unwrap().label.name, "Linux");
+//! ```
+
+use std::vec::Vec;
+use std::string::String;
+use std::fmt;
+use std::str::FromStr;
+use super::{TcpSegment, TcpOpts, SeqNum, URG, ACK, PSH, SYN, ECE, CWR};
+use ip::IpHeader;
+use window::MAX_WINDOW_SHIFT;
+
+/// Largest number of hops between a host and the capture
+const MAX_DISTANCE : u8 = 35;
+
+bitflags! {
+    /// Oddities of the IP and TCP headers, as named by p0f
+    flags Quirks : u32 {
+        /// `df`: the IPv4 Don't Fragment flag is set
+        const DONT_FRAGMENT    = 0b00000000000000001,
+        /// `id+`: the IPv4 ID is non-zero with Don't Fragment
+        const NONZERO_ID       = 0b00000000000000010,
+        /// `id-`: the IPv4 ID is zero without Don't Fragment
+        const ZERO_ID          = 0b00000000000000100,
+        /// `ecn`: ECN is used by the IP header or the TCP flags
+        const ECN              = 0b00000000000001000,
+        /// `0+`: the reserved IPv4 flag is set
+        const MUST_BE_ZERO     = 0b00000000000010000,
+        /// `flow`: the IPv6 flow label is non-zero
+        const FLOW_LABEL       = 0b00000000000100000,
+        /// `seq-`: the sequence number is zero
+        const ZERO_SEQ         = 0b00000000001000000,
+        /// `ack+`: the acknowledgement number is non-zero without ACK
+        const NONZERO_ACK      = 0b00000000010000000,
+        /// `ack-`: the acknowledgement number is zero with ACK
+        const ZERO_ACK         = 0b00000000100000000,
+        /// `uptr+`: the urgent pointer is non-zero without URG
+        const NONZERO_URG_PTR  = 0b00000001000000000,
+        /// `urgf+`: the URG flag is set
+        const URG_FLAG         = 0b00000010000000000,
+        /// `pushf+`: the PSH flag is set
+        const PUSH_FLAG        = 0b00000100000000000,
+        /// `ts1-`: the TSval is zero
+        const ZERO_TSVAL       = 0b00001000000000000,
+        /// `ts2+`: the TSecr of a SYN is non-zero
+        const NONZERO_TSECR    = 0b00010000000000000,
+        /// `opt+`: non-zero bytes follow the END option
+        const TRAILING_OPTIONS = 0b00100000000000000,
+        /// `exws`: the window scale is above 14
+        const EXCESSIVE_SCALE  = 0b01000000000000000,
+        /// `bad`: the options are malformed, which parsing rejects
+        const BAD_OPTIONS      = 0b10000000000000000
+    }
+}
+
+/// The quirks only found in the IPv4 header
+fn ipv4_quirks() -> Quirks {
+    DONT_FRAGMENT | NONZERO_ID | ZERO_ID | MUST_BE_ZERO
+}
+
+/// The quirks only found in the IP header
+fn ip_quirks() -> Quirks {
+    ipv4_quirks() | FLOW_LABEL
+}
+
+/// The quirks with their names in signatures
+const QUIRK_NAMES : [(&'static str, Quirks); 17] = [
+    ("df", DONT_FRAGMENT), ("id+", NONZERO_ID), ("id-", ZERO_ID), ("ecn", ECN), ("0+", MUST_BE_ZERO),
+    ("flow", FLOW_LABEL), ("seq-", ZERO_SEQ), ("ack+", NONZERO_ACK), ("ack-", ZERO_ACK),
+    ("uptr+", NONZERO_URG_PTR), ("urgf+", URG_FLAG), ("pushf+", PUSH_FLAG), ("ts1-", ZERO_TSVAL),
+    ("ts2+", NONZERO_TSECR), ("opt+", TRAILING_OPTIONS), ("exws", EXCESSIVE_SCALE), ("bad", BAD_OPTIONS)
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Which segment of the handshake a signature describes
+pub enum SignatureKind {
+    /// A SYN, in a `[tcp:request]` section
+    Request,
+    /// A SYN-ACK, in a `[tcp:response]` section
+    Response
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IpVersion {
+    V4,
+    V6
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// The window size of a signature
+pub enum WindowSize {
+    /// `*`
+    Any,
+    /// A fixed size
+    Value(u16),
+    /// `mss*N`: a multiple of the MSS
+    Mss(u16),
+    /// `mtu*N`: a multiple of the MTU the MSS implies
+    Mtu(u16),
+    /// `%N`: any multiple of N
+    Multiple(u16)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// One entry of the option layout of a signature
+pub enum OptionLayout {
+    /// `eol+N`: END followed by N bytes of padding
+    Eol(u8),
+    Nop,
+    Mss,
+    Ws,
+    Sok,
+    Sack,
+    Ts,
+    /// `?N`: an option of kind N
+    Unknown(u8)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// A p0f signature. Fields that are None match anything.
+pub struct Signature {
+    pub version : Option<IpVersion>,
+    /// The TTL the sender starts with
+    pub ttl     : u8,
+    /// Whether the TTL is unreliable, e.g. `64-`, so only needs to be no larger
+    pub bad_ttl : bool,
+    /// Length of the IPv4 options, always 0 for IPv6 as in p0f
+    pub olen    : u8,
+    pub mss     : Option<u16>,
+    pub window  : WindowSize,
+    pub scale   : Option<u8>,
+    pub layout  : Vec<OptionLayout>,
+    pub quirks  : Quirks,
+    /// Whether the segment carries data
+    pub payload : Option<bool>
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// What a signature identifies, e.g. `s:unix:Linux:3.11 and newer`
+pub struct Label {
+    /// Whether the signature is generic (`g`) rather than specific (`s`)
+    pub generic : bool,
+    /// `win`, `unix`, `other`, or `!` for an application rather than an OS
+    pub class   : String,
+    pub name    : String,
+    pub flavor  : String
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// A signature of a database, with its label
+pub struct Entry {
+    pub kind        : SignatureKind,
+    pub label       : Label,
+    pub signature   : Signature
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// The reasons a signature can fail to parse
+pub enum SignatureError {
+    /// There are not 8 fields, carries how many there are
+    FieldCount(usize),
+    /// A field is malformed, carries its index counting from 0, e.g. 4 for `wsize,scale`
+    Field(usize)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// The reasons a database can fail to parse. Each variant carries the line
+/// number, counting from 1.
+pub enum DatabaseError {
+    /// A line of a TCP section is neither a comment nor `key = value`
+    InvalidLine(usize),
+    /// A label is not `type:class:name:flavor`
+    InvalidLabel(usize),
+    /// A signature is malformed, and why
+    InvalidSignature(usize, SignatureError),
+    /// A signature comes before any label of its section
+    MissingLabel(usize)
+}
+
+impl DatabaseError {
+    /// The line at which parsing failed
+    pub fn line(&self) -> usize {
+        match self {
+            &DatabaseError::InvalidLine(line)          |
+            &DatabaseError::InvalidLabel(line)         |
+            &DatabaseError::InvalidSignature(line, _)  |
+            &DatabaseError::MissingLabel(line)         => line
+        }
+    }
+}
+
+/// A field that is either `*` or a value, at the given index
+fn wildcard<T : FromStr>(field : &str, index : usize) -> Result<Option<T>, SignatureError> {
+    if field == "*" {
+        return Ok(None);
+    }
+    number(field, index).map(Some)
+}
+
+fn number<T : FromStr>(field : &str, index : usize) -> Result<T, SignatureError> {
+    field.parse().map_err(|_| SignatureError::Field(index))
+}
+
+impl Signature {
+    /// Parse the `ver:ittl:olen:mss:wsize,scale:olayout:quirks:pclass` form of a signature
+    pub fn parse(sig : &str) -> Result<Signature, SignatureError> {
+        let fields : Vec<&str> = sig.split(':').map(|field| field.trim()).collect();
+        if fields.len() != 8 {
+            return Err(SignatureError::FieldCount(fields.len()));
+        }
+
+        let version = match fields[0] {
+            "4" => Some(IpVersion::V4),
+            "6" => Some(IpVersion::V6),
+            "*" => None,
+            _ => return Err(SignatureError::Field(0))
+        };
+
+        let (ttl, bad_ttl) = if fields[1].ends_with('-') {
+            (try!(number(fields[1].trim_right_matches('-'), 1)), true)
+        } else if let Some(plus) = fields[1].find('+') {
+            // The `observed+distance` form p0f suggests for new signatures
+            let observed : u8 = try!(number(&fields[1][..plus], 1));
+            let distance = &fields[1][plus + 1..];
+            let distance : u8 = if distance == "?" { 0 } else { try!(number(distance, 1)) };
+            (try!(observed.checked_add(distance).ok_or(SignatureError::Field(1))), false)
+        } else {
+            (try!(number(fields[1], 1)), false)
+        };
+
+        let window_scale : Vec<&str> = fields[4].split(',').collect();
+        if window_scale.len() != 2 {
+            return Err(SignatureError::Field(4));
+        }
+        let window = match window_scale[0] {
+            "*" => WindowSize::Any,
+            w if w.starts_with("mss*") => WindowSize::Mss(try!(number(&w[4..], 4))),
+            w if w.starts_with("mtu*") => WindowSize::Mtu(try!(number(&w[4..], 4))),
+            w if w.starts_with('%') => WindowSize::Multiple(try!(number(&w[1..], 4))),
+            w => WindowSize::Value(try!(number(w, 4)))
+        };
+
+        let mut layout = Vec::new();
+        for name in fields[5].split(',').filter(|name| !name.is_empty()) {
+            layout.push(match name {
+                "nop" => OptionLayout::Nop,
+                "mss" => OptionLayout::Mss,
+                "ws" => OptionLayout::Ws,
+                "sok" => OptionLayout::Sok,
+                "sack" => OptionLayout::Sack,
+                "ts" => OptionLayout::Ts,
+                n if n.starts_with("eol+") => OptionLayout::Eol(try!(number(&n[4..], 5))),
+                n if n.starts_with('?') => OptionLayout::Unknown(try!(number(&n[1..], 5))),
+                _ => return Err(SignatureError::Field(5))
+            });
+        }
+
+        let mut quirks = Quirks::empty();
+        for name in fields[6].split(',').filter(|name| !name.is_empty()) {
+            match QUIRK_NAMES.iter().find(|quirk| quirk.0 == name) {
+                Some(quirk) => quirks.insert(quirk.1),
+                None => return Err(SignatureError::Field(6))
+            }
+        }
+
+        Ok(Signature {
+            version : version,
+            ttl     : ttl,
+            bad_ttl : bad_ttl,
+            olen    : try!(number(fields[2], 2)),
+            mss     : try!(wildcard(fields[3], 3)),
+            window  : window,
+            scale   : try!(wildcard(window_scale[1], 4)),
+            layout  : layout,
+            quirks  : quirks,
+            payload : match fields[7] {
+                "0" => Some(false),
+                "+" => Some(true),
+                "*" => None,
+                _ => return Err(SignatureError::Field(7))
+            }
+        })
+    }
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.version {
+            Some(IpVersion::V4) => try!(write!(f, "4:")),
+            Some(IpVersion::V6) => try!(write!(f, "6:")),
+            None => try!(write!(f, "*:"))
+        }
+        try!(write!(f, "{}{}:{}:", self.ttl, if self.bad_ttl { "-" } else { "" }, self.olen));
+        match self.mss {
+            Some(mss) => try!(write!(f, "{}:", mss)),
+            None => try!(write!(f, "*:"))
+        }
+        try!(match self.window {
+            WindowSize::Any => write!(f, "*,"),
+            WindowSize::Value(window) => write!(f, "{},", window),
+            WindowSize::Mss(n) => write!(f, "mss*{},", n),
+            WindowSize::Mtu(n) => write!(f, "mtu*{},", n),
+            WindowSize::Multiple(n) => write!(f, "%{},", n)
+        });
+        match self.scale {
+            Some(scale) => try!(write!(f, "{}:", scale)),
+            None => try!(write!(f, "*:"))
+        }
+        for (i, opt) in self.layout.iter().enumerate() {
+            if i > 0 {
+                try!(write!(f, ","));
+            }
+            try!(match *opt {
+                OptionLayout::Eol(padding) => write!(f, "eol+{}", padding),
+                OptionLayout::Nop => write!(f, "nop"),
+                OptionLayout::Mss => write!(f, "mss"),
+                OptionLayout::Ws => write!(f, "ws"),
+                OptionLayout::Sok => write!(f, "sok"),
+                OptionLayout::Sack => write!(f, "sack"),
+                OptionLayout::Ts => write!(f, "ts"),
+                OptionLayout::Unknown(kind) => write!(f, "?{}", kind)
+            });
+        }
+        try!(write!(f, ":"));
+        let mut first = true;
+        for &(name, _) in QUIRK_NAMES.iter().filter(|quirk| self.quirks.contains(quirk.1)) {
+            try!(write!(f, "{}{}", if first { "" } else { "," }, name));
+            first = false;
+        }
+        match self.payload {
+            Some(false) => write!(f, ":0"),
+            Some(true) => write!(f, ":+"),
+            None => write!(f, ":*")
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// What a SYN or SYN-ACK reveals about its sender
+pub struct Fingerprint {
+    pub kind    : SignatureKind,
+    /// The IP version, TTL and options length, when the IP header is known
+    pub version : Option<IpVersion>,
+    pub ttl     : Option<u8>,
+    pub olen    : u8,
+    pub mss     : Option<u16>,
+    pub window  : u16,
+    pub scale   : Option<u8>,
+    pub layout  : Vec<OptionLayout>,
+    pub quirks  : Quirks,
+    pub payload : bool
+}
+
+impl Fingerprint {
+    /// The fingerprint of a SYN or SYN-ACK, with the IP header that carried it if known.
+    /// Returns None for other segments.
+    pub fn new(segment : &TcpSegment, ip : Option<&IpHeader>) -> Option<Fingerprint> {
+        let kind = match (segment.ctrl_flags.contains(SYN), segment.ctrl_flags.contains(ACK)) {
+            (true, false) => SignatureKind::Request,
+            (true, true) => SignatureKind::Response,
+            _ => return None
+        };
+        let mut quirks = Quirks::empty();
+        let (mut version, mut ttl, mut olen) = (None, None, 0);
+
+        match ip {
+            Some(&IpHeader::V4(ref header)) => {
+                version = Some(IpVersion::V4);
+                ttl = Some(header.ttl);
+                olen = header.options.len() as u8;
+                if header.reserved_flag {
+                    quirks.insert(MUST_BE_ZERO);
+                }
+                if header.dont_fragment {
+                    quirks.insert(DONT_FRAGMENT);
+                    if header.id != 0 {
+                        quirks.insert(NONZERO_ID);
+                    }
+                } else if header.id == 0 {
+                    quirks.insert(ZERO_ID);
+                }
+                if header.tos & 0x03 != 0 {
+                    quirks.insert(ECN);
+                }
+            },
+            Some(&IpHeader::V6(ref header)) => {
+                version = Some(IpVersion::V6);
+                ttl = Some(header.hop_limit);
+                if header.flow_label != 0 {
+                    quirks.insert(FLOW_LABEL);
+                }
+                if header.traffic_class & 0x03 != 0 {
+                    quirks.insert(ECN);
+                }
+            },
+            None => {}
+        }
+
+        let flags = segment.ctrl_flags;
+        let tcp_quirks = [(flags.intersects(ECE | CWR), ECN),
+                          (segment.seq() == SeqNum(0), ZERO_SEQ),
+                          (!flags.contains(ACK) && segment.ack() != SeqNum(0), NONZERO_ACK),
+                          (flags.contains(ACK) && segment.ack() == SeqNum(0), ZERO_ACK),
+                          (!flags.contains(URG) && segment.urg_ptr != 0, NONZERO_URG_PTR),
+                          (flags.contains(URG), URG_FLAG),
+                          (flags.contains(PSH), PUSH_FLAG)];
+        for &(found, quirk) in tcp_quirks.iter() {
+            if found {
+                quirks.insert(quirk);
+            }
+        }
+
+        let (mut mss, mut scale) = (None, None);
+        let mut layout = Vec::new();
+        for opt in segment.options.iter() {
+            match opt {
+                &TcpOpts::END => {
+                    let padding = segment.options.iter().filter_map(|opt| match opt {
+                        &TcpOpts::Padding(ref padding) => Some(padding.len()),
+                        _ => None
+                    }).next().unwrap_or(0);
+                    layout.push(OptionLayout::Eol(padding as u8));
+                },
+                &TcpOpts::Padding(ref padding) => {
+                    if padding.iter().any(|&byte| byte != 0) {
+                        quirks.insert(TRAILING_OPTIONS);
+                    }
+                },
+                &TcpOpts::NOP => layout.push(OptionLayout::Nop),
+                &TcpOpts::MSS(size) => {
+                    mss = Some(size);
+                    layout.push(OptionLayout::Mss);
+                },
+                &TcpOpts::WindowScale(shift) => {
+                    scale = Some(shift);
+                    if shift > MAX_WINDOW_SHIFT {
+                        quirks.insert(EXCESSIVE_SCALE);
+                    }
+                    layout.push(OptionLayout::Ws);
+                },
+                &TcpOpts::SAckPermitted => layout.push(OptionLayout::Sok),
+                &TcpOpts::SAck(_) => layout.push(OptionLayout::Sack),
+                &TcpOpts::TimeStamp { time, echo } => {
+                    if time == 0 {
+                        quirks.insert(ZERO_TSVAL);
+                    }
+                    if echo != 0 && kind == SignatureKind::Request {
+                        quirks.insert(NONZERO_TSECR);
+                    }
+                    layout.push(OptionLayout::Ts);
+                },
+                _ => layout.push(OptionLayout::Unknown(opt.kind().unwrap()))
+            }
+        }
+
+        Some(Fingerprint {
+            kind    : kind,
+            version : version,
+            ttl     : ttl,
+            olen    : olen,
+            mss     : mss,
+            window  : segment.window,
+            scale   : scale,
+            layout  : layout,
+            quirks  : quirks,
+            payload : !segment.data.is_empty()
+        })
+    }
+
+    /// The initial TTL the sender most likely used
+    pub fn initial_ttl(&self) -> Option<u8> {
+        self.ttl.map(|ttl| if ttl <= 32 { 32 } else if ttl <= 64 { 64 } else if ttl <= 128 { 128 } else { 255 })
+    }
+
+    /// A signature matching this fingerprint, for adding to a database. None
+    /// without the IP header, as signatures need the initial TTL.
+    pub fn signature(&self) -> Option<Signature> {
+        let ttl = match self.initial_ttl() {
+            Some(ttl) => ttl,
+            None => return None
+        };
+        let window = match self.mss {
+            Some(mss) if mss != 0 && self.window != 0 && self.window % mss == 0 => WindowSize::Mss(self.window / mss),
+            _ => WindowSize::Value(self.window)
+        };
+        Some(Signature {
+            version : self.version,
+            ttl     : ttl,
+            bad_ttl : false,
+            olen    : self.olen,
+            mss     : Some(self.mss.unwrap_or(0)),
+            window  : window,
+            scale   : Some(self.scale.unwrap_or(0)),
+            layout  : self.layout.clone(),
+            quirks  : self.quirks,
+            payload : Some(self.payload)
+        })
+    }
+
+    /// Whether a signature matches, giving the number of hops from the sender if known
+    fn matches(&self, signature : &Signature) -> Option<Option<u8>> {
+        let mss = self.mss.unwrap_or(0);
+        let ignored = match self.version {
+            Some(IpVersion::V4) => Quirks::empty(),
+            Some(IpVersion::V6) => ipv4_quirks(),
+            None => ip_quirks()
+        };
+        let (quirks, sig_quirks) = (self.quirks - ignored, signature.quirks - ignored);
+
+        let window = match signature.window {
+            WindowSize::Any => true,
+            WindowSize::Value(window) => self.window == window,
+            WindowSize::Mss(n) => self.window as u32 == mss as u32 * n as u32,
+            WindowSize::Mtu(n) => {
+                let header_len = if self.version == Some(IpVersion::V6) { 60 } else { 40 };
+                self.window as u32 == (mss as u32 + header_len) * n as u32
+            },
+            WindowSize::Multiple(n) => n != 0 && self.window % n == 0
+        };
+        if !window || signature.layout != self.layout || sig_quirks != quirks ||
+           signature.mss.map_or(false, |sig_mss| sig_mss != mss) ||
+           signature.scale.map_or(false, |sig_scale| sig_scale != self.scale.unwrap_or(0)) ||
+           signature.payload.map_or(false, |payload| payload != self.payload) {
+            return None;
+        }
+
+        let version = match self.version {
+            Some(version) => version,
+            None => return Some(None)
+        };
+        if signature.version.map_or(false, |sig_version| sig_version != version) || signature.olen != self.olen {
+            return None;
+        }
+        let ttl = self.ttl.unwrap();
+        if ttl > signature.ttl || (!signature.bad_ttl && signature.ttl - ttl > MAX_DISTANCE) {
+            return None;
+        }
+        Some(if signature.bad_ttl { None } else { Some(signature.ttl - ttl) })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// The signature a fingerprint matched
+pub struct Match<'a> {
+    pub label       : &'a Label,
+    pub signature   : &'a Signature,
+    /// The number of hops between the sender and the capture, if known
+    pub distance    : Option<u8>
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+/// The TCP signatures of a p0f v3 database
+pub struct SignatureDatabase {
+    entries : Vec<Entry>
+}
+
+impl SignatureDatabase {
+    /// Parse the contents of a `p0f.fp` file. Sections other than `[tcp:request]`
+    /// and `[tcp:response]` are skipped.
+    pub fn parse(text : &str) -> Result<SignatureDatabase, DatabaseError> {
+        let mut entries = Vec::new();
+        let mut kind = None;
+        let mut label : Option<Label> = None;
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+            if line.starts_with('[') {
+                kind = match line {
+                    "[tcp:request]" => Some(SignatureKind::Request),
+                    "[tcp:response]" => Some(SignatureKind::Response),
+                    _ => None
+                };
+                label = None;
+                continue;
+            }
+            let kind = match kind {
+                Some(kind) => kind,
+                None => continue
+            };
+
+            let (key, value) = match line.find('=') {
+                Some(equals) => (line[..equals].trim(), line[equals + 1..].trim()),
+                None => return Err(DatabaseError::InvalidLine(i + 1))
+            };
+            match key {
+                "label" => {
+                    let fields : Vec<&str> = value.splitn(4, ':').collect();
+                    if fields.len() != 4 || (fields[0] != "s" && fields[0] != "g") {
+                        return Err(DatabaseError::InvalidLabel(i + 1));
+                    }
+                    label = Some(Label {
+                        generic : fields[0] == "g",
+                        class   : String::from(fields[1]),
+                        name    : String::from(fields[2]),
+                        flavor  : String::from(fields[3])
+                    });
+                },
+                "sig" => {
+                    let signature = try!(Signature::parse(value).map_err(|err| DatabaseError::InvalidSignature(i + 1, err)));
+                    entries.push(Entry {
+                        kind        : kind,
+                        label       : try!(label.clone().ok_or(DatabaseError::MissingLabel(i + 1))),
+                        signature   : signature
+                    });
+                },
+                _ => {}
+            }
+        }
+        Ok(SignatureDatabase { entries: entries })
+    }
+
+    /// The signatures, in the order of the file
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The first specific signature matching a fingerprint, or else the first generic one
+    pub fn identify<'a>(&'a self, fingerprint : &Fingerprint) -> Option<Match<'a>> {
+        let mut generic = None;
+        for entry in self.entries.iter().filter(|entry| entry.kind == fingerprint.kind) {
+            if let Some(distance) = fingerprint.matches(&entry.signature) {
+                let found = Match {
+                    label       : &entry.label,
+                    signature   : &entry.signature,
+                    distance    : distance
+                };
+                if !entry.label.generic {
+                    return Some(found);
+                }
+                if generic.is_none() {
+                    generic = Some(found);
+                }
+            }
+        }
+        generic
+    }
+}
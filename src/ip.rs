@@ -0,0 +1,503 @@
+//! # Link and Network Layers
+//! Parsing of Ethernet II frames (with 802.1Q and QinQ tags), IPv4 packets
+//! (with options and fragment fields) and IPv6 packets (with extension header
+//! chains), to locate the TCP segment they carry and build its pseudo-header.
+//!
+//! # Example
+//! ```rust
+//! use tcp_parser::TcpSegment;
+//! use tcp_parser::ip;
+//! let frame : Vec<u8> = vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 0x08, 0x00,
+//!                            0x45, 0, 0, 40, 0, 1, 0x40, 0, 64, 6, 0, 0,
+//!                            10, 0, 0, 1, 10, 0, 0, 2,
+//!                            0x30, 0x39, 0, 80, 0, 0, 0, 1, 0, 0, 0, 0,
+//!                            0x50, 0x02, 0x72, 0x10, 0xf9, 0x45, 0, 0];
+//! let (tcp, pseudo_header) = ip::tcp_from_ethernet(&frame).unwrap();
+//! assert!(TcpSegment::parse(tcp).verify_checksum(pseudo_header));
+//! ```
+
+use std::vec::Vec;
+use super::{IPv4PseudoHeader, IPv6PseudoHeader, PseudoHeader};
+use util::{U8ToU16, U8ToU32};
+
+/// EtherType of an IPv4 payload
+pub const ETHERTYPE_IPV4    : u16 = 0x0800;
+/// EtherType of an IPv6 payload
+pub const ETHERTYPE_IPV6    : u16 = 0x86DD;
+/// Tag protocol identifier of an 802.1Q customer VLAN tag
+pub const ETHERTYPE_VLAN    : u16 = 0x8100;
+/// Tag protocol identifier of an 802.1ad service VLAN tag (QinQ)
+pub const ETHERTYPE_QINQ    : u16 = 0x88A8;
+/// Pre-standard tag protocol identifier still used for QinQ
+pub const ETHERTYPE_QINQ_OLD: u16 = 0x9100;
+
+/// IP protocol number of TCP
+pub const IPPROTO_TCP       : u8 = 6;
+
+/// IPv6 Hop-by-Hop Options extension header
+pub const IPV6_HOP_BY_HOP   : u8 = 0;
+/// IPv6 Routing extension header
+pub const IPV6_ROUTING      : u8 = 43;
+/// IPv6 Fragment extension header
+pub const IPV6_FRAGMENT     : u8 = 44;
+/// IPsec Encapsulating Security Payload
+pub const IPV6_ESP          : u8 = 50;
+/// IPsec Authentication Header
+pub const IPV6_AUTH         : u8 = 51;
+/// No next header
+pub const IPV6_NO_NEXT      : u8 = 59;
+/// IPv6 Destination Options extension header
+pub const IPV6_DEST_OPTS    : u8 = 60;
+/// Mobility extension header
+pub const IPV6_MOBILITY     : u8 = 135;
+/// Host Identity Protocol extension header
+pub const IPV6_HIP          : u8 = 139;
+/// Shim6 extension header
+pub const IPV6_SHIM6        : u8 = 140;
+
+const ETHERNET_HEADER_LEN   : usize = 14;
+const VLAN_TAG_LEN          : usize = 4;
+const IPV4_HEADER_LEN       : usize = 20;
+const IPV6_HEADER_LEN       : usize = 40;
+const IPV6_FRAGMENT_LEN     : usize = 8;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// The reasons a frame or packet can fail to yield a TCP segment. Offsets
+/// are relative to the start of the buffer that was passed in.
+pub enum IpParseError {
+    /// The buffer ended before the header starting at this offset did
+    Truncated(usize),
+    /// The frame carries neither IPv4 nor IPv6
+    UnsupportedEtherType(u16),
+    /// The IP version field is neither 4 nor 6
+    InvalidVersion(u8),
+    /// The IPv4 header length is below 5 words
+    InvalidHeaderLength(u8),
+    /// The IP length field disagrees with the header or the buffer
+    InvalidTotalLength(usize),
+    /// The packet is a fragment; its TCP segment needs reassembly first
+    Fragmented,
+    /// The upper-layer protocol is not TCP
+    NotTcp(u8)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// An 802.1Q or 802.1ad VLAN tag
+pub struct VlanTag {
+    /// Tag protocol identifier
+    pub tpid    : u16,
+    /// Priority code point
+    pub pcp     : u8,
+    /// Drop eligible indicator
+    pub dei     : bool,
+    /// VLAN identifier
+    pub vid     : u16
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// An Ethernet II frame, with its VLAN tags outermost first
+pub struct EthernetFrame<'a> {
+    pub dest_mac    : [u8; 6],
+    pub src_mac     : [u8; 6],
+    pub vlan_tags   : Vec<VlanTag>,
+    /// EtherType of the payload, after any VLAN tags
+    pub ether_type  : u16,
+    pub payload     : &'a [u8]
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// An IPv4 header
+pub struct IPv4Header {
+    /// Header length in 32-bit words
+    pub ihl             : u8,
+    /// Differentiated services code point and ECN bits
+    pub tos             : u8,
+    pub total_len       : u16,
+    pub id              : u16,
+    /// The reserved flag, which must be zero
+    pub reserved_flag   : bool,
+    pub dont_fragment   : bool,
+    pub more_fragments  : bool,
+    /// Fragment offset in units of 8 bytes
+    pub fragment_offset : u16,
+    pub ttl             : u8,
+    pub protocol        : u8,
+    pub checksum        : u16,
+    pub source_addr     : u32,
+    pub dest_addr       : u32,
+    /// Raw options, including any padding
+    pub options         : Vec<u8>
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// The contents of an IPv6 Fragment extension header
+pub struct IPv6Fragment {
+    /// Fragment offset in units of 8 bytes
+    pub offset          : u16,
+    pub more_fragments  : bool,
+    pub id              : u32
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// An IPv6 header, along with what was learnt walking its extension headers
+pub struct IPv6Header {
+    pub traffic_class   : u8,
+    pub flow_label      : u32,
+    pub payload_len     : u16,
+    /// Next header field of the fixed header
+    pub next_header     : u8,
+    pub hop_limit       : u8,
+    pub source_addr     : [u8; 16],
+    pub dest_addr       : [u8; 16],
+    /// Types of the extension headers walked, in order
+    pub extension_headers : Vec<u8>,
+    /// The Fragment header, if one was present
+    pub fragment        : Option<IPv6Fragment>,
+    /// Final destination named by a Routing header with segments left
+    pub final_dest      : Option<[u8; 16]>
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// An IPv4 or IPv6 header
+pub enum IpHeader {
+    V4(IPv4Header),
+    V6(IPv6Header)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// An IPv4 or IPv6 pseudo-header
+pub enum IpPseudoHeader {
+    V4(IPv4PseudoHeader),
+    V6(IPv6PseudoHeader)
+}
+
+impl PseudoHeader for IpPseudoHeader {
+    fn as_u16_stream(&self) -> Vec<u16> {
+        match self {
+            &IpPseudoHeader::V4(ref header) => header.as_u16_stream(),
+            &IpPseudoHeader::V6(ref header) => header.as_u16_stream()
+        }
+    }
+
+    fn set_tcp_len(&mut self, len : usize) {
+        match self {
+            &mut IpPseudoHeader::V4(ref mut header) => header.set_tcp_len(len),
+            &mut IpPseudoHeader::V6(ref mut header) => header.set_tcp_len(len)
+        }
+    }
+}
+
+impl IpPseudoHeader {
+    /// The source address
+    pub fn source(&self) -> IpAddress {
+        match self {
+            &IpPseudoHeader::V4(ref header) => IpAddress::V4(header.source_addr),
+            &IpPseudoHeader::V6(ref header) => IpAddress::V6(header.source_addr)
+        }
+    }
+
+    /// The destination address
+    pub fn dest(&self) -> IpAddress {
+        match self {
+            &IpPseudoHeader::V4(ref header) => IpAddress::V4(header.dest_addr),
+            &IpPseudoHeader::V6(ref header) => IpAddress::V6(header.dest_addr)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+/// An IPv4 or IPv6 address
+pub enum IpAddress {
+    V4(u32),
+    V6([u8; 16])
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// An IP packet. For fragments, `protocol` and `payload` are those of the
+/// fragment itself: for IPv6 this is whatever follows the Fragment header.
+pub struct IpPacket<'a> {
+    pub header      : IpHeader,
+    /// Upper-layer protocol, after any IPv6 extension headers
+    pub protocol    : u8,
+    pub payload     : &'a [u8]
+}
+
+impl IPv6Header {
+    /// Walk the extension headers at the start of `data`, the first being of
+    /// type `next_header`, recording them on this header. Returns the protocol
+    /// of what follows and its offset into `data`.
+    pub fn walk_extensions(&mut self, next_header : u8, data : &[u8]) -> Result<(u8, usize), IpParseError> {
+        let mut next = next_header;
+        let mut offset = 0;
+        loop {
+            let len = match next {
+                IPV6_HOP_BY_HOP | IPV6_ROUTING | IPV6_DEST_OPTS | IPV6_MOBILITY | IPV6_HIP | IPV6_SHIM6 => {
+                    if data.len() < offset + 2 {
+                        return Err(IpParseError::Truncated(offset));
+                    }
+                    8 + 8 * data[offset + 1] as usize
+                },
+                IPV6_AUTH => {
+                    if data.len() < offset + 2 {
+                        return Err(IpParseError::Truncated(offset));
+                    }
+                    8 + 4 * data[offset + 1] as usize
+                },
+                IPV6_FRAGMENT => IPV6_FRAGMENT_LEN,
+                _ => break
+            };
+            if data.len() < offset + len {
+                return Err(IpParseError::Truncated(offset));
+            }
+            let kind = next;
+            let ext = &data[offset..offset + len];
+            self.extension_headers.push(kind);
+            next = ext[0];
+            offset += len;
+
+            if kind == IPV6_ROUTING {
+                if let Some(dest) = routing_final_dest(ext) {
+                    self.final_dest = Some(dest);
+                }
+            } else if kind == IPV6_FRAGMENT {
+                let frag = ext[2..4].iter().to_u16().unwrap();
+                let fragment = IPv6Fragment {
+                    offset          : frag >> 3,
+                    more_fragments  : frag & 0x0001 != 0,
+                    id              : ext[4..8].iter().to_u32().unwrap()
+                };
+                self.fragment = Some(fragment);
+                if fragment.offset != 0 || fragment.more_fragments {
+                    break;
+                }
+            }
+        }
+        Ok((next, offset))
+    }
+}
+
+impl<'a> EthernetFrame<'a> {
+    /// Parse the IP packet carried by this frame
+    pub fn ip(&self) -> Result<IpPacket<'a>, IpParseError> {
+        let offset = ETHERNET_HEADER_LEN + VLAN_TAG_LEN * self.vlan_tags.len();
+        let packet = match self.ether_type {
+            ETHERTYPE_IPV4 => parse_ipv4(self.payload),
+            ETHERTYPE_IPV6 => parse_ipv6(self.payload),
+            ether_type => return Err(IpParseError::UnsupportedEtherType(ether_type))
+        };
+        packet.map_err(|err| match err {
+            IpParseError::Truncated(off) => IpParseError::Truncated(offset + off),
+            IpParseError::InvalidTotalLength(off) => IpParseError::InvalidTotalLength(offset + off),
+            err => err
+        })
+    }
+}
+
+impl<'a> IpPacket<'a> {
+    /// Whether this packet is only part of the datagram it belongs to
+    pub fn is_fragment(&self) -> bool {
+        match self.header {
+            IpHeader::V4(ref header) => header.more_fragments || header.fragment_offset != 0,
+            IpHeader::V6(ref header) => match header.fragment {
+                Some(frag) => frag.more_fragments || frag.offset != 0,
+                None => false
+            }
+        }
+    }
+
+    /// The pseudo-header covering the payload of this packet
+    pub fn pseudo_header(&self) -> IpPseudoHeader {
+        match self.header {
+            IpHeader::V4(ref header) => IpPseudoHeader::V4(IPv4PseudoHeader {
+                source_addr : header.source_addr,
+                dest_addr   : header.dest_addr,
+                protocol    : self.protocol,
+                tcp_len     : self.payload.len() as u16
+            }),
+            IpHeader::V6(ref header) => IpPseudoHeader::V6(IPv6PseudoHeader {
+                source_addr : header.source_addr,
+                dest_addr   : header.final_dest.unwrap_or(header.dest_addr),
+                tcp_len     : self.payload.len() as u32,
+                next_header : self.protocol
+            })
+        }
+    }
+
+    /// The TCP segment carried by this packet, with its pseudo-header
+    pub fn tcp(&self) -> Result<(&'a [u8], IpPseudoHeader), IpParseError> {
+        if self.is_fragment() {
+            return Err(IpParseError::Fragmented);
+        }
+        if self.protocol != IPPROTO_TCP {
+            return Err(IpParseError::NotTcp(self.protocol));
+        }
+        Ok((self.payload, self.pseudo_header()))
+    }
+}
+
+/// Parse an Ethernet II header and any VLAN tags following it
+pub fn parse_ethernet<'a>(data : &'a [u8]) -> Result<EthernetFrame<'a>, IpParseError> {
+    if data.len() < ETHERNET_HEADER_LEN {
+        return Err(IpParseError::Truncated(0));
+    }
+    let mut dest_mac = [0u8; 6];
+    let mut src_mac = [0u8; 6];
+    dest_mac.copy_from_slice(&data[0..6]);
+    src_mac.copy_from_slice(&data[6..12]);
+
+    let mut vlan_tags = Vec::new();
+    let mut offset = 12;
+    let mut ether_type = data[12..14].iter().to_u16().unwrap();
+    while ether_type == ETHERTYPE_VLAN || ether_type == ETHERTYPE_QINQ || ether_type == ETHERTYPE_QINQ_OLD {
+        if data.len() < offset + VLAN_TAG_LEN + 2 {
+            return Err(IpParseError::Truncated(offset));
+        }
+        let tci = data[offset + 2..offset + 4].iter().to_u16().unwrap();
+        vlan_tags.push(VlanTag {
+            tpid    : ether_type,
+            pcp     : (tci >> 13) as u8,
+            dei     : tci & 0x1000 != 0,
+            vid     : tci & 0x0FFF
+        });
+        offset += VLAN_TAG_LEN;
+        ether_type = data[offset..offset + 2].iter().to_u16().unwrap();
+    }
+
+    Ok(EthernetFrame {
+        dest_mac    : dest_mac,
+        src_mac     : src_mac,
+        vlan_tags   : vlan_tags,
+        ether_type  : ether_type,
+        payload     : &data[offset + 2..]
+    })
+}
+
+/// Parse an IPv4 or IPv6 packet, depending on its version field
+pub fn parse_ip<'a>(data : &'a [u8]) -> Result<IpPacket<'a>, IpParseError> {
+    match data.first().map(|b| b >> 4) {
+        Some(4) => parse_ipv4(data),
+        Some(6) => parse_ipv6(data),
+        Some(version) => Err(IpParseError::InvalidVersion(version)),
+        None => Err(IpParseError::Truncated(0))
+    }
+}
+
+/// Parse an IPv4 packet. Link layer padding beyond the total length is dropped.
+pub fn parse_ipv4<'a>(data : &'a [u8]) -> Result<IpPacket<'a>, IpParseError> {
+    if data.len() < IPV4_HEADER_LEN {
+        return Err(IpParseError::Truncated(0));
+    }
+    if data[0] >> 4 != 4 {
+        return Err(IpParseError::InvalidVersion(data[0] >> 4));
+    }
+    let ihl = data[0] & 0x0F;
+    if ihl < 5 {
+        return Err(IpParseError::InvalidHeaderLength(ihl));
+    }
+    let header_len = 4 * ihl as usize;
+    if data.len() < header_len {
+        return Err(IpParseError::Truncated(0));
+    }
+    let total_len = data[2..4].iter().to_u16().unwrap();
+    if (total_len as usize) < header_len || total_len as usize > data.len() {
+        return Err(IpParseError::InvalidTotalLength(2));
+    }
+    let frag = data[6..8].iter().to_u16().unwrap();
+
+    let header = IPv4Header {
+        ihl             : ihl,
+        tos             : data[1],
+        total_len       : total_len,
+        id              : data[4..6].iter().to_u16().unwrap(),
+        reserved_flag   : frag & 0x8000 != 0,
+        dont_fragment   : frag & 0x4000 != 0,
+        more_fragments  : frag & 0x2000 != 0,
+        fragment_offset : frag & 0x1FFF,
+        ttl             : data[8],
+        protocol        : data[9],
+        checksum        : data[10..12].iter().to_u16().unwrap(),
+        source_addr     : data[12..16].iter().to_u32().unwrap(),
+        dest_addr       : data[16..20].iter().to_u32().unwrap(),
+        options         : data[IPV4_HEADER_LEN..header_len].iter().cloned().collect()
+    };
+    Ok(IpPacket {
+        protocol    : header.protocol,
+        header      : IpHeader::V4(header),
+        payload     : &data[header_len..total_len as usize]
+    })
+}
+
+/// Parse an IPv6 packet, walking its extension headers up to the upper-layer
+/// header. The walk stops after the Fragment header of a fragment that is not
+/// atomic, and at ESP or No Next Header, whose contents cannot be walked.
+pub fn parse_ipv6<'a>(data : &'a [u8]) -> Result<IpPacket<'a>, IpParseError> {
+    if data.len() < IPV6_HEADER_LEN {
+        return Err(IpParseError::Truncated(0));
+    }
+    if data[0] >> 4 != 6 {
+        return Err(IpParseError::InvalidVersion(data[0] >> 4));
+    }
+    let payload_len = data[4..6].iter().to_u16().unwrap();
+    let end = IPV6_HEADER_LEN + payload_len as usize;
+    if end > data.len() {
+        return Err(IpParseError::InvalidTotalLength(4));
+    }
+    let data = &data[..end];
+
+    let mut source_addr = [0u8; 16];
+    let mut dest_addr = [0u8; 16];
+    source_addr.copy_from_slice(&data[8..24]);
+    dest_addr.copy_from_slice(&data[24..40]);
+
+    let mut header = IPv6Header {
+        traffic_class       : (data[0..2].iter().to_u16().unwrap() >> 4) as u8,
+        flow_label          : data[0..4].iter().to_u32().unwrap() & 0x000F_FFFF,
+        payload_len         : payload_len,
+        next_header         : data[6],
+        hop_limit           : data[7],
+        source_addr         : source_addr,
+        dest_addr           : dest_addr,
+        extension_headers   : Vec::new(),
+        fragment            : None,
+        final_dest          : None
+    };
+
+    let next_header = header.next_header;
+    let (protocol, offset) = match header.walk_extensions(next_header, &data[IPV6_HEADER_LEN..]) {
+        Ok(walked) => walked,
+        Err(IpParseError::Truncated(off)) => return Err(IpParseError::Truncated(IPV6_HEADER_LEN + off)),
+        Err(err) => return Err(err)
+    };
+
+    Ok(IpPacket {
+        header      : IpHeader::V6(header),
+        protocol    : protocol,
+        payload     : &data[IPV6_HEADER_LEN + offset..]
+    })
+}
+
+/// The final destination named by a Routing header, if segments are left
+/// (RFC 8200 section 8.1). Types 0 (deprecated), 2 (Mobile IPv6) and 4
+/// (Segment Routing) are understood.
+fn routing_final_dest(ext : &[u8]) -> Option<[u8; 16]> {
+    let routing_type = ext[2];
+    let segments_left = ext[3];
+    if segments_left == 0 || ext.len() < 8 + 16 {
+        return None;
+    }
+    let start = match routing_type {
+        0 => ext.len() - 16,
+        2 | 4 => 8,
+        _ => return None
+    };
+    let mut dest = [0u8; 16];
+    dest.copy_from_slice(&ext[start..start + 16]);
+    Some(dest)
+}
+
+/// Locate the TCP segment in an Ethernet frame, along with its pseudo-header
+pub fn tcp_from_ethernet(data : &[u8]) -> Result<(&[u8], IpPseudoHeader), IpParseError> {
+    let frame = try!(parse_ethernet(data));
+    let packet = try!(frame.ip());
+    packet.tcp()
+}
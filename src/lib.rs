@@ -18,18 +18,48 @@ extern crate collections;
 
 #[cfg(feature = "core")]
 mod std {
-    pub use core::{fmt, iter, option, ops, slice, mem};
+    pub use core::{cmp, fmt, iter, option, ops, slice, mem, str};
     pub use collections::{boxed, vec, string};
+    pub mod collections {
+        pub use collections::{btree_map, BTreeMap, VecDeque};
+    }
     pub mod prelude {
         pub use core::prelude as v1;
     }
 }
 
 use std::vec::Vec;
+use std::fmt;
 
 pub mod util;
+pub mod seq;
 mod parser;
+mod options;
+mod segment_ref;
+mod crypto;
+mod signature;
+mod builder;
+pub mod tcp_ao;
+pub mod mptcp;
+pub mod ip;
+pub mod overlap;
+pub mod defrag;
+pub mod stream;
+pub mod conntrack;
+pub mod state;
+pub mod analysis;
+pub mod rtt;
+pub mod window;
+pub mod sack;
+pub mod validate;
+pub mod anomaly;
+pub mod fingerprint;
+pub use options::OptionsIter;
+pub use segment_ref::TcpSegmentRef;
+pub use builder::{TcpSegmentBuilder, TcpBuildError};
+pub use seq::SeqNum;
 use util::{U8ToU16, U8ToU32, U32ToU8, U16ToU8, U32ToU16};
+use mptcp::MptcpOption;
 
 bitflags! {
     /// TCP Control flags, Only 9 bits needed
@@ -55,6 +85,25 @@ bitflags! {
     }
 }
 
+/// Formats the flags the way tcpdump does, e.g. `S.` for a SYN-ACK,
+/// `FP.` for a FIN-PSH-ACK, or `none` when no flag is set
+impl fmt::Display for TcpCTRL {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "none");
+        }
+
+        let letters = [(FIN, 'F'), (SYN, 'S'), (RST, 'R'), (PSH, 'P'), (ACK, '.'),
+                       (URG, 'U'), (ECE, 'E'), (CWR, 'W'), (NS, 'e')];
+        for &(flag, letter) in letters.iter() {
+            if self.contains(flag) {
+                try!(write!(f, "{}", letter));
+            }
+        }
+        Ok(())
+    }
+}
+
 bitflags! {
     /// TCP Options
     flags TcpOptFlags : u8 {
@@ -71,10 +120,23 @@ bitflags! {
         /// Selective Acknowledgement
         const SACK      = 0b00000101,
         /// Timestmp
-        const TIME      = 0b00001000
+        const TIME      = 0b00001000,
+        /// MD5 Signature
+        const MD5SIG    = 0b00010011,
+        /// TCP Authentication Option
+        const AUTH      = 0b00011101,
+        /// Multipath TCP
+        const MPTCP     = 0b00011110,
+        /// TCP Fast Open Cookie
+        const FASTOPEN  = 0b00100010,
+        /// Experimental option, identified by a 16 bit magic number (RFC 6994)
+        const EXPERIMENT= 0b11111110
     }
 }
 
+/// Magic number identifying a Fast Open cookie carried in the experimental option
+pub const TFO_MAGIC : u16 = 0xF989;
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 /// The set of possible TCP options
 pub enum TcpOpts {
@@ -84,33 +146,71 @@ pub enum TcpOpts {
     WindowScale(u8),    // Window scale, length should be 3 (SYN only)
     SAckPermitted,      // Selective ACK permitted (SYN only)
     SAck(Vec<(u32, u32)>),     // Selective ACK (variable length, 1-4 bocks of 32 bit begin/end ptrs)
-    TimeStamp { time: u32, echo: u32 }      // Timestamp and echo of prev timestamp, length should be 10
+    TimeStamp { time: u32, echo: u32 },     // Timestamp and echo of prev timestamp, length should be 10
+    Md5Signature([u8; 16]),                 // RFC 2385 MD5 digest, length should be 18
+    AuthOption { key_id: u8, rnext_key_id: u8, mac: Vec<u8> },  // RFC 5925 TCP-AO, length is 4 + MAC length
+    Mptcp(MptcpOption),                     // RFC 8684 Multipath TCP, length depends on the subtype
+    FastOpenCookie(Vec<u8>),                // RFC 7413 Fast Open cookie, empty when requesting one
+    FastOpenCookieExperimental(Vec<u8>),    // Pre-RFC Fast Open cookie, experimental kind with magic 0xF989
+    Unknown { kind: u8, data: Vec<u8> },    // Any other kind, data excludes the kind and length bytes
+    Padding(Vec<u8>)                        // Bytes following an END option, up to the data offset
 }
 
 impl TcpOpts {
-    fn opt_flag(&self) -> TcpOptFlags {
-        match self {
+    /// The option kind, or None for padding
+    pub fn kind(&self) -> Option<u8> {
+        let flag = match self {
             &TcpOpts::END => END,
             &TcpOpts::NOP => NOP,
             &TcpOpts::MSS(_) => MSS,
             &TcpOpts::WindowScale(_) => SCALE,
             &TcpOpts::SAckPermitted => SACKPERM,
             &TcpOpts::SAck(_) => SACK,
-            &TcpOpts::TimeStamp{time : _ , echo: _} => TIME
+            &TcpOpts::TimeStamp{time : _ , echo: _} => TIME,
+            &TcpOpts::Md5Signature(_) => MD5SIG,
+            &TcpOpts::AuthOption{key_id: _, rnext_key_id: _, mac: _} => AUTH,
+            &TcpOpts::Mptcp(_) => MPTCP,
+            &TcpOpts::FastOpenCookie(_) => FASTOPEN,
+            &TcpOpts::FastOpenCookieExperimental(_) => EXPERIMENT,
+            &TcpOpts::Unknown{kind, data: _} => return Some(kind),
+            &TcpOpts::Padding(_) => return None
+        };
+        Some(flag.bits())
+    }
+
+    /// Length of the option once encoded, kind and length bytes included
+    fn encoded_len(&self) -> usize {
+        match self {
+            &TcpOpts::END | &TcpOpts::NOP => 1,
+            &TcpOpts::MSS(_) => 4,
+            &TcpOpts::WindowScale(_) => 3,
+            &TcpOpts::SAckPermitted => 2,
+            &TcpOpts::SAck(ref ptrs) => ptrs.len() * 8 + 2,
+            &TcpOpts::TimeStamp{time : _, echo: _} => 10,
+            &TcpOpts::Md5Signature(_) => 18,
+            &TcpOpts::AuthOption{key_id: _, rnext_key_id: _, ref mac} => mac.len() + 4,
+            &TcpOpts::Mptcp(ref mptcp) => mptcp.to_bytes().len() + 2,
+            &TcpOpts::FastOpenCookie(ref cookie) => cookie.len() + 2,
+            &TcpOpts::FastOpenCookieExperimental(ref cookie) => cookie.len() + 4,
+            &TcpOpts::Unknown{kind: _, data: ref d} => d.len() + 2,
+            &TcpOpts::Padding(ref p) => p.len()
         }
     }
 }
 
 trait TcpOptStream {
+    fn as_u8_stream(&self) -> Vec<u8>;
     fn as_u16_stream(&self) -> Vec<u16>;
 }
 
 impl TcpOptStream for Vec<TcpOpts> {
-    fn as_u16_stream(&self) -> Vec<u16> {
+    fn as_u8_stream(&self) -> Vec<u8> {
         let mut data = Vec::with_capacity(self.len()*4);    // Easier to allocate a larger buffer than needed; reduce allocations
 
         for opt in self.iter() {
-            data.push(opt.opt_flag().bits());
+            if let Some(kind) = opt.kind() {
+                data.push(kind);
+            }
             match opt {
                 &TcpOpts::END | &TcpOpts::NOP => {},
                 &TcpOpts::MSS(mss) => {
@@ -135,11 +235,45 @@ impl TcpOptStream for Vec<TcpOpts> {
                     data.push(0x0A);
                     data.extend(t.to_u8().iter());
                     data.extend(e.to_u8().iter());
+                },
+                &TcpOpts::Md5Signature(ref digest) => {
+                    data.push(0x12);
+                    data.extend(digest.iter());
+                },
+                &TcpOpts::AuthOption{key_id, rnext_key_id, ref mac} => {
+                    data.push((mac.len() as u8) + 4);
+                    data.push(key_id);
+                    data.push(rnext_key_id);
+                    data.extend(mac.iter());
+                },
+                &TcpOpts::Mptcp(ref mptcp) => {
+                    let body = mptcp.to_bytes();
+                    data.push((body.len() as u8) + 2);
+                    data.extend(body.iter());
+                },
+                &TcpOpts::FastOpenCookie(ref cookie) => {
+                    data.push((cookie.len() as u8) + 2);
+                    data.extend(cookie.iter());
+                },
+                &TcpOpts::FastOpenCookieExperimental(ref cookie) => {
+                    data.push((cookie.len() as u8) + 4);
+                    data.extend(TFO_MAGIC.to_u8().iter());
+                    data.extend(cookie.iter());
+                },
+                &TcpOpts::Unknown{kind: _, data: ref d} => {
+                    data.push((d.len() as u8) + 2);
+                    data.extend(d.iter());
+                },
+                &TcpOpts::Padding(ref p) => {
+                    data.extend(p.iter());
                 }
             }
         }
+        data
+    }
 
-        data.chunks(2).map(|chunk| {
+    fn as_u16_stream(&self) -> Vec<u16> {
+        self.as_u8_stream().chunks(2).map(|chunk| {
             if chunk.len() == 1 {
                 (chunk[0] as u16) << 8
             } else {
@@ -157,14 +291,16 @@ pub struct TcpSegment {
     pub src_port        : u16,              
     /// dest port
     pub dest_port       : u16,              
-    /// sequence number
+    /// sequence number, as `seq()` gives it for serial arithmetic
     pub seq_num         : u32,              
-    /// acknowledge mnumber
+    /// acknowledge mnumber, as `ack()` gives it for serial arithmetic
     pub ack_num         : u32,              
     /// Data offset - in practice, only 4 bits, size of TCP header in 32-bit words
     pub data_off        : u8,               
+    /// Reserved bits between the data offset and the control flags, should be 0
+    pub reserved        : u8,
     /// Control flags 
-    pub ctrl_flags      : u16,              
+    pub ctrl_flags      : TcpCTRL,          
     /// TCP Window size
     pub window          : u16,              
     /// TCP checksum
@@ -177,6 +313,14 @@ pub struct TcpSegment {
     pub data            : Vec<u8>           
 }
 
+/// A pseudo-header covered by the TCP checksum, which depends on the network layer
+pub trait PseudoHeader {
+    /// The pseudo-header as 16-bit words, in network order
+    fn as_u16_stream(&self) -> Vec<u16>;
+    /// Set the length field to the length of the TCP segment
+    fn set_tcp_len(&mut self, len : usize);
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 /// A pseudo-header for an IPv4 Packet
 pub struct IPv4PseudoHeader {
@@ -186,14 +330,77 @@ pub struct IPv4PseudoHeader {
     pub tcp_len     : u16
 }
 
-// TODO: Add InvalidOption enum for better error messages
-#[allow(dead_code)]
+impl PseudoHeader for IPv4PseudoHeader {
+    fn as_u16_stream(&self) -> Vec<u16> {
+        let (src_hi, src_lo) = self.source_addr.to_u16();
+        let (dest_hi, dest_lo) = self.dest_addr.to_u16();
+        [src_hi, src_lo, dest_hi, dest_lo, self.protocol as u16, self.tcp_len].iter().cloned().collect()
+    }
+
+    fn set_tcp_len(&mut self, len : usize) {
+        self.tcp_len = len as u16;
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// A pseudo-header for an IPv6 Packet (RFC 8200 section 8.1)
+pub struct IPv6PseudoHeader {
+    pub source_addr : [u8; 16],
+    pub dest_addr   : [u8; 16],
+    /// Upper-layer packet length
+    pub tcp_len     : u32,
+    /// Next header value of the upper-layer protocol, 6 for TCP
+    pub next_header : u8
+}
+
+impl PseudoHeader for IPv6PseudoHeader {
+    fn as_u16_stream(&self) -> Vec<u16> {
+        let mut words = Vec::with_capacity(20);
+        for addr in [&self.source_addr, &self.dest_addr].iter() {
+            for chunk in addr.chunks(2) {
+                words.push(chunk.iter().to_u16().unwrap());
+            }
+        }
+        let (len_hi, len_lo) = self.tcp_len.to_u16();
+        words.push(len_hi);
+        words.push(len_lo);
+        words.push(0);
+        words.push(self.next_header as u16);
+        words
+    }
+
+    fn set_tcp_len(&mut self, len : usize) {
+        self.tcp_len = len as u32;
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
-enum TcpParseError {
-    InvalidLength,
-    InvalidReserved,
-    InvalidDataOffset,
-    InvalidOption
+/// The reasons a byte stream can fail to parse as a TcpSegment. Each variant
+/// carries the byte offset into the segment at which parsing failed.
+pub enum TcpParseError {
+    /// The segment is shorter than the 20 byte fixed header
+    InvalidLength(usize),
+    /// The reserved bits between the data offset and the flags are non-zero
+    InvalidReserved(usize),
+    /// The data offset is less than the minimum of 5 words
+    InvalidDataOffset(usize),
+    /// The data offset points past the end of the segment
+    DataOffsetOverflow(usize),
+    /// An option is malformed or truncated
+    InvalidOption(usize)
+}
+
+impl TcpParseError {
+    /// The byte offset into the segment at which parsing failed
+    pub fn offset(&self) -> usize {
+        match self {
+            &TcpParseError::InvalidLength(off)      |
+            &TcpParseError::InvalidReserved(off)    |
+            &TcpParseError::InvalidDataOffset(off)  |
+            &TcpParseError::DataOffsetOverflow(off) |
+            &TcpParseError::InvalidOption(off)      => off
+        }
+    }
 }
 
 /// A TCP segment that can be parsed from a byte stream, or manually built.
@@ -203,19 +410,122 @@ enum TcpParseError {
 /// use tcp_parser::TcpSegment;
 /// let data : Vec<u8> = vec![151, 116, 0, 80, 4, 12, 185, 160, 0, 0, 0, 0, 160, 2, 96, 224, 81, 
 ///                           40, 0, 0, 2, 4, 4, 216, 4, 2, 8, 10, 1, 49,10,120,0,0,0,0,1,3,3,7];
-/// let segment = TcpSegment::parse(data);
+/// let segment = TcpSegment::parse(&data);
+/// assert_eq!(TcpSegment::try_parse(&data), Ok(segment));
 /// ```
 impl TcpSegment {
-    /// Parse the given byte stream into a TcpSegment. At the moment, panicks on failure
+    /// Parse the given byte stream into a TcpSegment, accepting what `try_parse`
+    /// accepts. Panics on failure; use `try_parse` to handle malformed segments.
     pub fn parse<T : AsRef<[u8]>>(segment : T) -> TcpSegment {
         match parser::parse(segment.as_ref()) {
-            nom::IResult::Done(_, seg) => seg,
-            r => panic!("{:?}", r)
+            Ok(seg) => seg,
+            Err(e) => panic!("{:?}", e)
+        }
+    }
+
+    /// Parse the given byte stream into a TcpSegment, returning
+    /// the reason and offset of the failure if it is malformed.
+    /// Non-zero reserved bits are rejected as `InvalidReserved`.
+    pub fn try_parse<T : AsRef<[u8]>>(segment : T) -> Result<TcpSegment, TcpParseError> {
+        parser::parse(segment.as_ref())
+    }
+
+    /// Parse the given byte stream as `try_parse` does, but keep non-zero
+    /// reserved bits in `reserved` instead of rejecting the segment.
+    pub fn try_parse_lenient<T : AsRef<[u8]>>(segment : T) -> Result<TcpSegment, TcpParseError> {
+        parser::parse_lenient(segment.as_ref())
+    }
+
+    /// The Fast Open cookie carried by this segment, in either encoding.
+    /// An empty cookie is a request for one.
+    pub fn fast_open_cookie(&self) -> Option<&[u8]> {
+        for opt in self.options.iter() {
+            match opt {
+                &TcpOpts::FastOpenCookie(ref cookie) |
+                &TcpOpts::FastOpenCookieExperimental(ref cookie) => return Some(cookie),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// Whether this is a SYN carrying Fast Open data, i.e. a SYN with
+    /// a Fast Open cookie option and a non-empty payload
+    pub fn has_fast_open_data(&self) -> bool {
+        self.ctrl_flags.contains(SYN) &&
+            self.fast_open_cookie().is_some() &&
+            !self.data.is_empty()
+    }
+
+    /// The sequence number
+    pub fn seq(&self) -> SeqNum {
+        SeqNum(self.seq_num)
+    }
+
+    /// The acknowledgement number
+    pub fn ack(&self) -> SeqNum {
+        SeqNum(self.ack_num)
+    }
+
+    /// Sequence numbers taken up by this segment: its data, plus one each for SYN and FIN
+    pub fn seq_len(&self) -> u32 {
+        let mut len = self.data.len() as u32;
+        if self.ctrl_flags.contains(SYN) {
+            len += 1;
         }
+        if self.ctrl_flags.contains(FIN) {
+            len += 1;
+        }
+        len
+    }
+
+    /// The sequence number following this segment, i.e. the acknowledgement that covers it
+    pub fn end_seq(&self) -> SeqNum {
+        self.seq() + self.seq_len()
+    }
+
+    /// The sequence number relative to the sender's initial sequence number
+    pub fn relative_seq(&self, isn : SeqNum) -> u32 {
+        self.seq().relative_to(isn)
+    }
+
+    /// The acknowledgement number relative to the receiver's initial sequence number
+    pub fn relative_ack(&self, peer_isn : SeqNum) -> u32 {
+        self.ack().relative_to(peer_isn)
+    }
+
+    /// A SYN opening a connection, i.e. without ACK
+    pub fn is_syn(&self) -> bool {
+        self.ctrl_flags.contains(SYN) && !self.ctrl_flags.contains(ACK)
+    }
+
+    /// A SYN-ACK answering a SYN
+    pub fn is_syn_ack(&self) -> bool {
+        self.ctrl_flags.contains(SYN | ACK)
+    }
+
+    /// A bare acknowledgement: only ACK set, and no data
+    pub fn is_pure_ack(&self) -> bool {
+        self.ctrl_flags == ACK && self.data.is_empty()
+    }
+
+    /// Whether the FIN flag is set
+    pub fn is_fin(&self) -> bool {
+        self.ctrl_flags.contains(FIN)
+    }
+
+    /// Whether the RST flag is set
+    pub fn is_rst(&self) -> bool {
+        self.ctrl_flags.contains(RST)
+    }
+
+    /// The 16 bit word holding the data offset, reserved bits and control flags
+    fn offset_flags(&self) -> u16 {
+        ((self.data_off as u16) << 12) | (((self.reserved & 0x07) as u16) << 9) | self.ctrl_flags.bits()
     }
 
     /// Calculte the checksum using the provided pseudo header.
-    pub fn calculate_checksum(&self, pseudo_header : IPv4PseudoHeader) -> u16 {
+    pub fn calculate_checksum<P : PseudoHeader>(&self, pseudo_header : P) -> u16 {
         let add_u16 = |sum: &mut u32, x: u16|{
             *sum = *sum + x as u32;
             if (*sum & 0x8000_0000) != 0 {
@@ -232,15 +542,14 @@ impl TcpSegment {
         let mut checksum : u32 = 0;
         let sum : &mut u32 = &mut checksum;
 
-        add_u32(sum, pseudo_header.source_addr);
-        add_u32(sum, pseudo_header.dest_addr);
-        add_u16(sum, pseudo_header.protocol as u16);
-        add_u16(sum, pseudo_header.tcp_len);
+        for x in pseudo_header.as_u16_stream() {
+            add_u16(sum, x);
+        }
         add_u16(sum, self.src_port);
         add_u16(sum, self.dest_port);
         add_u32(sum, self.seq_num);
         add_u32(sum, self.ack_num);
-        add_u16(sum, ((self.data_off as u16) << 12) | self.ctrl_flags);
+        add_u16(sum, self.offset_flags());
         add_u16(sum, self.window);
         add_u16(sum, self.urg_ptr);
 
@@ -264,6 +573,11 @@ impl TcpSegment {
         !((*sum & 0x0000FFFF) as u16)
     }
 
+    /// Check the checksum field against the one calculated using the provided pseudo header.
+    pub fn verify_checksum<P : PseudoHeader>(&self, pseudo_header : P) -> bool {
+        self.calculate_checksum(pseudo_header) == self.checksum
+    }
+
     /// Create a bytestream from this segment
     pub fn as_bytestream(&self) -> Vec<u8> {
         let mut data = Vec::with_capacity(self.data.len() + (self.data_off as usize)*4);
@@ -271,7 +585,7 @@ impl TcpSegment {
         data.extend(self.dest_port.to_u8().iter());
         data.extend(self.seq_num.to_u8().iter());
         data.extend(self.ack_num.to_u8().iter());
-        data.extend((((self.data_off as u16) << 12) | (self.ctrl_flags & 0x1FF)).to_u8().iter());
+        data.extend(self.offset_flags().to_u8().iter());
         data.extend(self.window.to_u8().iter());
         data.extend(self.checksum.to_u8().iter());
         data.extend(self.urg_ptr.to_u8().iter());
@@ -3,21 +3,29 @@ extern crate pcap;
 use std::env;
 
 use tcp_parser::TcpSegment; 
+use tcp_parser::ip::{self, IpHeader};
+use tcp_parser::defrag::{IPv4Defragmenter, IPv6Defragmenter, DefragConfig};
 
 fn main() {
-    const IPV4_START : u8 = 0x0E;
-    const IPV4_PACKET_TYPE : usize = 0x17;
-    const TCP_PACKET : u8 = 6;
-
     let file = env::args().nth(1).unwrap_or("tests/100_packets.pcap".to_string());
 
     let mut cap = pcap::Capture::from_file(file).unwrap();
+    let mut defrag_v4 = IPv4Defragmenter::new(DefragConfig::default());
+    let mut defrag_v6 = IPv6Defragmenter::new(DefragConfig::default());
     let mut data = Vec::new();
     while let Ok(packet) = cap.next() {
-        if packet[IPV4_PACKET_TYPE] as u8 == TCP_PACKET {
-            let tcp_len = 4 * (packet[0x0E] & 0x0F);
-            let start = IPV4_START + tcp_len; 
-            data.push(packet[start as usize ..].into_iter().cloned().collect::<Vec<u8>>());
+        let now = packet.header.ts.tv_sec as u64 * 1000 + packet.header.ts.tv_usec as u64 / 1000;
+        let datagram = match ip::parse_ethernet(&packet).and_then(|frame| frame.ip()) {
+            Ok(ip_packet) => match ip_packet.header {
+                IpHeader::V4(_) => defrag_v4.push(&ip_packet, now),
+                IpHeader::V6(_) => defrag_v6.push(&ip_packet, now)
+            },
+            Err(_) => continue
+        };
+        if let Some(datagram) = datagram {
+            if let Ok((tcp, _)) = datagram.as_packet().tcp() {
+                data.push(tcp.to_vec());
+            }
         }
     }
 
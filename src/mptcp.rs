@@ -0,0 +1,423 @@
+//! # Multipath TCP
+//! Decoding and encoding of the Multipath TCP option (kind 30, RFC 8684).
+//! The first nibble after the kind and length selects the subtype.
+
+use std::vec::Vec;
+use util::{U8ToU16, U8ToU32, U8ToU64, U16ToU8, U32ToU8, U64ToU8};
+
+/// MP_CAPABLE subtype
+pub const MP_CAPABLE    : u8 = 0x0;
+/// MP_JOIN subtype
+pub const MP_JOIN       : u8 = 0x1;
+/// DSS subtype
+pub const DSS           : u8 = 0x2;
+/// ADD_ADDR subtype
+pub const ADD_ADDR      : u8 = 0x3;
+/// REMOVE_ADDR subtype
+pub const REMOVE_ADDR   : u8 = 0x4;
+/// MP_PRIO subtype
+pub const MP_PRIO       : u8 = 0x5;
+/// MP_FAIL subtype
+pub const MP_FAIL       : u8 = 0x6;
+/// MP_FASTCLOSE subtype
+pub const MP_FASTCLOSE  : u8 = 0x7;
+
+const DSS_DATA_ACK      : u8 = 0x01;
+const DSS_DATA_ACK_8    : u8 = 0x02;
+const DSS_MAPPING       : u8 = 0x04;
+const DSS_DSN_8         : u8 = 0x08;
+const DSS_DATA_FIN      : u8 = 0x10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// A data sequence number or data ACK, which may be sent as 4 or 8 octets
+pub enum DataSeq {
+    Short(u32),
+    Long(u64)
+}
+
+impl DataSeq {
+    /// The value, widened to 64 bits
+    pub fn value(&self) -> u64 {
+        match self {
+            &DataSeq::Short(x) => x as u64,
+            &DataSeq::Long(x) => x
+        }
+    }
+
+    fn is_long(&self) -> bool {
+        match self {
+            &DataSeq::Short(_) => false,
+            &DataSeq::Long(_) => true
+        }
+    }
+
+    fn encode(&self, data : &mut Vec<u8>) {
+        match self {
+            &DataSeq::Short(x) => data.extend(x.to_u8().iter()),
+            &DataSeq::Long(x) => data.extend(x.to_u8().iter())
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// The DSS mapping from subflow sequence space to data sequence space
+pub struct DssMapping {
+    /// Data sequence number of the first mapped byte
+    pub dsn         : DataSeq,
+    /// Subflow sequence number, relative to the subflow's ISN
+    pub subflow_seq : u32,
+    /// Number of bytes covered by the mapping
+    pub data_len    : u16,
+    /// DSS checksum, present only if checksums were negotiated
+    pub checksum    : Option<u16>
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// An address advertised by ADD_ADDR
+pub enum MptcpAddr {
+    V4(u32),
+    V6([u8; 16])
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// The Multipath TCP option subtypes
+pub enum MptcpOption {
+    /// MP_CAPABLE, which carries more fields as the handshake progresses
+    MpCapable {
+        version         : u8,
+        flags           : u8,
+        sender_key      : Option<u64>,
+        receiver_key    : Option<u64>,
+        data_len        : Option<u16>,
+        checksum        : Option<u16>
+    },
+    /// MP_JOIN on a SYN
+    MpJoinSyn { backup: bool, address_id: u8, receiver_token: u32, sender_nonce: u32 },
+    /// MP_JOIN on a SYN/ACK
+    MpJoinSynAck { backup: bool, address_id: u8, sender_hmac: u64, sender_nonce: u32 },
+    /// MP_JOIN on the third ACK, with the 12 reserved bits that follow the subtype
+    MpJoinAck { reserved: u16, sender_hmac: [u8; 20] },
+    /// Data Sequence Signal
+    Dss { data_fin: bool, data_ack: Option<DataSeq>, mapping: Option<DssMapping> },
+    /// ADD_ADDR, with an optional port and a truncated HMAC unless it is an echo
+    AddAddr { echo: bool, address_id: u8, address: MptcpAddr, port: Option<u16>, hmac: Option<u64> },
+    /// REMOVE_ADDR
+    RemoveAddr { address_ids: Vec<u8> },
+    /// MP_PRIO, the address ID is only present in RFC 6824 encodings
+    MpPrio { backup: bool, address_id: Option<u8> },
+    /// MP_FAIL
+    MpFail { dsn: u64 },
+    /// MP_FASTCLOSE
+    MpFastClose { receiver_key: u64 },
+    /// Any other subtype, holding the raw option body starting at the subtype byte
+    Unknown(Vec<u8>)
+}
+
+impl MptcpOption {
+    /// Decode the body of an MPTCP option, i.e. the bytes following the
+    /// kind and length. Returns `None` if the length does not match the subtype.
+    pub fn parse(body : &[u8]) -> Option<MptcpOption> {
+        if body.is_empty() {
+            return None;
+        }
+
+        let subtype = body[0] >> 4;
+        let opt = match subtype {
+            MP_CAPABLE => {
+                if body.len() < 2 {
+                    return None;
+                }
+                let keys = &body[2..];
+                let (sender_key, receiver_key, data_len, checksum) = match keys.len() {
+                    0  => (None, None, None, None),
+                    8  => (Some(read_u64(keys, 0)), None, None, None),
+                    16 => (Some(read_u64(keys, 0)), Some(read_u64(keys, 8)), None, None),
+                    18 => (Some(read_u64(keys, 0)), Some(read_u64(keys, 8)), Some(read_u16(keys, 16)), None),
+                    20 => (Some(read_u64(keys, 0)), Some(read_u64(keys, 8)), Some(read_u16(keys, 16)),
+                           Some(read_u16(keys, 18))),
+                    _  => return None
+                };
+                MptcpOption::MpCapable {
+                    version:        body[0] & 0x0F,
+                    flags:          body[1],
+                    sender_key:     sender_key,
+                    receiver_key:   receiver_key,
+                    data_len:       data_len,
+                    checksum:       checksum
+                }
+            },
+            MP_JOIN => match body.len() {
+                10 => MptcpOption::MpJoinSyn {
+                    backup:         body[0] & 0x01 != 0,
+                    address_id:     body[1],
+                    receiver_token: read_u32(body, 2),
+                    sender_nonce:   read_u32(body, 6)
+                },
+                14 => MptcpOption::MpJoinSynAck {
+                    backup:         body[0] & 0x01 != 0,
+                    address_id:     body[1],
+                    sender_hmac:    read_u64(body, 2),
+                    sender_nonce:   read_u32(body, 10)
+                },
+                22 => {
+                    let mut hmac = [0u8; 20];
+                    hmac.copy_from_slice(&body[2..22]);
+                    MptcpOption::MpJoinAck {
+                        reserved:       ((body[0] & 0x0F) as u16) << 8 | body[1] as u16,
+                        sender_hmac:    hmac
+                    }
+                },
+                _ => return None
+            },
+            DSS => {
+                if body.len() < 2 {
+                    return None;
+                }
+                let flags = body[1];
+                let mut off = 2;
+
+                let data_ack = if flags & DSS_DATA_ACK == 0 {
+                    None
+                } else if flags & DSS_DATA_ACK_8 == 0 {
+                    off += 4;
+                    if body.len() < off { return None; }
+                    Some(DataSeq::Short(read_u32(body, off - 4)))
+                } else {
+                    off += 8;
+                    if body.len() < off { return None; }
+                    Some(DataSeq::Long(read_u64(body, off - 8)))
+                };
+
+                let mapping = if flags & DSS_MAPPING == 0 {
+                    None
+                } else {
+                    let dsn = if flags & DSS_DSN_8 == 0 {
+                        off += 4;
+                        if body.len() < off { return None; }
+                        DataSeq::Short(read_u32(body, off - 4))
+                    } else {
+                        off += 8;
+                        if body.len() < off { return None; }
+                        DataSeq::Long(read_u64(body, off - 8))
+                    };
+                    if body.len() < off + 6 {
+                        return None;
+                    }
+                    let subflow_seq = read_u32(body, off);
+                    let data_len = read_u16(body, off + 4);
+                    off += 6;
+                    let checksum = if body.len() == off + 2 {
+                        off += 2;
+                        Some(read_u16(body, off - 2))
+                    } else {
+                        None
+                    };
+                    Some(DssMapping {
+                        dsn:            dsn,
+                        subflow_seq:    subflow_seq,
+                        data_len:       data_len,
+                        checksum:       checksum
+                    })
+                };
+
+                if off != body.len() {
+                    return None;
+                }
+                MptcpOption::Dss {
+                    data_fin:   flags & DSS_DATA_FIN != 0,
+                    data_ack:   data_ack,
+                    mapping:    mapping
+                }
+            },
+            ADD_ADDR => {
+                let rest = body.len().wrapping_sub(2);
+                let (addr_len, port, hmac) = match rest {
+                    4  => (4, false, false),
+                    6  => (4, true, false),
+                    12 => (4, false, true),
+                    14 => (4, true, true),
+                    16 => (16, false, false),
+                    18 => (16, true, false),
+                    24 => (16, false, true),
+                    26 => (16, true, true),
+                    _  => return None
+                };
+                let address = if addr_len == 4 {
+                    MptcpAddr::V4(read_u32(body, 2))
+                } else {
+                    let mut addr = [0u8; 16];
+                    addr.copy_from_slice(&body[2..18]);
+                    MptcpAddr::V6(addr)
+                };
+                let port_off = 2 + addr_len;
+                let hmac_off = if port { port_off + 2 } else { port_off };
+                MptcpOption::AddAddr {
+                    echo:       body[0] & 0x01 != 0,
+                    address_id: body[1],
+                    address:    address,
+                    port:       if port { Some(read_u16(body, port_off)) } else { None },
+                    hmac:       if hmac { Some(read_u64(body, hmac_off)) } else { None }
+                }
+            },
+            REMOVE_ADDR => {
+                if body.len() < 2 {
+                    return None;
+                }
+                MptcpOption::RemoveAddr { address_ids: body[1..].iter().cloned().collect() }
+            },
+            MP_PRIO => match body.len() {
+                1 => MptcpOption::MpPrio { backup: body[0] & 0x01 != 0, address_id: None },
+                2 => MptcpOption::MpPrio { backup: body[0] & 0x01 != 0, address_id: Some(body[1]) },
+                _ => return None
+            },
+            MP_FAIL => {
+                if body.len() != 10 {
+                    return None;
+                }
+                MptcpOption::MpFail { dsn: read_u64(body, 2) }
+            },
+            MP_FASTCLOSE => {
+                if body.len() != 10 {
+                    return None;
+                }
+                MptcpOption::MpFastClose { receiver_key: read_u64(body, 2) }
+            },
+            _ => MptcpOption::Unknown(body.iter().cloned().collect())
+        };
+        Some(opt)
+    }
+
+    /// The subtype of this option
+    pub fn subtype(&self) -> u8 {
+        match self {
+            &MptcpOption::MpCapable{..} => MP_CAPABLE,
+            &MptcpOption::MpJoinSyn{..} |
+            &MptcpOption::MpJoinSynAck{..} |
+            &MptcpOption::MpJoinAck{..} => MP_JOIN,
+            &MptcpOption::Dss{..} => DSS,
+            &MptcpOption::AddAddr{..} => ADD_ADDR,
+            &MptcpOption::RemoveAddr{..} => REMOVE_ADDR,
+            &MptcpOption::MpPrio{..} => MP_PRIO,
+            &MptcpOption::MpFail{..} => MP_FAIL,
+            &MptcpOption::MpFastClose{..} => MP_FASTCLOSE,
+            &MptcpOption::Unknown(ref body) => body.first().map_or(0, |b| b >> 4)
+        }
+    }
+
+    /// Encode the body of this option, i.e. everything following the kind and length
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(28);
+        let sub = self.subtype() << 4;
+
+        match self {
+            &MptcpOption::MpCapable{version, flags, sender_key, receiver_key, data_len, checksum} => {
+                data.push(sub | (version & 0x0F));
+                data.push(flags);
+                for key in sender_key.iter().chain(receiver_key.iter()) {
+                    data.extend(key.to_u8().iter());
+                }
+                for x in data_len.iter().chain(checksum.iter()) {
+                    data.extend(x.to_u8().iter());
+                }
+            },
+            &MptcpOption::MpJoinSyn{backup, address_id, receiver_token, sender_nonce} => {
+                data.push(sub | backup as u8);
+                data.push(address_id);
+                data.extend(receiver_token.to_u8().iter());
+                data.extend(sender_nonce.to_u8().iter());
+            },
+            &MptcpOption::MpJoinSynAck{backup, address_id, sender_hmac, sender_nonce} => {
+                data.push(sub | backup as u8);
+                data.push(address_id);
+                data.extend(sender_hmac.to_u8().iter());
+                data.extend(sender_nonce.to_u8().iter());
+            },
+            &MptcpOption::MpJoinAck{reserved, ref sender_hmac} => {
+                data.push(sub | (reserved >> 8) as u8 & 0x0F);
+                data.push(reserved as u8);
+                data.extend(sender_hmac.iter());
+            },
+            &MptcpOption::Dss{data_fin, ref data_ack, ref mapping} => {
+                let mut flags = 0;
+                if data_fin {
+                    flags |= DSS_DATA_FIN;
+                }
+                if let &Some(ref ack) = data_ack {
+                    flags |= DSS_DATA_ACK;
+                    if ack.is_long() {
+                        flags |= DSS_DATA_ACK_8;
+                    }
+                }
+                if let &Some(ref map) = mapping {
+                    flags |= DSS_MAPPING;
+                    if map.dsn.is_long() {
+                        flags |= DSS_DSN_8;
+                    }
+                }
+                data.push(sub);
+                data.push(flags);
+                if let &Some(ref ack) = data_ack {
+                    ack.encode(&mut data);
+                }
+                if let &Some(ref map) = mapping {
+                    map.dsn.encode(&mut data);
+                    data.extend(map.subflow_seq.to_u8().iter());
+                    data.extend(map.data_len.to_u8().iter());
+                    if let Some(checksum) = map.checksum {
+                        data.extend(checksum.to_u8().iter());
+                    }
+                }
+            },
+            &MptcpOption::AddAddr{echo, address_id, ref address, port, hmac} => {
+                data.push(sub | echo as u8);
+                data.push(address_id);
+                match address {
+                    &MptcpAddr::V4(addr) => data.extend(addr.to_u8().iter()),
+                    &MptcpAddr::V6(ref addr) => data.extend(addr.iter())
+                }
+                if let Some(port) = port {
+                    data.extend(port.to_u8().iter());
+                }
+                if let Some(hmac) = hmac {
+                    data.extend(hmac.to_u8().iter());
+                }
+            },
+            &MptcpOption::RemoveAddr{ref address_ids} => {
+                data.push(sub);
+                data.extend(address_ids.iter());
+            },
+            &MptcpOption::MpPrio{backup, address_id} => {
+                data.push(sub | backup as u8);
+                if let Some(id) = address_id {
+                    data.push(id);
+                }
+            },
+            &MptcpOption::MpFail{dsn} => {
+                data.push(sub);
+                data.push(0);
+                data.extend(dsn.to_u8().iter());
+            },
+            &MptcpOption::MpFastClose{receiver_key} => {
+                data.push(sub);
+                data.push(0);
+                data.extend(receiver_key.to_u8().iter());
+            },
+            &MptcpOption::Unknown(ref body) => {
+                data.extend(body.iter());
+            }
+        }
+        data
+    }
+}
+
+fn read_u16(data : &[u8], off : usize) -> u16 {
+    data[off..off + 2].iter().to_u16().unwrap()
+}
+
+fn read_u32(data : &[u8], off : usize) -> u32 {
+    data[off..off + 4].iter().to_u32().unwrap()
+}
+
+fn read_u64(data : &[u8], off : usize) -> u64 {
+    data[off..off + 8].iter().to_u64().unwrap()
+}
@@ -0,0 +1,70 @@
+use super::TcpOpts;
+use parser;
+
+/// An iterator over an options area, parsing each option on demand.
+///
+/// Options are walked as generic kind/length pairs, so unrecognised kinds
+/// are yielded as `TcpOpts::Unknown` rather than failing the segment.
+/// Iteration stops at the END option; any bytes after it are yielded
+/// as a single `TcpOpts::Padding` so the segment can be rebuilt exactly.
+///
+/// # Example
+/// ```rust
+/// use tcp_parser::{OptionsIter, TcpOpts};
+/// let opts = [1, 1, 253, 4, 0xde, 0xad, 0, 0];
+/// let parsed : Vec<TcpOpts> = OptionsIter::new(&opts).collect();
+/// assert_eq!(parsed, vec![
+///     TcpOpts::NOP,
+///     TcpOpts::NOP,
+///     TcpOpts::Unknown { kind: 253, data: vec![0xde, 0xad] },
+///     TcpOpts::END,
+///     TcpOpts::Padding(vec![0])
+/// ]);
+/// ```
+#[derive(Clone, Debug)]
+pub struct OptionsIter<'a> {
+    data    : &'a [u8],
+    ended   : bool
+}
+
+impl<'a> OptionsIter<'a> {
+    /// Iterate over the given options area, i.e. the bytes between
+    /// the fixed header and the data offset
+    pub fn new(data : &'a [u8]) -> OptionsIter<'a> {
+        OptionsIter {
+            data    : data,
+            ended   : false
+        }
+    }
+
+    /// The bytes that have not been consumed yet. Once iteration has
+    /// finished, this is only non-empty if an option was malformed.
+    pub fn remaining(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+impl<'a> Iterator for OptionsIter<'a> {
+    type Item = TcpOpts;
+
+    fn next(&mut self) -> Option<TcpOpts> {
+        if self.data.is_empty() {
+            return None;
+        }
+
+        if self.ended {
+            let padding = self.data.iter().cloned().collect();
+            self.data = &[];
+            return Some(TcpOpts::Padding(padding));
+        }
+
+        match parser::option(self.data) {
+            Some((opt, rest)) => {
+                self.ended = opt == TcpOpts::END;
+                self.data = rest;
+                Some(opt)
+            },
+            None => None
+        }
+    }
+}
@@ -0,0 +1,50 @@
+//! # Overlap Policies
+//! When two IP fragments or two TCP segments carry different bytes for the same
+//! offsets, the receiving stack decides which to keep, and stacks disagree. The
+//! policies here are the target-based reassembly policies catalogued by Novak and
+//! Sturges, shared by the defragmenters and the stream reassembler.
+//!
+//! # Example
+//! ```rust
+//! use tcp_parser::overlap::OverlapPolicy;
+//! // New data starting before old data that it also extends past
+//! assert!(OverlapPolicy::Windows.new_wins((0, 16), (8, 16)));
+//! assert!(!OverlapPolicy::First.new_wins((0, 16), (8, 16)));
+//! ```
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// How bytes carried by more than one fragment or segment are resolved. Each
+/// variant says when newly received data replaces the overlapping bytes of
+/// data received earlier.
+pub enum OverlapPolicy {
+    /// Never: the data received first wins
+    First,
+    /// Always: the data received last wins
+    Last,
+    /// When the new data starts before the old
+    Bsd,
+    /// When the new data starts at or before the old
+    Linux,
+    /// When the new data starts before the old and ends at or after its end
+    Windows,
+    /// When the new data ends after the old
+    Solaris,
+    /// When the new data starts before or ends after the old
+    HpUx
+}
+
+impl OverlapPolicy {
+    /// Whether new data spanning `[new.0, new.1)` replaces the overlapping bytes
+    /// of earlier data that spanned `[old.0, old.1)`
+    pub fn new_wins(&self, new : (u64, u64), old : (u64, u64)) -> bool {
+        match *self {
+            OverlapPolicy::First   => false,
+            OverlapPolicy::Last    => true,
+            OverlapPolicy::Bsd     => new.0 < old.0,
+            OverlapPolicy::Linux   => new.0 <= old.0,
+            OverlapPolicy::Windows => new.0 < old.0 && new.1 >= old.1,
+            OverlapPolicy::Solaris => new.1 > old.1,
+            OverlapPolicy::HpUx    => new.0 < old.0 || new.1 > old.1
+        }
+    }
+}
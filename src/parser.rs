@@ -1,35 +1,31 @@
-use nom::{IResult, rest, be_u8, be_u16, be_u32, eof};
+use nom::{IResult, be_u8, be_u16, be_u32};
 use nom::IResult::*;
-use super::{TcpOpts, TcpSegment, TcpParseError, END, NOP, MSS, SCALE, SACKPERM, SACK, TIME};
+use super::{TcpOpts, TcpSegment, TcpParseError, TcpCTRL, OptionsIter, END, NOP, MSS, SCALE, SACKPERM, SACK, TIME, MD5SIG, AUTH, MPTCP, FASTOPEN, EXPERIMENT, TFO_MAGIC};
+use mptcp::MptcpOption;
 use std::vec::Vec;
 
+/// Length of the fixed portion of the TCP header, in bytes
+pub const HEADER_LEN    : usize = 20;
+/// Byte holding the data offset and reserved bits
+pub const DATA_OFF_BYTE : usize = 12;
+
 #[derive(Debug)]
 struct DataOffsetFlags{
     data_off: u8,
+    reserved: u8,
     flags   : u16
 }
 named!(data_flags<DataOffsetFlags>, chain!(
-    data : bits!(separated_pair!(take_bits!(u8, 4), tag_bits!(u8, 3, 0b000), take_bits!(u8, 1))) ~
+    data : bits!(pair!(take_bits!(u8, 4), pair!(take_bits!(u8, 3), take_bits!(u8, 1)))) ~
     flags: be_u8,
     ||{
         DataOffsetFlags{
             data_off    : data.0,
-            flags       : ((data.1 as u16) << 8) | (flags as u16)
+            reserved    : (data.1).0,
+            flags       : (((data.1).1 as u16) << 8) | (flags as u16)
         }
     }
 ));
-named!(parse_end<TcpOpts>, chain!(
-            tag!(&[END.bits()]),
-    ||{
-        TcpOpts::END
-    }
-));
-named!(parse_nop<TcpOpts>, chain!(
-            tag!(&[NOP.bits()]),
-    ||{
-        TcpOpts::NOP
-    }
-));
 named!(parse_mss<TcpOpts>, chain!(
             tag!(&[MSS.bits()]) ~
             tag!(&[0x04])       ~
@@ -56,7 +52,7 @@ named!(parse_sackperm<TcpOpts>, chain!(
 named!(parse_sack<TcpOpts>, chain!(
             tag!(&[SACK.bits()]) ~
     len:    be_u8                ~
-    data:   count!(pair!(be_u32, be_u32), ((len-2)/8) as usize),
+    data:   cond_reduce!(len >= 2, count!(pair!(be_u32, be_u32), ((len-2)/8) as usize)),
     ||{
         TcpOpts::SAck(data)
     }
@@ -72,24 +68,106 @@ named!(parse_time<TcpOpts>, chain!(
         }
     }
 ));
-
-named!(parse_opts<Vec<TcpOpts> >, chain!(
-    data: many0!(alt!(
-                        parse_end       |
-                        parse_nop       |
-                        parse_mss       |
-                        parse_scale     |
-                        parse_sackperm  |
-                        parse_sack      |
-                        parse_time
-                     ))    ~
-          eof,
+named!(parse_md5<TcpOpts>, chain!(
+            tag!(&[MD5SIG.bits()])  ~
+            tag!(&[0x12])           ~
+    digest: take!(16)               ,
+    ||{
+        let mut sig = [0u8; 16];
+        sig.copy_from_slice(digest);
+        TcpOpts::Md5Signature(sig)
+    }
+));
+named!(parse_auth<TcpOpts>, chain!(
+                    tag!(&[AUTH.bits()])    ~
+    len:            be_u8                   ~
+    key_id:         be_u8                   ~
+    rnext_key_id:   be_u8                   ~
+    mac:            cond_reduce!(len >= 4, take!((len - 4) as usize)),
+    ||{
+        TcpOpts::AuthOption {
+            key_id:         key_id,
+            rnext_key_id:   rnext_key_id,
+            mac:            mac.iter().cloned().collect()
+        }
+    }
+));
+named!(parse_fastopen<TcpOpts>, chain!(
+            tag!(&[FASTOPEN.bits()])    ~
+    len:    be_u8                       ~
+    cookie: take!((len - 2) as usize)   ,
+    ||{
+        TcpOpts::FastOpenCookie(cookie.iter().cloned().collect())
+    }
+));
+named!(parse_fastopen_exp<TcpOpts>, chain!(
+            tag!(&[EXPERIMENT.bits()])  ~
+    len:    be_u8                       ~
+            tag!(&[(TFO_MAGIC >> 8) as u8, TFO_MAGIC as u8]) ~
+    cookie: take!((len - 4) as usize)   ,
     ||{
-        data
+        TcpOpts::FastOpenCookieExperimental(cookie.iter().cloned().collect())
     }
 ));
 
-named!(pub parse<&[u8], TcpSegment>, chain!(
+/// Whether an experimental option carries the Fast Open magic number
+fn is_fastopen_exp(tlv: &[u8]) -> bool {
+    tlv.len() >= 4 && ((tlv[2] as u16) << 8 | tlv[3] as u16) == TFO_MAGIC
+}
+
+/// Parse a single option off the front of the options area, returning the
+/// option and the remaining input. Kinds without a dedicated variant are
+/// returned as `TcpOpts::Unknown`. Returns `None` if the option is truncated
+/// or its length does not match its kind.
+pub fn option(input: &[u8]) -> Option<(TcpOpts, &[u8])> {
+    let kind = match input.first() {
+        Some(&kind) => kind,
+        None        => return None
+    };
+
+    if kind == END.bits() {
+        return Some((TcpOpts::END, &input[1..]));
+    }
+    if kind == NOP.bits() {
+        return Some((TcpOpts::NOP, &input[1..]));
+    }
+
+    if input.len() < 2 {
+        return None;
+    }
+    let len = input[1] as usize;
+    if len < 2 || len > input.len() {
+        return None;
+    }
+    let (tlv, rest) = input.split_at(len);
+
+    let parsed = match kind {
+        k if k == MSS.bits()      => parse_mss(tlv),
+        k if k == SCALE.bits()    => parse_scale(tlv),
+        k if k == SACKPERM.bits() => parse_sackperm(tlv),
+        k if k == SACK.bits()     => parse_sack(tlv),
+        k if k == TIME.bits()     => parse_time(tlv),
+        k if k == MD5SIG.bits()   => parse_md5(tlv),
+        k if k == AUTH.bits()     => parse_auth(tlv),
+        k if k == MPTCP.bits()    => match MptcpOption::parse(&tlv[2..]) {
+            Some(mptcp) => Done(&tlv[len..], TcpOpts::Mptcp(mptcp)),
+            None        => return None
+        },
+        k if k == FASTOPEN.bits() => parse_fastopen(tlv),
+        k if k == EXPERIMENT.bits() && is_fastopen_exp(tlv) => parse_fastopen_exp(tlv),
+        _ => Done(&tlv[len..], TcpOpts::Unknown {
+            kind: kind,
+            data: tlv[2..].iter().cloned().collect()
+        })
+    };
+
+    match parsed {
+        Done(remaining, opt) if remaining.is_empty() => Some((opt, rest)),
+        _ => None
+    }
+}
+
+named!(header<&[u8], TcpSegment>, chain!(
         src_port:       be_u16  ~
         dest_port:      be_u16  ~
         seq_num:        be_u32  ~
@@ -97,9 +175,7 @@ named!(pub parse<&[u8], TcpSegment>, chain!(
         offset_flags:   data_flags ~
         window:         be_u16  ~
         checksum:       be_u16  ~
-        urg_ptr:        be_u16  ~
-        options:        flat_map!(take!((4*offset_flags.data_off as usize) - 20), parse_opts)             ~
-        data:           rest,
+        urg_ptr:        be_u16  ,
         ||{
             TcpSegment{
                 src_port:       src_port,
@@ -107,12 +183,85 @@ named!(pub parse<&[u8], TcpSegment>, chain!(
                 seq_num:        seq_num,
                 ack_num:        ack_num,
                 data_off:       offset_flags.data_off,  
-                ctrl_flags:     offset_flags.flags,
+                reserved:       offset_flags.reserved,
+                ctrl_flags:     TcpCTRL::from_bits_truncate(offset_flags.flags),
                 window:         window,
                 checksum:       checksum,
                 urg_ptr:        urg_ptr,
-                options:        options,
-                data:           data.iter().cloned().collect()
+                options:        Vec::new(),
+                data:           Vec::new()
             }
         }
 ));
+
+/// Check the fixed header before handing it to nom, so that a bad data offset
+/// is reported instead of underflowing the options length. Returns the header length.
+pub fn check_layout(input: &[u8]) -> Result<usize, TcpParseError> {
+    if input.len() < HEADER_LEN {
+        return Err(TcpParseError::InvalidLength(input.len()));
+    }
+
+    let data_off = (input[DATA_OFF_BYTE] >> 4) as usize;
+    if data_off < 5 {
+        return Err(TcpParseError::InvalidDataOffset(DATA_OFF_BYTE));
+    }
+    if 4 * data_off > input.len() {
+        return Err(TcpParseError::DataOffsetOverflow(DATA_OFF_BYTE));
+    }
+
+    Ok(4 * data_off)
+}
+
+/// Check the fixed header as `check_layout` does, also rejecting non-zero reserved bits
+pub fn check_header(input: &[u8]) -> Result<usize, TcpParseError> {
+    let header_len = try!(check_layout(input));
+    if input[DATA_OFF_BYTE] & 0x0E != 0 {
+        return Err(TcpParseError::InvalidReserved(DATA_OFF_BYTE));
+    }
+    Ok(header_len)
+}
+
+/// Walk the options area, passing each option to `f` and reporting
+/// the offset of the first option that could not be parsed
+pub fn walk_options<F : FnMut(TcpOpts)>(input: &[u8], mut f: F) -> Result<(), TcpParseError> {
+    let mut iter = OptionsIter::new(input);
+    while let Some(opt) = iter.next() {
+        f(opt);
+    }
+
+    if iter.remaining().is_empty() {
+        Ok(())
+    } else {
+        Err(TcpParseError::InvalidOption(HEADER_LEN + input.len() - iter.remaining().len()))
+    }
+}
+
+/// Parse the options area into a list of options
+fn options(input: &[u8]) -> Result<Vec<TcpOpts>, TcpParseError> {
+    let mut opts = Vec::new();
+    try!(walk_options(input, |opt| opts.push(opt)));
+    Ok(opts)
+}
+
+/// Parse a TCP segment, returning the reason and offset of the failure if it is malformed
+pub fn parse(input: &[u8]) -> Result<TcpSegment, TcpParseError> {
+    let header_len = try!(check_header(input));
+    parse_segment(input, header_len)
+}
+
+/// Parse a TCP segment as `parse` does, keeping non-zero reserved bits instead of rejecting them
+pub fn parse_lenient(input: &[u8]) -> Result<TcpSegment, TcpParseError> {
+    let header_len = try!(check_layout(input));
+    parse_segment(input, header_len)
+}
+
+fn parse_segment(input: &[u8], header_len: usize) -> Result<TcpSegment, TcpParseError> {
+    let mut segment = match header(input) {
+        Done(_, segment) => segment,
+        _ => return Err(TcpParseError::InvalidLength(input.len()))
+    };
+    segment.options = try!(options(&input[HEADER_LEN..header_len]));
+    segment.data = input[header_len..].iter().cloned().collect();
+
+    Ok(segment)
+}
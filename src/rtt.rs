@@ -0,0 +1,298 @@
+//! # Round-Trip Time Estimation
+//! Measures the round-trip times of a connection from the capture, in milliseconds.
+//! The RTT of a direction is the time from a segment sent in it until the reply
+//! that covers it, and is sampled two ways: by matching the TSval of a segment with
+//! the first TSecr that echoes it, and by matching data with the first ACK that
+//! covers it. Following Karn's algorithm, data that was sent more than once gives
+//! no ACK sample. Samples feed the smoothed RTT and RTT variation of RFC 6298, from
+//! timestamps alone once a direction has timestamp samples.
+//!
+//! # Example
+//! ```rust
+//! use tcp_parser::{TcpSegment, TcpOpts};
+//! use tcp_parser::stream::Direction;
+//! use tcp_parser::rtt::RttEstimator;
+//! let mut rtt = RttEstimator::new();
+//! let mut request = TcpSegment::parse(&[0x30, 0x39, 0, 80, 0, 0, 0, 1, 0, 0, 0, 1, 0x50, 0x18, 1, 0, 0, 0, 0, 0]);
+//! request.data = b"GET /".to_vec();
+//! let mut reply = TcpSegment::parse(&[0, 80, 0x30, 0x39, 0, 0, 0, 1, 0, 0, 0, 6, 0x50, 0x10, 1, 0, 0, 0, 0, 0]);
+//! rtt.push(Direction::ClientToServer, &request, 1000);
+//! rtt.push(Direction::ServerToClient, &reply, 1040);
+//! assert_eq!(rtt.samples(Direction::ClientToServer)[0].rtt, 40);
+//! assert_eq!(rtt.srtt(Direction::ClientToServer), Some(40.0));
+//! assert_eq!(rtt.rttvar(Direction::ClientToServer), Some(20.0));
+//! ```
+
+use std::vec::Vec;
+use std::collections::VecDeque;
+use super::{TcpSegment, TcpOpts, SeqNum, ACK};
+use stream::Direction;
+
+/// Weight of a new sample in the smoothed RTT
+const ALPHA : f64 = 1.0 / 8.0;
+/// Weight of a new sample in the RTT variation
+const BETA  : f64 = 1.0 / 4.0;
+/// Lower bound of the retransmission timeout, in milliseconds
+const MIN_RTO : f64 = 1000.0;
+/// Most TSvals, and runs of data, awaiting a reply in one direction. Beyond it the
+/// oldest are dropped, e.g. when the capture only has one side of the connection.
+const MAX_PENDING : usize = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// How an RTT sample was taken
+pub enum RttSource {
+    /// A TSecr echoing a TSval
+    Timestamp,
+    /// An ACK covering data
+    Ack
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// One measured round trip
+pub struct RttSample {
+    /// When the reply was seen
+    pub time    : u64,
+    pub rtt     : u64,
+    pub source  : RttSource
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// The distribution of the samples of a direction
+pub struct RttSummary {
+    pub count   : usize,
+    pub min     : u64,
+    pub max     : u64,
+    pub p50     : u64,
+    pub p90     : u64,
+    pub p99     : u64
+}
+
+/// Data sent and not yet acknowledged
+struct Outstanding {
+    end             : SeqNum,
+    sent            : u64,
+    retransmitted   : bool
+}
+
+/// Measurements of one direction
+struct Flow {
+    /// The TSvals not yet echoed and when each was first sent, oldest first
+    tsvals      : VecDeque<(u32, u64)>,
+    /// Ordered by sequence number, as only data beyond what was sent is added
+    outstanding : VecDeque<Outstanding>,
+    /// The sequence number after the highest data sent
+    next_seq    : Option<SeqNum>,
+    samples     : Vec<RttSample>,
+    srtt        : Option<f64>,
+    rttvar      : f64,
+    timestamps  : bool
+}
+
+impl Flow {
+    fn new() -> Flow {
+        Flow {
+            tsvals      : VecDeque::new(),
+            outstanding : VecDeque::new(),
+            next_seq    : None,
+            samples     : Vec::new(),
+            srtt        : None,
+            rttvar      : 0.0,
+            timestamps  : false
+        }
+    }
+
+    /// Data sent at time `now`
+    fn sent(&mut self, segment : &TcpSegment, now : u64) {
+        let (seq, end) = (segment.seq(), segment.end_seq());
+        let retransmitted = match self.next_seq {
+            Some(next) => seq.lt(next),
+            None => false
+        };
+        if retransmitted {
+            for data in self.outstanding.iter_mut().rev().take_while(|data| data.end.gt(seq)) {
+                data.retransmitted = true;
+            }
+        }
+        if self.next_seq.map_or(true, |next| end.gt(next)) {
+            self.next_seq = Some(end);
+            if self.outstanding.len() == MAX_PENDING {
+                self.outstanding.pop_front();
+            }
+            self.outstanding.push_back(Outstanding {
+                end             : end,
+                sent            : now,
+                retransmitted   : retransmitted
+            });
+        }
+    }
+
+    /// A TSval sent at time `now`. Only the first segment to carry a TSval times it.
+    fn stamped(&mut self, tsval : u32, now : u64) {
+        if self.tsvals.back().map_or(false, |&(last, _)| SeqNum(tsval).le(SeqNum(last))) {
+            return;
+        }
+        if self.tsvals.len() == MAX_PENDING {
+            self.tsvals.pop_front();
+        }
+        self.tsvals.push_back((tsval, now));
+    }
+
+    /// The peer acknowledged up to `ack` at time `now`: sample the latest data it covers
+    fn acknowledged(&mut self, ack : SeqNum, now : u64) {
+        let mut latest = None;
+        while self.outstanding.front().map_or(false, |data| data.end.le(ack)) {
+            latest = self.outstanding.pop_front();
+        }
+        if let Some(Outstanding { sent, retransmitted: false, .. }) = latest {
+            self.sample(now, now.saturating_sub(sent), RttSource::Ack);
+        }
+    }
+
+    /// The peer echoed `echo` at time `now`, which also covers the TSvals before it
+    fn echoed(&mut self, echo : u32, now : u64) {
+        let mut sent = None;
+        while let Some(&(tsval, time)) = self.tsvals.front() {
+            if SeqNum(tsval).gt(SeqNum(echo)) {
+                break;
+            }
+            if tsval == echo {
+                sent = Some(time);
+            }
+            self.tsvals.pop_front();
+        }
+        let sent = match sent {
+            Some(sent) => sent,
+            None => return
+        };
+        self.timestamps = true;
+        self.sample(now, now.saturating_sub(sent), RttSource::Timestamp);
+    }
+
+    /// Record a sample, updating the estimate as RFC 6298 section 2 does
+    fn sample(&mut self, now : u64, rtt : u64, source : RttSource) {
+        self.samples.push(RttSample {
+            time    : now,
+            rtt     : rtt,
+            source  : source
+        });
+        if self.timestamps && source == RttSource::Ack {
+            return;
+        }
+
+        let r = rtt as f64;
+        self.srtt = Some(match self.srtt {
+            None => {
+                self.rttvar = r / 2.0;
+                r
+            },
+            Some(srtt) => {
+                let diff = if srtt > r { srtt - r } else { r - srtt };
+                self.rttvar = (1.0 - BETA) * self.rttvar + BETA * diff;
+                (1.0 - ALPHA) * srtt + ALPHA * r
+            }
+        });
+    }
+}
+
+/// Estimates the round-trip times of both directions of one connection
+pub struct RttEstimator {
+    flows : [Flow; 2]
+}
+
+impl Default for RttEstimator {
+    fn default() -> RttEstimator {
+        RttEstimator::new()
+    }
+}
+
+impl RttEstimator {
+    pub fn new() -> RttEstimator {
+        RttEstimator {
+            flows : [Flow::new(), Flow::new()]
+        }
+    }
+
+    /// Feed a segment sent in the given direction at time `now`, in milliseconds
+    pub fn push(&mut self, direction : Direction, segment : &TcpSegment, now : u64) {
+        let reverse = direction.reverse().index();
+        let timestamp = segment.options.iter().filter_map(|opt| match opt {
+            &TcpOpts::TimeStamp { time, echo } => Some((time, echo)),
+            _ => None
+        }).next();
+
+        // TSecr is only meaningful with the ACK flag (RFC 7323 section 3.2)
+        if segment.ctrl_flags.contains(ACK) {
+            if let Some((_, echo)) = timestamp {
+                self.flows[reverse].echoed(echo, now);
+            }
+            self.flows[reverse].acknowledged(segment.ack(), now);
+        }
+
+        let flow = &mut self.flows[direction.index()];
+        if let Some((time, _)) = timestamp {
+            flow.stamped(time, now);
+        }
+        if segment.seq_len() > 0 {
+            flow.sent(segment, now);
+        }
+    }
+
+    /// Samples of a direction, in the order they were taken
+    pub fn samples(&self, direction : Direction) -> &[RttSample] {
+        &self.flows[direction.index()].samples
+    }
+
+    /// The smoothed RTT (SRTT) of a direction, once it has a sample
+    pub fn srtt(&self, direction : Direction) -> Option<f64> {
+        self.flows[direction.index()].srtt
+    }
+
+    /// The RTT variation (RTTVAR) of a direction, once it has a sample
+    pub fn rttvar(&self, direction : Direction) -> Option<f64> {
+        let flow = &self.flows[direction.index()];
+        flow.srtt.map(|_| flow.rttvar)
+    }
+
+    /// The retransmission timeout a sender in this direction would use, at least one second
+    pub fn rto(&self, direction : Direction) -> Option<f64> {
+        let flow = &self.flows[direction.index()];
+        flow.srtt.map(|srtt| {
+            let rto = srtt + 4.0 * flow.rttvar;
+            if rto < MIN_RTO { MIN_RTO } else { rto }
+        })
+    }
+
+    /// The sample at or below which `percent` percent of the samples of a direction fall
+    pub fn percentile(&self, direction : Direction, percent : u32) -> Option<u64> {
+        let mut rtts : Vec<u64> = self.samples(direction).iter().map(|sample| sample.rtt).collect();
+        rtts.sort();
+        percentile(&rtts, percent)
+    }
+
+    /// Minimum, maximum and percentiles of the samples of a direction
+    pub fn summary(&self, direction : Direction) -> Option<RttSummary> {
+        let mut rtts : Vec<u64> = self.samples(direction).iter().map(|sample| sample.rtt).collect();
+        rtts.sort();
+        if rtts.is_empty() {
+            return None;
+        }
+        Some(RttSummary {
+            count   : rtts.len(),
+            min     : rtts[0],
+            max     : rtts[rtts.len() - 1],
+            p50     : percentile(&rtts, 50).unwrap(),
+            p90     : percentile(&rtts, 90).unwrap(),
+            p99     : percentile(&rtts, 99).unwrap()
+        })
+    }
+}
+
+/// Nearest-rank percentile of sorted values
+fn percentile(sorted : &[u64], percent : u32) -> Option<u64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (percent as usize * sorted.len() + 99) / 100;
+    let rank = if rank == 0 { 1 } else if rank > sorted.len() { sorted.len() } else { rank };
+    Some(sorted[rank - 1])
+}
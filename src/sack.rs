@@ -0,0 +1,247 @@
+//! # SACK Scoreboard
+//! Rebuilds, for each direction of a connection, what the receiver reported having
+//! received: the cumulative ACK, plus the ranges above it that SACK blocks (RFC 2018)
+//! reported, merged across ACKs. A first SACK block below the cumulative ACK, or
+//! within the second block, reports data received twice (D-SACK, RFC 2883) and is
+//! kept apart. The sender bytes that were missing below a SACKed range at any point
+//! are remembered as holes, which shows where data was lost.
+//!
+//! Ranges are reported as sequence numbers `[left, right)` of the direction the data
+//! was sent in, which is the opposite of the ACKs carrying them.
+//!
+//! # Example
+//! ```rust
+//! use tcp_parser::{TcpSegment, TcpOpts, SeqNum};
+//! use tcp_parser::stream::Direction;
+//! use tcp_parser::sack::SackScoreboard;
+//! let mut scoreboard = SackScoreboard::new();
+//! let mut ack = TcpSegment::parse(&[0, 80, 0x30, 0x39, 0, 0, 0, 1, 0, 0, 0x03, 0xE8, 0x50, 0x10, 1, 0, 0, 0, 0, 0]);
+//! ack.options = vec![TcpOpts::SAck(vec![(2000, 3000)])];
+//! scoreboard.push(Direction::ServerToClient, &ack);
+//! assert_eq!(scoreboard.holes(Direction::ClientToServer), vec![(SeqNum(1000), SeqNum(2000))]);
+//! ```
+
+use std::vec::Vec;
+use std::cmp;
+use super::{TcpSegment, TcpOpts, SeqNum, ACK};
+use stream::Direction;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// What an ACK revealed about the data of the opposite direction
+pub enum SackEvent {
+    /// A D-SACK block: the receiver got these bytes more than once
+    DuplicateSack {
+        left    : SeqNum,
+        right   : SeqNum
+    },
+    /// These bytes are missing below data the receiver SACKed, for the first time
+    Hole {
+        left    : SeqNum,
+        right   : SeqNum
+    },
+    /// A SACK block that ends before it starts, or that is below the cumulative ACK
+    /// without being a D-SACK block
+    InvalidBlock {
+        left    : SeqNum,
+        right   : SeqNum
+    }
+}
+
+/// Remove the covered parts of `range` from sorted disjoint ranges, returning the rest
+fn uncovered(ranges : &[(u64, u64)], range : (u64, u64)) -> Vec<(u64, u64)> {
+    let mut parts = Vec::new();
+    let mut cursor = range.0;
+    for &(start, end) in ranges.iter() {
+        if end <= cursor {
+            continue;
+        }
+        if start >= range.1 {
+            break;
+        }
+        if start > cursor {
+            parts.push((cursor, start));
+        }
+        cursor = end;
+    }
+    if cursor < range.1 {
+        parts.push((cursor, range.1));
+    }
+    parts
+}
+
+/// Add a range to sorted disjoint ranges, merging those it overlaps or touches
+fn merge(ranges : &mut Vec<(u64, u64)>, range : (u64, u64)) {
+    ranges.push(range);
+    ranges.sort();
+    let mut merged : Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+    for &(start, end) in ranges.iter() {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => {
+                last.1 = cmp::max(last.1, end);
+                continue;
+            },
+            _ => {}
+        }
+        merged.push((start, end));
+    }
+    *ranges = merged;
+}
+
+/// The receiver's view of the data of one direction. Ranges are kept as offsets
+/// from the first cumulative ACK, so that they do not wrap.
+struct Board {
+    /// The first cumulative ACK seen
+    base    : Option<SeqNum>,
+    /// Offset of the cumulative ACK
+    acked   : u64,
+    sacked  : Vec<(u64, u64)>,
+    holes   : Vec<(u64, u64)>,
+    dsacks  : Vec<(SeqNum, SeqNum)>
+}
+
+impl Board {
+    fn new() -> Board {
+        Board {
+            base    : None,
+            acked   : 0,
+            sacked  : Vec::new(),
+            holes   : Vec::new(),
+            dsacks  : Vec::new()
+        }
+    }
+
+    /// Offset of a sequence number, unwrapped around the cumulative ACK
+    fn offset(&self, seq : SeqNum) -> i64 {
+        let acked = self.base.unwrap() + self.acked as u32;
+        self.acked as i64 + (seq - acked) as i64
+    }
+
+    fn seq(&self, offset : u64) -> SeqNum {
+        self.base.unwrap() + offset as u32
+    }
+
+    fn ranges(&self, ranges : &[(u64, u64)]) -> Vec<(SeqNum, SeqNum)> {
+        ranges.iter().map(|&(start, end)| (self.seq(start), self.seq(end))).collect()
+    }
+
+    fn ack(&mut self, ack : SeqNum, blocks : &[(u32, u32)], events : &mut Vec<SackEvent>) {
+        if self.base.is_none() {
+            self.base = Some(ack);
+        }
+        let offset = self.offset(ack);
+        if offset > self.acked as i64 {
+            self.acked = offset as u64;
+        }
+
+        let mut blocks = blocks.iter().map(|&(left, right)| (SeqNum(left), SeqNum(right)));
+        let first = blocks.next();
+        let second = blocks.next();
+        let mut rest = Vec::new();
+        if let Some((left, right)) = first {
+            let within_second = match second {
+                Some((second_left, second_right)) => left.ge(second_left) && right.le(second_right),
+                None => false
+            };
+            if left.lt(right) && (right.le(ack) || within_second) {
+                self.dsacks.push((left, right));
+                events.push(SackEvent::DuplicateSack { left: left, right: right });
+            } else {
+                rest.push((left, right));
+            }
+        }
+        rest.extend(second.into_iter().chain(blocks));
+
+        for &(left, right) in rest.iter() {
+            let (start, end) = (self.offset(left), self.offset(right));
+            if start >= end || end <= self.acked as i64 {
+                events.push(SackEvent::InvalidBlock { left: left, right: right });
+                continue;
+            }
+            merge(&mut self.sacked, (cmp::max(start, self.acked as i64) as u64, end as u64));
+        }
+
+        let acked = self.acked;
+        self.sacked.retain(|&(_, end)| end > acked);
+        if let Some(first) = self.sacked.first_mut() {
+            first.0 = cmp::max(first.0, acked);
+        }
+
+        for hole in self.current_holes().iter() {
+            for &(start, end) in uncovered(&self.holes, *hole).iter() {
+                events.push(SackEvent::Hole { left: self.seq(start), right: self.seq(end) });
+            }
+            merge(&mut self.holes, *hole);
+        }
+    }
+
+    /// The ranges between the cumulative ACK and the SACKed data
+    fn current_holes(&self) -> Vec<(u64, u64)> {
+        match self.sacked.last() {
+            Some(&(_, end)) => uncovered(&self.sacked, (self.acked, end)),
+            None => Vec::new()
+        }
+    }
+}
+
+/// SACK scoreboards of both directions of one connection
+pub struct SackScoreboard {
+    boards : [Board; 2]
+}
+
+impl Default for SackScoreboard {
+    fn default() -> SackScoreboard {
+        SackScoreboard::new()
+    }
+}
+
+impl SackScoreboard {
+    pub fn new() -> SackScoreboard {
+        SackScoreboard {
+            boards : [Board::new(), Board::new()]
+        }
+    }
+
+    /// Feed a segment sent in the given direction. Its ACK and SACK blocks
+    /// update the scoreboard of the opposite direction.
+    pub fn push(&mut self, direction : Direction, segment : &TcpSegment) -> Vec<SackEvent> {
+        let mut events = Vec::new();
+        if !segment.ctrl_flags.contains(ACK) {
+            return events;
+        }
+        let blocks = segment.options.iter().filter_map(|opt| match opt {
+            &TcpOpts::SAck(ref blocks) => Some(&blocks[..]),
+            _ => None
+        }).next().unwrap_or(&[]);
+        self.boards[direction.reverse().index()].ack(segment.ack(), blocks, &mut events);
+        events
+    }
+
+    /// The cumulative ACK of the data sent in a direction
+    pub fn cumulative_ack(&self, direction : Direction) -> Option<SeqNum> {
+        let board = &self.boards[direction.index()];
+        board.base.map(|_| board.seq(board.acked))
+    }
+
+    /// The ranges above the cumulative ACK the receiver reported having
+    pub fn sacked(&self, direction : Direction) -> Vec<(SeqNum, SeqNum)> {
+        let board = &self.boards[direction.index()];
+        board.ranges(&board.sacked)
+    }
+
+    /// The ranges currently missing below SACKed data
+    pub fn holes(&self, direction : Direction) -> Vec<(SeqNum, SeqNum)> {
+        let board = &self.boards[direction.index()];
+        board.ranges(&board.current_holes())
+    }
+
+    /// Every range that was missing below SACKed data at some point, merged
+    pub fn holes_seen(&self, direction : Direction) -> Vec<(SeqNum, SeqNum)> {
+        let board = &self.boards[direction.index()];
+        board.ranges(&board.holes)
+    }
+
+    /// The D-SACK blocks reported, in order
+    pub fn dsacks(&self, direction : Direction) -> &[(SeqNum, SeqNum)] {
+        &self.boards[direction.index()].dsacks
+    }
+}
@@ -0,0 +1,129 @@
+use super::{TcpSegment, TcpParseError, TcpCTRL, OptionsIter, SeqNum};
+use parser;
+use util::{U8ToU16, U8ToU32};
+
+/// A borrowed view of a TCP segment. Header fields are read directly
+/// from the underlying buffer, options are parsed lazily and the
+/// payload is a slice of the input, so nothing is copied until
+/// `to_owned` is called.
+///
+/// # Example
+/// ```rust
+/// use tcp_parser::{TcpSegment, TcpSegmentRef};
+/// let data : Vec<u8> = vec![151, 116, 0, 80, 4, 12, 185, 160, 0, 0, 0, 0, 160, 2, 96, 224, 81,
+///                           40, 0, 0, 2, 4, 4, 216, 4, 2, 8, 10, 1, 49,10,120,0,0,0,0,1,3,3,7];
+/// let segment = TcpSegmentRef::parse(&data).unwrap();
+/// assert_eq!(segment.src_port(), 38772);
+/// assert_eq!(segment.to_owned(), TcpSegment::parse(&data));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TcpSegmentRef<'a> {
+    data : &'a [u8]
+}
+
+impl<'a> TcpSegmentRef<'a> {
+    /// Validate the header and options of the given buffer and wrap it.
+    /// Non-zero reserved bits are accepted, and read with `reserved`.
+    pub fn parse(data : &'a [u8]) -> Result<TcpSegmentRef<'a>, TcpParseError> {
+        let header_len = try!(parser::check_layout(data));
+        try!(parser::walk_options(&data[parser::HEADER_LEN..header_len], |_| {}));
+        Ok(TcpSegmentRef { data: data })
+    }
+
+    /// source port
+    pub fn src_port(&self) -> u16 {
+        self.data[0..2].iter().to_u16().unwrap()
+    }
+
+    /// dest port
+    pub fn dest_port(&self) -> u16 {
+        self.data[2..4].iter().to_u16().unwrap()
+    }
+
+    /// sequence number
+    pub fn seq_num(&self) -> u32 {
+        self.data[4..8].iter().to_u32().unwrap()
+    }
+
+    /// acknowledge number
+    pub fn ack_num(&self) -> u32 {
+        self.data[8..12].iter().to_u32().unwrap()
+    }
+
+    /// sequence number, for serial number arithmetic
+    pub fn seq(&self) -> SeqNum {
+        SeqNum(self.seq_num())
+    }
+
+    /// acknowledge number, for serial number arithmetic
+    pub fn ack(&self) -> SeqNum {
+        SeqNum(self.ack_num())
+    }
+
+    /// Data offset - size of TCP header in 32-bit words
+    pub fn data_off(&self) -> u8 {
+        self.data[12] >> 4
+    }
+
+    /// Reserved bits between the data offset and the control flags
+    pub fn reserved(&self) -> u8 {
+        (self.data[12] >> 1) & 0x07
+    }
+
+    /// Control flags
+    pub fn ctrl_flags(&self) -> TcpCTRL {
+        TcpCTRL::from_bits_truncate(self.data[12..14].iter().to_u16().unwrap())
+    }
+
+    /// TCP Window size
+    pub fn window(&self) -> u16 {
+        self.data[14..16].iter().to_u16().unwrap()
+    }
+
+    /// TCP checksum
+    pub fn checksum(&self) -> u16 {
+        self.data[16..18].iter().to_u16().unwrap()
+    }
+
+    /// Offset from seq num indicating the last urgent data byte
+    pub fn urg_ptr(&self) -> u16 {
+        self.data[18..20].iter().to_u16().unwrap()
+    }
+
+    /// Lazily parse the TCP options
+    pub fn options(&self) -> OptionsIter<'a> {
+        OptionsIter::new(&self.data[parser::HEADER_LEN..self.header_len()])
+    }
+
+    /// application layer data
+    pub fn data(&self) -> &'a [u8] {
+        &self.data[self.header_len()..]
+    }
+
+    /// The whole segment, header included
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Copy this view into an owned TcpSegment
+    pub fn to_owned(&self) -> TcpSegment {
+        TcpSegment {
+            src_port:   self.src_port(),
+            dest_port:  self.dest_port(),
+            seq_num:    self.seq_num(),
+            ack_num:    self.ack_num(),
+            data_off:   self.data_off(),
+            reserved:   self.reserved(),
+            ctrl_flags: self.ctrl_flags(),
+            window:     self.window(),
+            checksum:   self.checksum(),
+            urg_ptr:    self.urg_ptr(),
+            options:    self.options().collect(),
+            data:       self.data().to_vec()
+        }
+    }
+
+    fn header_len(&self) -> usize {
+        4 * self.data_off() as usize
+    }
+}
@@ -0,0 +1,101 @@
+//! # Sequence Numbers
+//! TCP sequence and acknowledgement numbers live in a 32 bit space that wraps
+//! around, so they are compared with serial number arithmetic (RFC 1982): `a`
+//! is before `b` when `b` is less than 2^31 ahead of it. Two numbers exactly
+//! 2^31 apart are neither before nor after each other.
+//!
+//! The `seq_num` and `ack_num` fields of `TcpSegment` keep the raw header values,
+//! as parsing and building deal in those. Everything that compares or offsets them
+//! goes through the `seq()`, `ack()` and `end_seq()` accessors instead.
+//!
+//! # Example
+//! ```rust
+//! use tcp_parser::SeqNum;
+//! let isn = SeqNum(0xFFFFFFF0);
+//! let seq = isn + 0x20;
+//! assert_eq!(seq, SeqNum(0x10));
+//! assert!(isn.lt(seq) && seq.gt(isn));
+//! assert_eq!(seq - isn, 0x20);
+//! assert_eq!(seq.relative_to(isn), 0x20);
+//! assert!(seq.in_window(isn, 0x100));
+//! ```
+
+use std::fmt;
+use std::ops::{Add, Sub};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+/// A sequence or acknowledgement number. It deliberately does not implement
+/// `PartialOrd`, as the order is not transitive: use `lt`, `le`, `gt` and `ge`.
+pub struct SeqNum(pub u32);
+
+impl SeqNum {
+    /// Whether this number is before `other`
+    pub fn lt(self, other : SeqNum) -> bool {
+        let ahead = other.0.wrapping_sub(self.0);
+        ahead != 0 && ahead < 0x80000000
+    }
+
+    /// Whether this number is before or equal to `other`
+    pub fn le(self, other : SeqNum) -> bool {
+        self == other || self.lt(other)
+    }
+
+    /// Whether this number is after `other`
+    pub fn gt(self, other : SeqNum) -> bool {
+        other.lt(self)
+    }
+
+    /// Whether this number is after or equal to `other`
+    pub fn ge(self, other : SeqNum) -> bool {
+        other.le(self)
+    }
+
+    /// Whether this number is within the `len` numbers starting at `start`
+    pub fn in_window(self, start : SeqNum, len : u32) -> bool {
+        self.0.wrapping_sub(start.0) < len
+    }
+
+    /// The offset of this number from an initial sequence number, as Wireshark
+    /// shows relative sequence numbers: the SYN is 0 and the first data byte 1
+    pub fn relative_to(self, isn : SeqNum) -> u32 {
+        self.0.wrapping_sub(isn.0)
+    }
+}
+
+impl From<u32> for SeqNum {
+    fn from(seq : u32) -> SeqNum {
+        SeqNum(seq)
+    }
+}
+
+impl Add<u32> for SeqNum {
+    type Output = SeqNum;
+
+    fn add(self, len : u32) -> SeqNum {
+        SeqNum(self.0.wrapping_add(len))
+    }
+}
+
+impl Sub<u32> for SeqNum {
+    type Output = SeqNum;
+
+    fn sub(self, len : u32) -> SeqNum {
+        SeqNum(self.0.wrapping_sub(len))
+    }
+}
+
+/// The signed distance from `other` to this number. Exactly 2^31 apart is
+/// undefined by RFC 1982 and comes out negative.
+impl Sub<SeqNum> for SeqNum {
+    type Output = i32;
+
+    fn sub(self, other : SeqNum) -> i32 {
+        self.0.wrapping_sub(other.0) as i32
+    }
+}
+
+impl fmt::Display for SeqNum {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
@@ -0,0 +1,51 @@
+use super::{TcpSegment, TcpOpts, IPv4PseudoHeader};
+use crypto::md5::Md5;
+use crypto::constant_time_eq;
+use util::{U32ToU8, U16ToU8};
+
+/// TCP MD5 signature option (RFC 2385)
+impl TcpSegment {
+    /// The digest carried in the MD5 signature option, if present
+    pub fn md5_signature(&self) -> Option<[u8; 16]> {
+        for opt in self.options.iter() {
+            if let &TcpOpts::Md5Signature(sig) = opt {
+                return Some(sig);
+            }
+        }
+        None
+    }
+
+    /// Calculate the MD5 digest of this segment as specified by RFC 2385:
+    /// the pseudo header, the header without options and with a zero
+    /// checksum, the data and finally the connection's shared key.
+    pub fn calculate_md5_signature(&self, pseudo_header : IPv4PseudoHeader, key : &[u8]) -> [u8; 16] {
+        let mut md5 = Md5::new();
+
+        md5.update(&pseudo_header.source_addr.to_u8());
+        md5.update(&pseudo_header.dest_addr.to_u8());
+        md5.update(&[0, pseudo_header.protocol]);
+        md5.update(&pseudo_header.tcp_len.to_u8());
+
+        md5.update(&self.src_port.to_u8());
+        md5.update(&self.dest_port.to_u8());
+        md5.update(&self.seq_num.to_u8());
+        md5.update(&self.ack_num.to_u8());
+        md5.update(&self.offset_flags().to_u8());
+        md5.update(&self.window.to_u8());
+        md5.update(&[0, 0]);
+        md5.update(&self.urg_ptr.to_u8());
+
+        md5.update(&self.data);
+        md5.update(key);
+        md5.finish()
+    }
+
+    /// Check the MD5 signature option against the digest calculated from the
+    /// provided pseudo header and shared key. Segments without the option never verify.
+    pub fn verify_md5_signature(&self, pseudo_header : IPv4PseudoHeader, key : &[u8]) -> bool {
+        match self.md5_signature() {
+            Some(sig) => constant_time_eq(&sig, &self.calculate_md5_signature(pseudo_header, key)),
+            None      => false
+        }
+    }
+}
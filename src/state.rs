@@ -0,0 +1,352 @@
+//! # Connection State Tracking
+//! Follows the RFC 793/9293 state machine of both endpoints of a connection
+//! from the segments between them, in capture order. The tracker sits on the
+//! wire, so each segment is taken as sent by one endpoint and received by the
+//! other at once. Transitions are reported as events, along with simultaneous
+//! opens and closes, half-closed streams, and segments that no state accepts.
+//! Connections picked up after their handshake start out established.
+//!
+//! # Example
+//! ```rust
+//! use tcp_parser::TcpSegment;
+//! use tcp_parser::stream::Direction;
+//! use tcp_parser::state::{StateTracker, TcpState};
+//! let mut tracker = StateTracker::new();
+//! let syn = TcpSegment::parse(&[0x30, 0x39, 0, 80, 0, 0, 0, 1, 0, 0, 0, 0, 0x50, 0x02, 0, 0, 0, 0, 0, 0]);
+//! let syn_ack = TcpSegment::parse(&[0, 80, 0x30, 0x39, 0, 0, 0, 9, 0, 0, 0, 2, 0x50, 0x12, 0, 0, 0, 0, 0, 0]);
+//! tracker.push(Direction::ClientToServer, &syn);
+//! tracker.push(Direction::ServerToClient, &syn_ack);
+//! assert_eq!(tracker.state(Direction::ClientToServer), TcpState::Established);
+//! assert_eq!(tracker.state(Direction::ServerToClient), TcpState::SynReceived);
+//! ```
+
+use std::vec::Vec;
+use std::fmt;
+use super::{TcpSegment, SeqNum, SYN, ACK};
+use stream::Direction;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// The state of one endpoint of a connection
+pub enum TcpState {
+    Listen,
+    SynSent,
+    SynReceived,
+    Established,
+    FinWait1,
+    FinWait2,
+    CloseWait,
+    Closing,
+    LastAck,
+    TimeWait,
+    Closed
+}
+
+/// Formats the state the way RFC 9293 names it, e.g. `SYN-SENT`
+impl fmt::Display for TcpState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            TcpState::Listen      => "LISTEN",
+            TcpState::SynSent     => "SYN-SENT",
+            TcpState::SynReceived => "SYN-RECEIVED",
+            TcpState::Established => "ESTABLISHED",
+            TcpState::FinWait1    => "FIN-WAIT-1",
+            TcpState::FinWait2    => "FIN-WAIT-2",
+            TcpState::CloseWait   => "CLOSE-WAIT",
+            TcpState::Closing     => "CLOSING",
+            TcpState::LastAck     => "LAST-ACK",
+            TcpState::TimeWait    => "TIME-WAIT",
+            TcpState::Closed      => "CLOSED"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Why a segment does not fit the state of its sender
+pub enum InvalidReason {
+    /// A SYN from an endpoint that is already synchronized
+    UnexpectedSyn,
+    /// A SYN-ACK from an endpoint that did not receive a SYN
+    UnexpectedSynAck,
+    /// A SYN-ACK that does not acknowledge the SYN
+    AckMismatch,
+    /// A FIN from an endpoint that has not synchronized
+    UnexpectedFin,
+    /// Data past the FIN of its sender
+    DataAfterFin,
+    /// A segment other than a RST from an endpoint that has closed
+    SegmentAfterClose
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// What a segment did to the connection. Endpoints are named by the direction
+/// they send in, so `ClientToServer` is the client.
+pub enum StateEvent {
+    /// An endpoint moved to another state
+    Transition {
+        endpoint    : Direction,
+        from        : TcpState,
+        to          : TcpState
+    },
+    /// Both endpoints sent a SYN before receiving the other's
+    SimultaneousOpen,
+    /// Both endpoints sent a FIN before receiving the other's
+    SimultaneousClose,
+    /// This direction was closed by a FIN while the other stays open
+    HalfClosed(Direction),
+    /// The connection was picked up after its handshake
+    Midstream,
+    /// A segment sent in this direction does not fit the state of its sender
+    Invalid {
+        direction   : Direction,
+        state       : TcpState,
+        reason      : InvalidReason
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Peer {
+    state   : TcpState,
+    /// Sequence number of the SYN sent
+    isn     : Option<SeqNum>,
+    /// Sequence number of the FIN sent
+    fin     : Option<SeqNum>
+}
+
+impl Peer {
+    fn new(state : TcpState) -> Peer {
+        Peer {
+            state   : state,
+            isn     : None,
+            fin     : None
+        }
+    }
+
+    /// Whether an acknowledgement number covers the FIN sent
+    fn fin_acked(&self, ack : SeqNum) -> bool {
+        match self.fin {
+            Some(fin) => ack.ge(fin + 1),
+            None => false
+        }
+    }
+}
+
+/// Tracks the states of both endpoints of one connection. The client starts
+/// out closed and the server listening.
+pub struct StateTracker {
+    peers   : [Peer; 2],
+    started : bool
+}
+
+impl Default for StateTracker {
+    fn default() -> StateTracker {
+        StateTracker::new()
+    }
+}
+
+impl StateTracker {
+    pub fn new() -> StateTracker {
+        StateTracker {
+            peers   : [Peer::new(TcpState::Closed), Peer::new(TcpState::Listen)],
+            started : false
+        }
+    }
+
+    /// The state of the endpoint sending in a direction
+    pub fn state(&self, endpoint : Direction) -> TcpState {
+        self.peers[endpoint.index()].state
+    }
+
+    /// Whether exactly one direction was closed by a FIN
+    pub fn is_half_closed(&self) -> bool {
+        self.peers[0].fin.is_some() != self.peers[1].fin.is_some()
+    }
+
+    /// Feed a segment sent in the given direction, returning what it did
+    pub fn push(&mut self, direction : Direction, segment : &TcpSegment) -> Vec<StateEvent> {
+        let mut events = Vec::new();
+        self.push_with(direction, segment, |event| events.push(event));
+        events
+    }
+
+    /// Feed a segment sent in the given direction, passing what it did to `callback`
+    pub fn push_with<F : FnMut(StateEvent)>(&mut self, direction : Direction, segment : &TcpSegment, mut callback : F) {
+        let emit = &mut callback;
+        let receiver = direction.reverse();
+
+        if segment.is_rst() {
+            self.started = true;
+            self.set(direction, TcpState::Closed, emit);
+            self.set(receiver, TcpState::Closed, emit);
+            return;
+        }
+        if self.started && self.state(direction) == TcpState::Closed {
+            self.invalid(direction, InvalidReason::SegmentAfterClose, emit);
+            return;
+        }
+
+        if !self.started {
+            self.started = true;
+            if segment.is_syn_ack() {
+                // The SYN was not captured
+                self.peers[receiver.index()].isn = Some(segment.ack() - 1);
+                self.set(receiver, TcpState::SynSent, emit);
+                self.set(direction, TcpState::SynReceived, emit);
+            } else if !segment.is_syn() {
+                emit(StateEvent::Midstream);
+                self.set(direction, TcpState::Established, emit);
+                self.set(receiver, TcpState::Established, emit);
+            }
+        }
+
+        if segment.is_syn() {
+            if !self.syn(direction, segment, emit) {
+                return;
+            }
+        } else if segment.is_syn_ack() {
+            if !self.syn_ack(direction, segment, emit) {
+                return;
+            }
+        } else if segment.ctrl_flags.contains(ACK) {
+            self.ack(direction, segment.ack(), emit);
+        }
+
+        let syn_len = if segment.ctrl_flags.contains(SYN) { 1 } else { 0 };
+        let end = segment.seq() + (syn_len + segment.data.len() as u32);
+        if let Some(fin) = self.peers[direction.index()].fin {
+            if !segment.data.is_empty() && end.gt(fin) {
+                self.invalid(direction, InvalidReason::DataAfterFin, emit);
+            }
+        }
+        if segment.is_fin() {
+            self.fin(direction, segment, end, emit);
+        }
+    }
+
+    fn set<F : FnMut(StateEvent)>(&mut self, endpoint : Direction, to : TcpState, emit : &mut F) {
+        let peer = &mut self.peers[endpoint.index()];
+        if peer.state != to {
+            let from = peer.state;
+            peer.state = to;
+            emit(StateEvent::Transition { endpoint: endpoint, from: from, to: to });
+        }
+    }
+
+    fn invalid<F : FnMut(StateEvent)>(&self, direction : Direction, reason : InvalidReason, emit : &mut F) {
+        emit(StateEvent::Invalid { direction: direction, state: self.state(direction), reason: reason });
+    }
+
+    /// A SYN without ACK. Returns whether it was accepted.
+    fn syn<F : FnMut(StateEvent)>(&mut self, direction : Direction, segment : &TcpSegment, emit : &mut F) -> bool {
+        let receiver = direction.reverse();
+        let (sender, isn) = (self.state(direction), self.peers[direction.index()].isn);
+        match sender {
+            TcpState::Closed | TcpState::Listen | TcpState::SynSent => {
+                self.set(direction, TcpState::SynSent, emit);
+            },
+            // Sent before the SYN it crossed on the wire arrived
+            TcpState::SynReceived if isn.is_none() => {},
+            TcpState::SynReceived if isn == Some(segment.seq()) => return true,
+            _ => {
+                self.invalid(direction, InvalidReason::UnexpectedSyn, emit);
+                return false;
+            }
+        }
+        self.peers[direction.index()].isn = Some(segment.seq());
+
+        match self.state(receiver) {
+            TcpState::Closed | TcpState::Listen => self.set(receiver, TcpState::SynReceived, emit),
+            TcpState::SynSent => {
+                emit(StateEvent::SimultaneousOpen);
+                self.set(receiver, TcpState::SynReceived, emit);
+            },
+            _ => {}
+        }
+        true
+    }
+
+    /// A SYN-ACK. Returns whether it was accepted.
+    fn syn_ack<F : FnMut(StateEvent)>(&mut self, direction : Direction, segment : &TcpSegment, emit : &mut F) -> bool {
+        let receiver = direction.reverse();
+        match self.state(direction) {
+            TcpState::SynReceived => {},
+            // A retransmission after the handshake completed
+            TcpState::Established if self.peers[direction.index()].isn == Some(segment.seq()) => {},
+            _ => {
+                self.invalid(direction, InvalidReason::UnexpectedSynAck, emit);
+                return false;
+            }
+        }
+        if let Some(isn) = self.peers[receiver.index()].isn {
+            if segment.ack() != isn + 1 {
+                self.invalid(direction, InvalidReason::AckMismatch, emit);
+                return false;
+            }
+        }
+        self.peers[direction.index()].isn = Some(segment.seq());
+
+        match self.state(receiver) {
+            TcpState::SynSent | TcpState::SynReceived => self.set(receiver, TcpState::Established, emit),
+            _ => {}
+        }
+        true
+    }
+
+    /// An acknowledgement without SYN, completing the handshake or a close of the receiver
+    fn ack<F : FnMut(StateEvent)>(&mut self, direction : Direction, ack : SeqNum, emit : &mut F) {
+        let receiver = direction.reverse();
+        // The SYN-ACK was not captured
+        if self.state(direction) == TcpState::SynSent {
+            self.set(direction, TcpState::Established, emit);
+        }
+
+        let peer = self.peers[receiver.index()];
+        let syn_acked = match peer.isn {
+            Some(isn) => ack.ge(isn + 1),
+            None => true
+        };
+        let to = match peer.state {
+            TcpState::SynReceived if syn_acked => TcpState::Established,
+            TcpState::FinWait1 if peer.fin_acked(ack) => TcpState::FinWait2,
+            TcpState::Closing if peer.fin_acked(ack) => TcpState::TimeWait,
+            TcpState::LastAck if peer.fin_acked(ack) => TcpState::Closed,
+            state => state
+        };
+        self.set(receiver, to, emit);
+    }
+
+    /// A FIN, taking up sequence number `fin` after the data of the segment
+    fn fin<F : FnMut(StateEvent)>(&mut self, direction : Direction, segment : &TcpSegment, fin : SeqNum, emit : &mut F) {
+        let receiver = direction.reverse();
+        if self.peers[direction.index()].fin.is_some() {
+            return;
+        }
+
+        let peer_fin_acked = segment.ctrl_flags.contains(ACK) && self.peers[receiver.index()].fin_acked(segment.ack());
+        let to = match self.state(direction) {
+            TcpState::SynReceived | TcpState::Established => TcpState::FinWait1,
+            // The FIN of the peer was not received yet: both were sent at once
+            TcpState::CloseWait if !peer_fin_acked => TcpState::Closing,
+            TcpState::CloseWait => TcpState::LastAck,
+            _ => {
+                self.invalid(direction, InvalidReason::UnexpectedFin, emit);
+                return;
+            }
+        };
+        self.peers[direction.index()].fin = Some(fin);
+        self.set(direction, to, emit);
+
+        match self.state(receiver) {
+            TcpState::SynReceived | TcpState::Established => {
+                self.set(receiver, TcpState::CloseWait, emit);
+                emit(StateEvent::HalfClosed(direction));
+            },
+            TcpState::FinWait1 => {
+                emit(StateEvent::SimultaneousClose);
+                self.set(receiver, TcpState::Closing, emit);
+            },
+            TcpState::FinWait2 => self.set(receiver, TcpState::TimeWait, emit),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,504 @@
+//! # Stream Reassembly
+//! Turns the segments of one connection back into the two application byte
+//! streams. Each direction tracks its sequence space from the ISN of its SYN
+//! (or from its first segment when the handshake was not captured), buffers
+//! out-of-order data, resolves overlapping bytes with an `OverlapPolicy`, reporting
+//! those that conflict, and delivers contiguous data in order. Bytes that will
+//! never arrive are reported as gaps: when the peer acknowledges past them, when
+//! too much data is buffered behind them, or when the direction is flushed.
+//!
+//! # Example
+//! ```rust
+//! use tcp_parser::TcpSegment;
+//! use tcp_parser::stream::{StreamReassembler, StreamConfig, Direction};
+//! use std::io::Read;
+//! let mut streams = StreamReassembler::new(StreamConfig::default());
+//! let syn = TcpSegment::parse(&[0, 80, 0, 81, 0, 0, 0, 99, 0, 0, 0, 0, 0x50, 0x02, 0, 0, 0, 0, 0, 0]);
+//! let mut segment = TcpSegment::parse(&[0, 80, 0, 81, 0, 0, 0, 103, 0, 0, 0, 0, 0x50, 0x08,
+//!                                        0, 0, 0, 0, 0, 0, b'l', b'o']);
+//! streams.push(Direction::ClientToServer, &syn);
+//! streams.push(Direction::ClientToServer, &segment);
+//! segment.seq_num = 100;
+//! segment.data = b"hel".to_vec();
+//! streams.push(Direction::ClientToServer, &segment);
+//!
+//! let mut text = String::new();
+//! streams.reader(Direction::ClientToServer).read_to_string(&mut text).unwrap();
+//! assert_eq!(text, "hello");
+//! ```
+
+use std::vec::Vec;
+use std::cmp;
+use std::mem;
+use std::collections::BTreeMap;
+use super::{TcpSegment, SeqNum, SYN, FIN, RST, ACK};
+
+pub use overlap::OverlapPolicy;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+/// The direction of a segment within a connection
+pub enum Direction {
+    /// Sent by the endpoint that opened the connection
+    ClientToServer,
+    /// Sent by the endpoint that accepted the connection
+    ServerToClient
+}
+
+impl Direction {
+    /// The opposite direction
+    pub fn reverse(&self) -> Direction {
+        match *self {
+            Direction::ClientToServer => Direction::ServerToClient,
+            Direction::ServerToClient => Direction::ClientToServer
+        }
+    }
+
+    /// 0 for client to server and 1 for server to client, to index per-direction state
+    pub fn index(&self) -> usize {
+        match *self {
+            Direction::ClientToServer => 0,
+            Direction::ServerToClient => 1
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// What a direction of the stream delivers, in stream order
+pub enum StreamEvent<'a> {
+    /// The next contiguous bytes of the stream
+    Data(&'a [u8]),
+    /// This many bytes are missing and were skipped
+    Gap(u64),
+    /// The sender closed this direction, after all of its data
+    Fin,
+    /// The sender reset the connection
+    Reset,
+    /// Overlapping segments carried different bytes from this stream offset on.
+    /// `kept` is what the overlap policy delivers, `discarded` what it dropped.
+    Conflict {
+        offset      : u64,
+        kept        : &'a [u8],
+        discarded   : &'a [u8]
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// A `StreamEvent` other than data, kept by `push` and `flush` until taken
+pub enum KeptEvent {
+    Gap(u64),
+    Fin,
+    Reset,
+    Conflict {
+        offset      : u64,
+        kept        : Vec<u8>,
+        discarded   : Vec<u8>
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Limits and overlap policy of a stream reassembler
+pub struct StreamConfig {
+    /// Out-of-order bytes buffered per direction before the hole in front of them is skipped
+    pub max_buffer  : usize,
+    /// How overlapping bytes that are not yet delivered are resolved. Bytes
+    /// already delivered are never replaced.
+    pub policy      : OverlapPolicy,
+    /// Delivered bytes kept per direction to detect retransmissions carrying
+    /// conflicting data
+    pub history     : usize
+}
+
+impl Default for StreamConfig {
+    fn default() -> StreamConfig {
+        StreamConfig {
+            max_buffer  : 1024 * 1024,
+            policy      : OverlapPolicy::First,
+            history     : 0
+        }
+    }
+}
+
+/// Out-of-order data, remembering the span of the segment it came from
+struct Piece {
+    data    : Vec<u8>,
+    origin  : (u64, u64)
+}
+
+/// The sequence space of one direction
+struct HalfStream {
+    config      : StreamConfig,
+    /// Sequence number of stream offset 0, once the first segment is seen
+    base        : Option<SeqNum>,
+    /// Stream offset of the next byte to deliver
+    delivered   : u64,
+    /// The last delivered bytes, up to `config.history` of them
+    history     : Vec<u8>,
+    /// Disjoint out-of-order data by stream offset
+    pending     : BTreeMap<u64, Piece>,
+    buffered    : usize,
+    conflicts   : u64,
+    /// Stream offset of the FIN
+    fin         : Option<u64>,
+    closed      : bool,
+    /// Skipped ranges, as stream offset and length
+    gaps        : Vec<(u64, u64)>
+}
+
+impl HalfStream {
+    fn new(config : StreamConfig) -> HalfStream {
+        HalfStream {
+            config      : config,
+            base        : None,
+            delivered   : 0,
+            history     : Vec::new(),
+            pending     : BTreeMap::new(),
+            buffered    : 0,
+            conflicts   : 0,
+            fin         : None,
+            closed      : false,
+            gaps        : Vec::new()
+        }
+    }
+
+    /// Stream offset of a sequence number, unwrapped around the next byte to
+    /// deliver. Negative offsets fall before the start of the stream.
+    fn offset(&self, seq : SeqNum) -> Option<i64> {
+        self.base.map(|base| {
+            let next = base + self.delivered as u32;
+            self.delivered as i64 + (seq - next) as i64
+        })
+    }
+
+    fn segment<F : FnMut(StreamEvent)>(&mut self, segment : &TcpSegment, emit : &mut F) {
+        if self.closed {
+            return;
+        }
+        let syn = segment.ctrl_flags.contains(SYN);
+        if self.base.is_none() {
+            self.base = Some(if syn { segment.seq() + 1 } else { segment.seq() });
+        }
+        if segment.ctrl_flags.contains(RST) {
+            self.closed = true;
+            emit(StreamEvent::Reset);
+            return;
+        }
+
+        let seq = if syn { segment.seq() + 1 } else { segment.seq() };
+        let start = self.offset(seq).unwrap();
+        let end = start + segment.data.len() as i64;
+        if start < self.delivered as i64 {
+            self.check_history(start, &segment.data, emit);
+        }
+        if end > self.delivered as i64 {
+            let skip = cmp::max(self.delivered as i64 - start, 0);
+            let origin = (cmp::max(start, 0) as u64, end as u64);
+            self.insert((start + skip) as u64, &segment.data[skip as usize..], origin, emit);
+        }
+        if segment.ctrl_flags.contains(FIN) && end >= 0 {
+            self.fin = Some(end as u64);
+        }
+
+        self.deliver(emit);
+        while self.buffered > self.config.max_buffer && !self.closed {
+            let next = *self.pending.keys().next().unwrap();
+            self.skip_to(next, emit);
+        }
+    }
+
+    /// Compare data that starts before the next byte to deliver with the
+    /// delivered bytes still in the history
+    fn check_history<F : FnMut(StreamEvent)>(&mut self, start : i64, data : &[u8], emit : &mut F) {
+        let history_start = self.delivered as i64 - self.history.len() as i64;
+        let from = cmp::max(start, history_start);
+        let to = cmp::min(start + data.len() as i64, self.delivered as i64);
+        if from >= to {
+            return;
+        }
+        let kept = &self.history[(from - history_start) as usize..(to - history_start) as usize];
+        let discarded = &data[(from - start) as usize..(to - start) as usize];
+        if kept != discarded {
+            self.conflicts += 1;
+            emit(StreamEvent::Conflict { offset: from as u64, kept: kept, discarded: discarded });
+        }
+    }
+
+    /// Buffer data at a stream offset at or after the next byte to deliver,
+    /// resolving overlaps with buffered data by the overlap policy. `origin` is
+    /// the span of the whole segment the data came from.
+    fn insert<F : FnMut(StreamEvent)>(&mut self, start : u64, data : &[u8], origin : (u64, u64), emit : &mut F) {
+        let end = start + data.len() as u64;
+        let overlapping : Vec<u64> = self.pending.iter()
+            .filter(|&(&key, piece)| key < end && key + piece.data.len() as u64 > start)
+            .map(|(&key, _)| key)
+            .collect();
+
+        // Parts of the new data not covered by buffered data that wins
+        let mut ranges = Vec::new();
+        let mut cursor = start;
+        for &key in overlapping.iter() {
+            let piece = self.pending.remove(&key).unwrap();
+            self.buffered -= piece.data.len();
+            let piece_end = key + piece.data.len() as u64;
+            let from = cmp::max(key, start);
+            let to = cmp::min(piece_end, end);
+            let new_wins = self.config.policy.new_wins(origin, piece.origin);
+
+            let old = &piece.data[(from - key) as usize..(to - key) as usize];
+            let new = &data[(from - start) as usize..(to - start) as usize];
+            if old != new {
+                self.conflicts += 1;
+                emit(if new_wins {
+                    StreamEvent::Conflict { offset: from, kept: new, discarded: old }
+                } else {
+                    StreamEvent::Conflict { offset: from, kept: old, discarded: new }
+                });
+            }
+
+            if new_wins {
+                if key < from {
+                    self.store(key, &piece.data[..(from - key) as usize], piece.origin);
+                }
+                if piece_end > to {
+                    self.store(to, &piece.data[(to - key) as usize..], piece.origin);
+                }
+            } else {
+                if cursor < from {
+                    ranges.push((cursor, from));
+                }
+                cursor = to;
+                self.store(key, &piece.data, piece.origin);
+            }
+        }
+        if cursor < end {
+            ranges.push((cursor, end));
+        }
+
+        for &(from, to) in ranges.iter() {
+            self.store(from, &data[(from - start) as usize..(to - start) as usize], origin);
+        }
+    }
+
+    fn store(&mut self, start : u64, data : &[u8], origin : (u64, u64)) {
+        self.buffered += data.len();
+        self.pending.insert(start, Piece {
+            data    : data.iter().cloned().collect(),
+            origin  : origin
+        });
+    }
+
+    /// Deliver buffered data that is now contiguous, then the FIN once reached
+    fn deliver<F : FnMut(StreamEvent)>(&mut self, emit : &mut F) {
+        loop {
+            let key = match self.pending.keys().next() {
+                Some(&key) if key <= self.delivered => key,
+                _ => break
+            };
+            let piece = self.pending.remove(&key).unwrap();
+            self.buffered -= piece.data.len();
+            let end = key + piece.data.len() as u64;
+            if end > self.delivered {
+                let data = &piece.data[(self.delivered - key) as usize..];
+                emit(StreamEvent::Data(data));
+                self.delivered = end;
+                self.remember(data);
+            }
+        }
+
+        if let Some(fin) = self.fin {
+            if self.delivered >= fin && !self.closed {
+                self.closed = true;
+                emit(StreamEvent::Fin);
+            }
+        }
+    }
+
+    /// Keep delivered bytes in the history
+    fn remember(&mut self, data : &[u8]) {
+        if self.config.history == 0 {
+            return;
+        }
+        self.history.extend(data.iter());
+        if self.history.len() > self.config.history {
+            let excess = self.history.len() - self.config.history;
+            self.history.drain(..excess);
+        }
+    }
+
+    /// Deliver everything up to a stream offset, skipping the holes on the way
+    fn skip_to<F : FnMut(StreamEvent)>(&mut self, target : u64, emit : &mut F) {
+        self.deliver(emit);
+        while self.delivered < target && !self.closed {
+            let next = match self.pending.keys().next() {
+                Some(&key) if key < target => key,
+                _ => target
+            };
+            let len = next - self.delivered;
+            self.gaps.push((self.delivered, len));
+            emit(StreamEvent::Gap(len));
+            self.delivered = next;
+            self.history.clear();
+            self.deliver(emit);
+        }
+    }
+
+    /// The peer acknowledged up to this sequence number, so anything missing before it was lost
+    fn acknowledged<F : FnMut(StreamEvent)>(&mut self, ack : SeqNum, emit : &mut F) {
+        if self.closed {
+            return;
+        }
+        let mut target = match self.offset(ack) {
+            Some(offset) if offset > self.delivered as i64 => offset as u64,
+            _ => return
+        };
+        // The FIN takes up a sequence number but no byte of the stream
+        if let Some(fin) = self.fin {
+            target = cmp::min(target, fin);
+        }
+        self.skip_to(target, emit);
+    }
+
+    fn flush<F : FnMut(StreamEvent)>(&mut self, emit : &mut F) {
+        let end = self.pending.iter().map(|(&key, piece)| key + piece.data.len() as u64).max();
+        let target = match (end, self.fin) {
+            (Some(end), Some(fin)) => cmp::max(end, fin),
+            (Some(end), None) => end,
+            (None, Some(fin)) => fin,
+            (None, None) => return
+        };
+        self.skip_to(target, emit);
+    }
+}
+
+/// What `push` and `flush` deliver, until it is read or taken
+#[derive(Default)]
+struct Output {
+    data    : [Vec<u8>; 2],
+    events  : Vec<(Direction, KeptEvent)>
+}
+
+impl Output {
+    fn keep(&mut self, direction : Direction, event : StreamEvent) {
+        let kept = match event {
+            StreamEvent::Data(data) => {
+                self.data[direction.index()].extend(data.iter());
+                return;
+            },
+            StreamEvent::Gap(len) => KeptEvent::Gap(len),
+            StreamEvent::Fin => KeptEvent::Fin,
+            StreamEvent::Reset => KeptEvent::Reset,
+            StreamEvent::Conflict { offset, kept, discarded } => KeptEvent::Conflict {
+                offset      : offset,
+                kept        : kept.to_vec(),
+                discarded   : discarded.to_vec()
+            }
+        };
+        self.events.push((direction, kept));
+    }
+}
+
+/// Reassembles both directions of one connection
+pub struct StreamReassembler {
+    halves  : [HalfStream; 2],
+    output  : Output
+}
+
+impl StreamReassembler {
+    pub fn new(config : StreamConfig) -> StreamReassembler {
+        StreamReassembler {
+            halves  : [HalfStream::new(config), HalfStream::new(config)],
+            output  : Output::default()
+        }
+    }
+
+    /// Feed a segment sent in the given direction, passing what each direction
+    /// delivers as a result to `callback`. The acknowledgement number of the
+    /// segment can cause gaps to be reported for the opposite direction.
+    pub fn push_with<F : FnMut(Direction, StreamEvent)>(&mut self, direction : Direction,
+                                                        segment : &TcpSegment, mut callback : F) {
+        self.halves[direction.index()].segment(segment, &mut |event| callback(direction, event));
+        if segment.ctrl_flags.contains(ACK) {
+            let reverse = direction.reverse();
+            self.halves[reverse.index()].acknowledged(segment.ack(), &mut |event| callback(reverse, event));
+        }
+    }
+
+    /// Feed a segment sent in the given direction, keeping delivered data to be
+    /// read with `reader` and the other events to be taken with `take_events`
+    pub fn push(&mut self, direction : Direction, segment : &TcpSegment) {
+        let mut output = mem::replace(&mut self.output, Output::default());
+        self.push_with(direction, segment, |direction, event| output.keep(direction, event));
+        self.output = output;
+    }
+
+    /// Deliver everything buffered for a direction, reporting the holes in
+    /// front of it as gaps, e.g. once the capture has ended
+    pub fn flush_with<F : FnMut(StreamEvent)>(&mut self, direction : Direction, mut callback : F) {
+        self.halves[direction.index()].flush(&mut callback);
+    }
+
+    /// Deliver everything buffered for a direction, keeping it as `push` does
+    pub fn flush(&mut self, direction : Direction) {
+        let mut output = mem::replace(&mut self.output, Output::default());
+        self.flush_with(direction, |event| output.keep(direction, event));
+        self.output = output;
+    }
+
+    /// Delivered bytes not yet read, for segments fed with `push`
+    pub fn available(&self, direction : Direction) -> usize {
+        self.output.data[direction.index()].len()
+    }
+
+    /// Take the events other than data kept by `push` and `flush` so far, in order
+    pub fn take_events(&mut self) -> Vec<(Direction, KeptEvent)> {
+        mem::replace(&mut self.output.events, Vec::new())
+    }
+
+    /// Out-of-order bytes waiting for the data in front of them
+    pub fn buffered(&self, direction : Direction) -> usize {
+        self.halves[direction.index()].buffered
+    }
+
+    /// Stream offset of the next byte a direction will deliver
+    pub fn delivered(&self, direction : Direction) -> u64 {
+        self.halves[direction.index()].delivered
+    }
+
+    /// Number of overlaps so far where segments carried conflicting bytes
+    pub fn conflicts(&self, direction : Direction) -> u64 {
+        self.halves[direction.index()].conflicts
+    }
+
+    /// Ranges skipped so far, as stream offset and length
+    pub fn gaps(&self, direction : Direction) -> &[(u64, u64)] {
+        &self.halves[direction.index()].gaps
+    }
+
+    /// Whether a direction has delivered its FIN or was reset
+    pub fn is_closed(&self, direction : Direction) -> bool {
+        self.halves[direction.index()].closed
+    }
+
+    /// Read the bytes delivered in a direction by `push` and `flush`
+    #[cfg(not(feature = "core"))]
+    pub fn reader<'a>(&'a mut self, direction : Direction) -> StreamReader<'a> {
+        StreamReader {
+            output : &mut self.output.data[direction.index()]
+        }
+    }
+}
+
+#[cfg(not(feature = "core"))]
+/// Reads the delivered bytes of one direction
+pub struct StreamReader<'a> {
+    output : &'a mut Vec<u8>
+}
+
+#[cfg(not(feature = "core"))]
+impl<'a> ::std::io::Read for StreamReader<'a> {
+    fn read(&mut self, buf : &mut [u8]) -> ::std::io::Result<usize> {
+        let len = cmp::min(buf.len(), self.output.len());
+        buf[..len].copy_from_slice(&self.output[..len]);
+        self.output.drain(..len);
+        Ok(len)
+    }
+}
@@ -0,0 +1,143 @@
+//! # TCP Authentication Option
+//! Traffic key derivation and MAC verification for TCP-AO (RFC 5925),
+//! using the HMAC-SHA-1-96 and AES-128-CMAC-96 algorithms of RFC 5926.
+
+use std::vec::Vec;
+use super::{TcpSegment, TcpOpts, IPv4PseudoHeader};
+use crypto::sha1::hmac_sha1;
+use crypto::aes::aes_cmac;
+use crypto::constant_time_eq;
+use util::{U32ToU8, U16ToU8};
+
+/// Length of the MAC produced by both RFC 5926 algorithms
+pub const MAC_LEN : usize = 12;
+
+const KDF_LABEL : &'static [u8] = b"TCP-AO";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// The MAC algorithms mandated by RFC 5926
+pub enum MacAlgorithm {
+    /// HMAC-SHA-1-96, with traffic keys derived by KDF_HMAC_SHA1
+    HmacSha1_96,
+    /// AES-128-CMAC-96, with traffic keys derived by KDF_AES_128_CMAC
+    AesCmac128_96
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// A Master Key Tuple, as configured on the endpoint that sent the segments being checked
+pub struct MasterKeyTuple {
+    /// KeyID placed in segments sent with this MKT
+    pub send_id         : u8,
+    /// KeyID expected in segments received with this MKT
+    pub recv_id         : u8,
+    /// The shared master key
+    pub master_key      : Vec<u8>,
+    /// MAC and key derivation algorithm
+    pub algorithm       : MacAlgorithm,
+    /// Whether options other than TCP-AO are covered by the MAC
+    pub include_options : bool
+}
+
+impl MasterKeyTuple {
+    /// Derive the traffic key for segments sent from the source to the destination of
+    /// `pseudo_header`. SYNs use a destination ISN of 0, all other segments use both ISNs.
+    pub fn traffic_key(&self, pseudo_header : IPv4PseudoHeader, src_port : u16, dest_port : u16,
+                       src_isn : u32, dest_isn : u32) -> Vec<u8> {
+        let mut context = Vec::with_capacity(20);
+        context.extend(pseudo_header.source_addr.to_u8().iter());
+        context.extend(pseudo_header.dest_addr.to_u8().iter());
+        context.extend(src_port.to_u8().iter());
+        context.extend(dest_port.to_u8().iter());
+        context.extend(src_isn.to_u8().iter());
+        context.extend(dest_isn.to_u8().iter());
+
+        match self.algorithm {
+            MacAlgorithm::HmacSha1_96 => {
+                let output_len = 160u16;
+                hmac_sha1(&self.master_key, &[&[1], KDF_LABEL, &context, &output_len.to_u8()])
+                    .iter().cloned().collect()
+            },
+            MacAlgorithm::AesCmac128_96 => {
+                // Master keys that are not 128 bits are first reduced with a zero key (RFC 5926 3.1.1.2)
+                let mut key = [0u8; 16];
+                if self.master_key.len() == 16 {
+                    key.copy_from_slice(&self.master_key);
+                } else {
+                    key = aes_cmac(&[0u8; 16], &[&self.master_key]);
+                }
+                let output_len = 128u16;
+                aes_cmac(&key, &[&[1], KDF_LABEL, &context, &output_len.to_u8()])
+                    .iter().cloned().collect()
+            }
+        }
+    }
+}
+
+/// The TCP-AO option carried by a segment, if present
+pub fn auth_option(segment : &TcpSegment) -> Option<(u8, u8, &[u8])> {
+    for opt in segment.options.iter() {
+        if let &TcpOpts::AuthOption{key_id, rnext_key_id, ref mac} = opt {
+            return Some((key_id, rnext_key_id, mac));
+        }
+    }
+    None
+}
+
+/// Calculate the MAC of a segment as specified by RFC 5925 section 5.1: the sequence
+/// number extension, the pseudo header, the header and options with the checksum and
+/// MAC zeroed, and the data.
+pub fn calculate_mac(segment : &TcpSegment, pseudo_header : IPv4PseudoHeader, mkt : &MasterKeyTuple,
+                     traffic_key : &[u8], sne : u32) -> Vec<u8> {
+    let mut canonical = segment.clone();
+    canonical.checksum = 0;
+    canonical.options = segment.options.iter().filter_map(|opt| match opt {
+        &TcpOpts::AuthOption{key_id, rnext_key_id, ref mac} => Some(TcpOpts::AuthOption {
+            key_id:         key_id,
+            rnext_key_id:   rnext_key_id,
+            mac:            mac.iter().map(|_| 0).collect()
+        }),
+        _ if mkt.include_options => Some(opt.clone()),
+        _ => None
+    }).collect();
+
+    let mut pseudo = Vec::with_capacity(12);
+    pseudo.extend(pseudo_header.source_addr.to_u8().iter());
+    pseudo.extend(pseudo_header.dest_addr.to_u8().iter());
+    pseudo.push(0);
+    pseudo.push(pseudo_header.protocol);
+    pseudo.extend(pseudo_header.tcp_len.to_u8().iter());
+
+    let bytes = canonical.as_bytestream();
+    let parts : [&[u8]; 3] = [&sne.to_u8(), &pseudo, &bytes];
+
+    let mut mac : Vec<u8> = match mkt.algorithm {
+        MacAlgorithm::HmacSha1_96 => hmac_sha1(traffic_key, &parts).iter().cloned().collect(),
+        MacAlgorithm::AesCmac128_96 => {
+            let mut key = [0u8; 16];
+            key.copy_from_slice(&traffic_key[..16]);
+            aes_cmac(&key, &parts).iter().cloned().collect()
+        }
+    };
+    mac.truncate(MAC_LEN);
+    mac
+}
+
+/// Check the TCP-AO option of a segment. `src_isn` and `dest_isn` are the initial sequence
+/// numbers of the sender and receiver of this segment, and `sne` the sequence number
+/// extension. Segments without the option, or whose KeyID does not match the MKT, never verify.
+pub fn verify(segment : &TcpSegment, pseudo_header : IPv4PseudoHeader, mkt : &MasterKeyTuple,
+              src_isn : u32, dest_isn : u32, sne : u32) -> bool {
+    let (key_id, mac) = match auth_option(segment) {
+        Some((key_id, _, mac)) => (key_id, mac),
+        None => return false
+    };
+    if key_id != mkt.send_id || mac.len() != MAC_LEN {
+        return false;
+    }
+
+    let dest_isn = if segment.is_syn() { 0 } else { dest_isn };
+
+    let traffic_key = mkt.traffic_key(pseudo_header.clone(), segment.src_port, segment.dest_port,
+                                      src_isn, dest_isn);
+    constant_time_eq(mac, &calculate_mac(segment, pseudo_header, mkt, &traffic_key, sne))
+}
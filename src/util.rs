@@ -27,6 +27,28 @@ impl<'a, T> U8ToU32 for T where T : Iterator<Item=&'a u8>{
     }
 }
 
+pub trait U8ToU64 {
+    fn to_u64(&mut self) -> Option<u64>;
+}
+
+impl<'a, T> U8ToU64 for T where T : Iterator<Item=&'a u8>{
+    fn to_u64(&mut self) -> Option<u64> {
+        let (count, _) = self.size_hint();
+        if count != 8 {
+            None
+        } else {
+            let mut x : u64 = 0;
+            for _ in 0..8 {
+                match self.next() {
+                    Some(&b) => x = (x << 8) | (b as u64),
+                    None => return None
+                }
+            }
+            Some(x)
+        }
+    }
+}
+
 pub trait U8ToU16 {
     fn to_u16(&mut self) -> Option<u16>;
 }
@@ -77,6 +99,20 @@ impl U32ToU8 for u32 {
     }
 }
 
+pub trait U64ToU8 {
+    fn to_u8(&self) -> [u8; 8];
+}
+
+impl U64ToU8 for u64 {
+    fn to_u8(&self) -> [u8; 8]{
+        let mut bytes = [0u8; 8];
+        for i in 0..8 {
+            bytes[i] = (self >> (56 - 8*i)) as u8;
+        }
+        bytes
+    }
+}
+
 pub trait U32ToU16{
     fn to_u16(&self) -> (u16, u16);
 }
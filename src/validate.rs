@@ -0,0 +1,192 @@
+//! # Option Validation
+//! Opt-in checks of the options of a segment against the rules of the RFCs that
+//! define them, which parsing does not enforce. Each violation is reported as a
+//! warning, and the segment is left as it is.
+//!
+//! `validate` checks a parsed segment, and `OptionValidator` adds the checks that
+//! depend on the handshake of the connection. `validate_bytes` checks the length
+//! fields of a raw segment, which catches options that fail to parse.
+//!
+//! # Example
+//! ```rust
+//! use tcp_parser::TcpSegment;
+//! use tcp_parser::validate::{self, OptionWarning};
+//! let segment = TcpSegment::parse(&[0x30, 0x39, 0, 80, 0, 0, 0, 1, 0, 0, 0, 1, 0x60, 0x10, 1, 0, 0, 0, 0, 0,
+//!                                       2, 4, 5, 0xB4]);
+//! assert_eq!(validate::validate(&segment), vec![OptionWarning::SynOnly(2)]);
+//! ```
+
+use std::vec::Vec;
+use super::{TcpSegment, TcpOpts, OptionsIter, END, NOP, MSS, SCALE, SACKPERM, SACK, TIME, SYN};
+use stream::Direction;
+use window::MAX_WINDOW_SHIFT;
+use parser;
+
+/// Most SACK blocks that fit in the options area (RFC 2018 section 3)
+const MAX_SACK_BLOCKS : usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// A rule broken by the options of a segment. Options are identified by kind.
+pub enum OptionWarning {
+    /// An option only allowed on SYNs (MSS, Window Scale, SACK Permitted) on another segment
+    SynOnly(u8),
+    /// An option that appears more than once
+    Duplicate(u8),
+    /// A Window Scale shift above 14
+    ShiftTooLarge(u8),
+    /// A SACK option without blocks, or with more than 4
+    SackBlocks(usize),
+    /// An option whose length field does not fit its kind, e.g. a SACK length that
+    /// is not 2 + 8n. Only found by `validate_bytes`.
+    BadLength {
+        kind    : u8,
+        len     : u8
+    },
+    /// A segment without timestamps, after both SYNs negotiated them (RFC 7323 section 3.2)
+    MissingTimestamp,
+    /// Options following the END option
+    OptionAfterEnd,
+    /// Non-zero bytes following the END option
+    NonZeroPadding
+}
+
+/// Check the options of a segment on their own
+pub fn validate(segment : &TcpSegment) -> Vec<OptionWarning> {
+    let mut warnings = Vec::new();
+    let syn = segment.ctrl_flags.contains(SYN);
+    let mut seen : Vec<u8> = Vec::new();
+    let mut ended = false;
+
+    for opt in segment.options.iter() {
+        if let &TcpOpts::Padding(ref padding) = opt {
+            check_padding(padding, &mut warnings);
+            continue;
+        }
+        let kind = opt.kind().unwrap();
+        if ended && kind != END.bits() {
+            warnings.push(OptionWarning::OptionAfterEnd);
+        }
+        match opt {
+            &TcpOpts::END => ended = true,
+            &TcpOpts::NOP | &TcpOpts::Mptcp(_) => continue,
+            &TcpOpts::MSS(_) | &TcpOpts::SAckPermitted if !syn => warnings.push(OptionWarning::SynOnly(kind)),
+            &TcpOpts::WindowScale(shift) => {
+                if !syn {
+                    warnings.push(OptionWarning::SynOnly(kind));
+                }
+                if shift > MAX_WINDOW_SHIFT {
+                    warnings.push(OptionWarning::ShiftTooLarge(shift));
+                }
+            },
+            &TcpOpts::SAck(ref blocks) if blocks.is_empty() || blocks.len() > MAX_SACK_BLOCKS => {
+                warnings.push(OptionWarning::SackBlocks(blocks.len()));
+            },
+            _ => {}
+        }
+        if kind != END.bits() {
+            if seen.contains(&kind) {
+                warnings.push(OptionWarning::Duplicate(kind));
+            } else {
+                seen.push(kind);
+            }
+        }
+    }
+    warnings
+}
+
+/// Bytes after the END option should be zero. Those that parse as options other
+/// than NOP are reported as such.
+fn check_padding(padding : &[u8], warnings : &mut Vec<OptionWarning>) {
+    let options = OptionsIter::new(padding).any(|opt| match opt {
+        TcpOpts::END | TcpOpts::NOP | TcpOpts::Padding(_) => false,
+        _ => true
+    });
+    if options {
+        warnings.push(OptionWarning::OptionAfterEnd);
+    } else if padding.iter().any(|&byte| byte != 0) {
+        warnings.push(OptionWarning::NonZeroPadding);
+    }
+}
+
+/// Check the length fields of the options of a raw segment, then the options
+/// themselves if the segment parses, reserved bits or not. Returns nothing if the
+/// data offset is malformed.
+pub fn validate_bytes(segment : &[u8]) -> Vec<OptionWarning> {
+    let mut warnings = Vec::new();
+    let header_len = match parser::check_layout(segment) {
+        Ok(len) => len,
+        Err(_) => return warnings
+    };
+
+    let mut area = &segment[parser::HEADER_LEN..header_len];
+    while area.len() >= 2 && area[0] != END.bits() {
+        if area[0] == NOP.bits() {
+            area = &area[1..];
+            continue;
+        }
+        let (kind, len) = (area[0], area[1]);
+        let valid = match kind {
+            k if k == MSS.bits()      => len == 4,
+            k if k == SCALE.bits()    => len == 3,
+            k if k == SACKPERM.bits() => len == 2,
+            k if k == SACK.bits()     => len >= 2 && (len - 2) % 8 == 0,
+            k if k == TIME.bits()     => len == 10,
+            _ => len >= 2
+        };
+        if !valid {
+            warnings.push(OptionWarning::BadLength { kind: kind, len: len });
+        }
+        if len < 2 || len as usize > area.len() {
+            break;
+        }
+        area = &area[len as usize..];
+    }
+
+    if let Ok(parsed) = TcpSegment::try_parse_lenient(segment) {
+        warnings.extend(validate(&parsed));
+    }
+    warnings
+}
+
+/// Validates the segments of one connection, following whether its SYNs negotiated timestamps
+pub struct OptionValidator {
+    /// Whether the SYN sent in each direction carried a timestamp, once seen
+    timestamps : [Option<bool>; 2]
+}
+
+impl Default for OptionValidator {
+    fn default() -> OptionValidator {
+        OptionValidator::new()
+    }
+}
+
+impl OptionValidator {
+    pub fn new() -> OptionValidator {
+        OptionValidator {
+            timestamps : [None, None]
+        }
+    }
+
+    /// Whether both SYNs carried a timestamp
+    pub fn timestamps_negotiated(&self) -> bool {
+        self.timestamps == [Some(true), Some(true)]
+    }
+
+    /// Check a segment sent in the given direction
+    pub fn push(&mut self, direction : Direction, segment : &TcpSegment) -> Vec<OptionWarning> {
+        let mut warnings = validate(segment);
+        let timestamp = segment.options.iter().any(|opt| match opt {
+            &TcpOpts::TimeStamp { .. } => true,
+            _ => false
+        });
+
+        if segment.ctrl_flags.contains(SYN) {
+            if self.timestamps[direction.index()].is_none() {
+                self.timestamps[direction.index()] = Some(timestamp);
+            }
+        } else if !segment.is_rst() && !timestamp && self.timestamps_negotiated() {
+            warnings.push(OptionWarning::MissingTimestamp);
+        }
+        warnings
+    }
+}
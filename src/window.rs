@@ -0,0 +1,190 @@
+//! # Receive Window Tracking
+//! Follows the window scaling (RFC 7323) negotiated in the handshake of a connection,
+//! to give the effective receive window advertised by each segment, and the zero
+//! window episodes of each direction. Scaling is in effect only if both the SYN and
+//! the SYN-ACK carry a Window Scale option, each giving the shift that applies to
+//! the windows its sender advertises, and never to the windows of SYNs themselves.
+//! Until both are seen, windows are taken as unscaled.
+//!
+//! # Example
+//! ```rust
+//! use tcp_parser::TcpSegment;
+//! use tcp_parser::stream::Direction;
+//! use tcp_parser::window::WindowTracker;
+//! let mut tracker = WindowTracker::new();
+//! let syn = TcpSegment::parse(&[0x30, 0x39, 0, 80, 0, 0, 0, 1, 0, 0, 0, 0, 0x60, 0x02, 0xFF, 0xFF, 0, 0, 0, 0,
+//!                               1, 3, 3, 7]);
+//! let syn_ack = TcpSegment::parse(&[0, 80, 0x30, 0x39, 0, 0, 0, 1, 0, 0, 0, 2, 0x60, 0x12, 0xFF, 0xFF, 0, 0, 0, 0,
+//!                                   1, 3, 3, 2]);
+//! tracker.push(Direction::ClientToServer, &syn, 0);
+//! tracker.push(Direction::ServerToClient, &syn_ack, 10);
+//! let ack = TcpSegment::parse(&[0x30, 0x39, 0, 80, 0, 0, 0, 2, 0, 0, 0, 2, 0x50, 0x10, 0x01, 0xF6, 0, 0, 0, 0]);
+//! assert_eq!(tracker.effective_window(Direction::ClientToServer, &ack), 502 << 7);
+//! ```
+
+use std::vec::Vec;
+use super::{TcpSegment, TcpOpts, SYN, RST};
+use stream::Direction;
+
+/// Largest shift allowed by RFC 7323 section 2.3
+pub const MAX_WINDOW_SHIFT : u8 = 14;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// A period during which a direction advertised a zero window
+pub struct ZeroWindow {
+    pub start   : u64,
+    /// When the window opened again, or None while it is still closed
+    pub end     : Option<u64>
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// What a segment revealed about the windows of a connection
+pub enum WindowEvent {
+    /// A Window Scale option offered a shift above 14, which is taken as 14
+    ShiftTooLarge {
+        direction   : Direction,
+        shift       : u8
+    },
+    /// Only one end offered window scaling, so neither direction scales
+    ScalingRefused {
+        offered_by  : Direction
+    },
+    /// A retransmitted SYN offered another shift than the first, which is kept
+    ShiftChanged {
+        direction   : Direction,
+        first       : Option<u8>,
+        second      : Option<u8>
+    },
+    /// A Window Scale option outside a SYN, which is ignored
+    ShiftOutsideSyn(Direction),
+    /// The first segment seen is not part of the handshake, so the scaling is unknown
+    HandshakeMissed,
+    /// The direction started advertising a zero window
+    ZeroWindowStart(Direction),
+    /// The direction opened its window again after this many milliseconds
+    ZeroWindowEnd(Direction, u64)
+}
+
+/// What one direction advertised
+struct Flow {
+    /// The shift offered by the SYN sent, once one is seen
+    offered      : Option<Option<u8>>,
+    zero_windows : Vec<ZeroWindow>
+}
+
+impl Flow {
+    fn new() -> Flow {
+        Flow {
+            offered      : None,
+            zero_windows : Vec::new()
+        }
+    }
+
+    fn zero_since(&self) -> Option<u64> {
+        match self.zero_windows.last() {
+            Some(&ZeroWindow { start, end: None }) => Some(start),
+            _ => None
+        }
+    }
+}
+
+/// Tracks the windows of both directions of one connection
+pub struct WindowTracker {
+    flows   : [Flow; 2],
+    started : bool
+}
+
+impl Default for WindowTracker {
+    fn default() -> WindowTracker {
+        WindowTracker::new()
+    }
+}
+
+impl WindowTracker {
+    pub fn new() -> WindowTracker {
+        WindowTracker {
+            flows   : [Flow::new(), Flow::new()],
+            started : false
+        }
+    }
+
+    /// Feed a segment sent in the given direction at time `now`, in milliseconds
+    pub fn push(&mut self, direction : Direction, segment : &TcpSegment, now : u64) -> Vec<WindowEvent> {
+        let mut events = Vec::new();
+        let syn = segment.ctrl_flags.contains(SYN);
+        let offer = segment.options.iter().filter_map(|opt| match opt {
+            &TcpOpts::WindowScale(shift) => Some(shift),
+            _ => None
+        }).next();
+
+        if !self.started && !syn {
+            events.push(WindowEvent::HandshakeMissed);
+        }
+        self.started = true;
+
+        if syn {
+            if let Some(shift) = offer {
+                if shift > MAX_WINDOW_SHIFT {
+                    events.push(WindowEvent::ShiftTooLarge { direction: direction, shift: shift });
+                }
+            }
+            let offered = offer.map(|shift| if shift > MAX_WINDOW_SHIFT { MAX_WINDOW_SHIFT } else { shift });
+            match self.flows[direction.index()].offered {
+                None => {
+                    self.flows[direction.index()].offered = Some(offered);
+                    if let (Some(a), Some(b)) = (self.flows[0].offered, self.flows[1].offered) {
+                        if a.is_some() != b.is_some() {
+                            let offered_by = if a.is_some() { Direction::ClientToServer } else { Direction::ServerToClient };
+                            events.push(WindowEvent::ScalingRefused { offered_by: offered_by });
+                        }
+                    }
+                },
+                Some(first) if first != offered => {
+                    events.push(WindowEvent::ShiftChanged { direction: direction, first: first, second: offered });
+                },
+                Some(_) => {}
+            }
+        } else if offer.is_some() {
+            events.push(WindowEvent::ShiftOutsideSyn(direction));
+        }
+
+        if !segment.ctrl_flags.contains(RST) {
+            let flow = &mut self.flows[direction.index()];
+            match (segment.window == 0, flow.zero_since()) {
+                (true, None) => {
+                    flow.zero_windows.push(ZeroWindow { start: now, end: None });
+                    events.push(WindowEvent::ZeroWindowStart(direction));
+                },
+                (false, Some(start)) => {
+                    flow.zero_windows.last_mut().unwrap().end = Some(now);
+                    events.push(WindowEvent::ZeroWindowEnd(direction, now.saturating_sub(start)));
+                },
+                _ => {}
+            }
+        }
+        events
+    }
+
+    /// The shift applied to the windows advertised in a direction: None until both
+    /// SYNs are seen, and zero unless both offered scaling
+    pub fn shift(&self, direction : Direction) -> Option<u8> {
+        match (self.flows[0].offered, self.flows[1].offered) {
+            (Some(Some(_)), Some(Some(_))) => self.flows[direction.index()].offered.unwrap(),
+            (Some(_), Some(_)) => Some(0),
+            _ => None
+        }
+    }
+
+    /// The receive window advertised by a segment sent in the given direction, in bytes
+    pub fn effective_window(&self, direction : Direction, segment : &TcpSegment) -> u32 {
+        if segment.ctrl_flags.contains(SYN) {
+            return segment.window as u32;
+        }
+        (segment.window as u32) << self.shift(direction).unwrap_or(0)
+    }
+
+    /// The zero window episodes of a direction, in order
+    pub fn zero_windows(&self, direction : Direction) -> &[ZeroWindow] {
+        &self.flows[direction.index()].zero_windows
+    }
+}
@@ -0,0 +1,101 @@
+extern crate tcp_parser;
+use tcp_parser::{TcpSegment, TcpSegmentBuilder, TcpCTRL, TcpOpts, IPv4PseudoHeader, SYN, ACK, PSH};
+use tcp_parser::analysis::*;
+
+use tcp_parser::stream::Direction::{ClientToServer, ServerToClient};
+
+fn segment(seq : u32, ack : u32, flags : TcpCTRL, window : u16, data : &[u8]) -> TcpSegment {
+    TcpSegmentBuilder::new()
+        .src_port(40000)
+        .dest_port(80)
+        .seq_num(seq)
+        .ack_num(ack)
+        .flags(flags)
+        .window(window)
+        .data(data.to_vec())
+        .build(IPv4PseudoHeader { source_addr: 1, dest_addr: 2, protocol: 6, tcp_len: 0 })
+        .unwrap()
+}
+
+fn handshake() -> SequenceAnalyzer {
+    let mut analyzer = SequenceAnalyzer::new(AnalysisConfig::default());
+    analyzer.push(ClientToServer, &segment(0, 0, SYN, 1000, b""), 0);
+    analyzer.push(ServerToClient, &segment(0, 1, SYN | ACK, 1000, b""), 10);
+    analyzer.push(ClientToServer, &segment(1, 1, ACK, 1000, b""), 20);
+    analyzer
+}
+
+#[test]
+fn test_retransmissions_and_dup_acks(){
+    let mut analyzer = handshake();
+    let data = [0u8; 100];
+    for i in 0..4 {
+        analyzer.push(ClientToServer, &segment(1 + i * 100, 1, ACK | PSH, 1000, &data), 100);
+    }
+
+    // The first segment is lost: three duplicates of the SYN-ACK's ACK, then the fast retransmission
+    for count in 1..4 {
+        let analysis = analyzer.push(ServerToClient, &segment(1, 1, ACK, 1000, b""), 110 + count as u64);
+        assert_eq!((analysis.flags, analysis.dup_ack_count), (DUPLICATE_ACK, count));
+    }
+    // A window update is not a duplicate
+    assert_eq!(analyzer.push(ServerToClient, &segment(1, 1, ACK, 2000, b""), 114).flags, AnalysisFlags::empty());
+    assert_eq!(analyzer.push(ClientToServer, &segment(1, 1, ACK | PSH, 1000, &data), 120).flags,
+               RETRANSMISSION | FAST_RETRANSMISSION);
+    analyzer.push(ServerToClient, &segment(1, 401, ACK, 2000, b""), 130);
+
+    // Data that was already acknowledged, then data that was not
+    assert_eq!(analyzer.push(ClientToServer, &segment(101, 1, ACK | PSH, 1000, &data), 400).flags,
+               RETRANSMISSION | SPURIOUS_RETRANSMISSION);
+    analyzer.push(ClientToServer, &segment(401, 1, ACK | PSH, 1000, &data), 500);
+    assert_eq!(analyzer.push(ClientToServer, &segment(401, 1, ACK | PSH, 1000, &data), 800).flags, RETRANSMISSION);
+
+    // A retransmitted SYN
+    let mut analyzer = SequenceAnalyzer::new(AnalysisConfig::default());
+    analyzer.push(ClientToServer, &segment(0, 0, SYN, 1000, b""), 0);
+    assert_eq!(analyzer.push(ClientToServer, &segment(0, 0, SYN, 1000, b""), 1000).flags, RETRANSMISSION);
+
+    let stats = analyzer.stats(ClientToServer);
+    assert_eq!((stats.retransmissions, stats.fast_retransmissions), (1, 0));
+}
+
+#[test]
+fn test_out_of_order_and_windows(){
+    let mut analyzer = handshake();
+    analyzer.push(ClientToServer, &segment(101, 1, ACK, 1000, &[0; 100]), 100);
+    assert_eq!(analyzer.push(ClientToServer, &segment(1, 1, ACK, 1000, &[0; 100]), 101).flags, OUT_OF_ORDER);
+
+    // Filling the window the server advertised, then probing it once it is zero
+    assert_eq!(analyzer.push(ClientToServer, &segment(201, 1, ACK, 1000, &[0; 800]), 102).flags, WINDOW_FULL);
+    analyzer.push(ServerToClient, &segment(1, 1001, ACK, 0, b""), 110);
+    assert_eq!(analyzer.push(ClientToServer, &segment(1001, 1, ACK, 1000, &[0]), 500).flags, ZERO_WINDOW_PROBE);
+
+    // Keep-alives, empty or with one garbage byte
+    analyzer.push(ServerToClient, &segment(1, 1002, ACK, 1000, b""), 600);
+    assert_eq!(analyzer.push(ClientToServer, &segment(1001, 1, ACK, 1000, b""), 10000).flags, KEEP_ALIVE);
+    assert_eq!(analyzer.push(ClientToServer, &segment(1001, 1, ACK, 1000, &[0]), 20000).flags, KEEP_ALIVE);
+
+    let stats = analyzer.stats(ClientToServer);
+    assert_eq!((stats.out_of_order, stats.window_full, stats.zero_window_probes, stats.keep_alives), (1, 1, 1, 2));
+    assert_eq!(stats.retransmissions, 0);
+}
+
+#[test]
+fn test_scaled_window_full(){
+    let syn = |flags : TcpCTRL, shift : u8| TcpSegmentBuilder::new()
+        .seq_num(0)
+        .ack_num(if flags.contains(ACK) { 1 } else { 0 })
+        .flags(flags)
+        .window(65535)
+        .option(TcpOpts::NOP)
+        .option(TcpOpts::WindowScale(shift))
+        .build(IPv4PseudoHeader { source_addr: 1, dest_addr: 2, protocol: 6, tcp_len: 0 })
+        .unwrap();
+    let mut analyzer = SequenceAnalyzer::new(AnalysisConfig::default());
+    analyzer.push(ClientToServer, &syn(SYN, 2), 0);
+    analyzer.push(ServerToClient, &syn(SYN | ACK, 7), 10);
+    // The server advertises 8 << 7 bytes, which the client fills
+    analyzer.push(ServerToClient, &segment(1, 1, ACK, 8, b""), 20);
+    assert_eq!(analyzer.push(ClientToServer, &segment(1, 1, ACK, 1000, &[0; 8]), 30).flags, AnalysisFlags::empty());
+    assert_eq!(analyzer.push(ClientToServer, &segment(9, 1, ACK, 1000, &[0; 1016]), 40).flags, WINDOW_FULL);
+}
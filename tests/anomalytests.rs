@@ -0,0 +1,51 @@
+extern crate tcp_parser;
+use tcp_parser::{TcpSegment, TcpOpts, TcpParseError};
+use tcp_parser::anomaly::{self, Anomaly, AnomalyCategory, AnomalyDetector};
+
+/// A segment from port 12345 to port 80 with the given flags and acknowledgement number
+fn segment(flags : u8, ack : u8) -> TcpSegment {
+    TcpSegment::parse(&[0x30, 0x39, 0, 80, 0, 0, 0, 1, 0, 0, 0, ack, 0x50, flags, 4, 0, 0, 0, 0, 0])
+}
+
+#[test]
+fn test_scans(){
+    assert_eq!(anomaly::detect(&segment(0x00, 0)), vec![Anomaly::NullScan]);
+    // ECE and CWR alone are still no control flags
+    assert_eq!(anomaly::detect(&segment(0xC0, 0)), vec![Anomaly::NullScan]);
+    assert_eq!(anomaly::detect(&segment(0x01, 0)), vec![Anomaly::FinScan]);
+    assert_eq!(anomaly::detect(&segment(0x29, 7)), vec![Anomaly::XmasScan, Anomaly::AckNumberWithoutAck]);
+    assert_eq!(anomaly::detect(&segment(0x02, 0)), vec![]);
+    // Every flag set is no Xmas scan, but it is a SYN with FIN and RST
+    let anomalies = anomaly::detect(&segment(0x3F, 1));
+    assert_eq!(anomalies, vec![Anomaly::SynFin, Anomaly::SynRst]);
+    assert!(anomalies.iter().all(|anomaly| anomaly.category() == AnomalyCategory::IllegalFlags));
+
+    // A FIN-ACK is only a Maimon scan when it opens the connection
+    let mut detector = AnomalyDetector::new();
+    assert_eq!(detector.push(&segment(0x11, 1)), vec![Anomaly::MaimonScan]);
+    let mut detector = AnomalyDetector::new();
+    detector.push(&segment(0x10, 1));
+    assert_eq!(detector.push(&segment(0x11, 1)), vec![]);
+}
+
+#[test]
+fn test_header_fields(){
+    let mut syn = segment(0x02, 0);
+    syn.data = b"hello".to_vec();
+    syn.urg_ptr = 3;
+    syn.src_port = 0;
+    assert_eq!(anomaly::detect(&syn), vec![Anomaly::DataOnSyn, Anomaly::UrgentPointerWithoutUrg,
+                                           Anomaly::ZeroSourcePort]);
+    syn.options = vec![TcpOpts::FastOpenCookie(vec![1, 2, 3, 4, 5, 6, 7, 8])];
+    syn.urg_ptr = 0;
+    assert_eq!(anomaly::detect(&syn), vec![Anomaly::ZeroSourcePort]);
+
+    // Reserved bits are rejected by strict parsing, but not here
+    let bytes = [0x30, 0x39, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0x5A, 0x02, 4, 0, 0, 0, 0, 0];
+    assert_eq!(TcpSegment::try_parse(&bytes[..]), Err(TcpParseError::InvalidReserved(12)));
+    let anomalies = anomaly::detect_bytes(&bytes).unwrap();
+    assert_eq!(anomalies, vec![Anomaly::ReservedBits(0b101), Anomaly::ZeroDestPort]);
+    assert_eq!(anomaly::detect(&TcpSegment::try_parse_lenient(&bytes[..]).unwrap()), anomalies);
+    assert_eq!(format!("{}", anomalies[0]), "reserved bits 0b101");
+    assert_eq!(anomaly::detect_bytes(&bytes[..12]), Err(TcpParseError::InvalidLength(12)));
+}
@@ -0,0 +1,92 @@
+extern crate tcp_parser;
+use tcp_parser::{TcpSegment, TcpSegmentBuilder, TcpCTRL, IPv4PseudoHeader, SYN, ACK, FIN, RST, PSH};
+use tcp_parser::ip::IpAddress;
+use tcp_parser::conntrack::{ConnectionTable, ConnectionConfig, CloseReason, Endpoint};
+
+use tcp_parser::stream::Direction::{ClientToServer, ServerToClient};
+
+const CLIENT : IpAddress = IpAddress::V4(0xC0A80001);
+const SERVER : IpAddress = IpAddress::V4(0xC0A80002);
+
+fn segment(src_port : u16, dest_port : u16, flags : TcpCTRL, data : &[u8]) -> TcpSegment {
+    TcpSegmentBuilder::new()
+        .src_port(src_port)
+        .dest_port(dest_port)
+        .seq_num(1)
+        .ack_num(1)
+        .flags(flags)
+        .window(65535)
+        .data(data.to_vec())
+        .build(IPv4PseudoHeader { source_addr: 1, dest_addr: 2, protocol: 6, tcp_len: 0 })
+        .unwrap()
+}
+
+#[test]
+fn test_directions_and_counters(){
+    let mut table = ConnectionTable::new(ConnectionConfig::default());
+    let (id, direction) = table.push(&segment(40000, 80, SYN, b""), CLIENT, SERVER, 0);
+    assert_eq!(direction, ClientToServer);
+    assert_eq!(table.push(&segment(80, 40000, SYN | ACK, b""), SERVER, CLIENT, 1), (id, ServerToClient));
+    table.push(&segment(40000, 80, ACK | PSH, b"GET /"), CLIENT, SERVER, 2);
+    table.push(&segment(80, 40000, ACK | PSH, b"200 OK\r\n"), SERVER, CLIENT, 3);
+
+    // Another client port, and the same ports between other hosts, are other connections
+    let (other, _) = table.push(&segment(40001, 80, SYN, b""), CLIENT, SERVER, 4);
+    let (third, _) = table.push(&segment(40000, 80, SYN, b""), CLIENT, IpAddress::V4(0xC0A80003), 4);
+    assert!(other != id && third != id && other != third);
+    assert_eq!(table.len(), 3);
+
+    let conn = table.get(id).unwrap();
+    assert_eq!(conn.client, Endpoint { addr: CLIENT, port: 40000 });
+    assert_eq!(conn.server, Endpoint { addr: SERVER, port: 80 });
+    assert_eq!((conn.first_seen, conn.last_seen), (0, 3));
+    assert_eq!((conn.counters(ClientToServer).packets, conn.counters(ClientToServer).bytes), (2, 5));
+    assert_eq!((conn.counters(ServerToClient).packets, conn.counters(ServerToClient).bytes), (2, 8));
+
+    // Without the SYN, a SYN-ACK tells which end is the server
+    let v6 = IpAddress::V6([0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01]);
+    let (id, direction) = table.push(&segment(443, 50000, SYN | ACK, b""), v6, CLIENT, 5);
+    assert_eq!(direction, ServerToClient);
+    assert_eq!(table.get(id).unwrap().server, Endpoint { addr: v6, port: 443 });
+}
+
+#[test]
+fn test_close_and_port_reuse(){
+    let config = ConnectionConfig { idle_timeout: 1000, time_wait: 100 };
+    let mut table = ConnectionTable::new(config);
+    let (first, _) = table.push(&segment(40000, 80, SYN, b""), CLIENT, SERVER, 0);
+    table.push(&segment(40000, 80, FIN | ACK, b""), CLIENT, SERVER, 10);
+    table.push(&segment(80, 40000, FIN | ACK, b""), SERVER, CLIENT, 20);
+    assert_eq!(table.get(first).unwrap().close_reason, Some(CloseReason::Fin));
+
+    // The last ACK still belongs to the closing connection
+    assert_eq!(table.push(&segment(40000, 80, ACK, b""), CLIENT, SERVER, 30).0, first);
+    assert_eq!(table.closed().count(), 0);
+
+    // A new SYN within TIME_WAIT reuses the ports
+    let (second, _) = table.push(&segment(40000, 80, SYN, b""), CLIENT, SERVER, 40);
+    assert!(second != first);
+    assert_eq!(table.closed().map(|conn| conn.id).collect::<Vec<u64>>(), vec![first]);
+    assert_eq!(table.get(first), None);
+    assert_eq!(table.get(second).unwrap().counters(ClientToServer).packets, 1);
+
+    // A RST closes it, and it leaves the table once TIME_WAIT is over
+    table.push(&segment(80, 40000, RST, b""), SERVER, CLIENT, 50);
+    table.expire(149);
+    assert_eq!(table.len(), 1);
+    table.expire(150);
+    assert!(table.is_empty());
+
+    // Idle connections expire
+    let (third, _) = table.push(&segment(40000, 80, ACK, b"x"), CLIENT, SERVER, 200);
+    table.push(&segment(40001, 80, SYN, b""), CLIENT, SERVER, 1199);
+    assert_eq!(table.active().map(|conn| conn.id).collect::<Vec<u64>>(), vec![third, third + 1]);
+    table.push(&segment(40001, 80, ACK, b""), CLIENT, SERVER, 1200);
+    assert_eq!(table.len(), 1);
+
+    let closed = table.take_closed();
+    assert_eq!(closed.iter().map(|conn| (conn.id, conn.close_reason)).collect::<Vec<_>>(),
+               vec![(first, Some(CloseReason::Fin)), (second, Some(CloseReason::Reset)),
+                    (third, Some(CloseReason::Expired))]);
+    assert_eq!(table.closed().count(), 0);
+}
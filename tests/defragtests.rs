@@ -0,0 +1,265 @@
+extern crate tcp_parser;
+use tcp_parser::{TcpSegment, TcpSegmentBuilder, IPv4PseudoHeader, IPv6PseudoHeader, ACK, PSH};
+use tcp_parser::ip::{self, IpHeader};
+use tcp_parser::defrag::{IPv4Defragmenter, IPv6Defragmenter, DefragConfig, DefragStats, OverlapPolicy};
+
+const CLIENT_V6 : [u8; 16] = [0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01];
+const SERVER_V6 : [u8; 16] = [0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x02];
+
+fn fragment(id : u16, offset : usize, more : bool, payload : &[u8]) -> Vec<u8> {
+    let total_len = 20 + payload.len();
+    let frag = (if more { 0x2000 } else { 0 }) | (offset / 8) as u16;
+    let mut packet = vec![0x45, 0, (total_len >> 8) as u8, total_len as u8,
+                          (id >> 8) as u8, id as u8, (frag >> 8) as u8, frag as u8, 64, 6, 0, 0,
+                          172, 16, 0, 1, 172, 16, 0, 2];
+    packet.extend(payload.iter());
+    packet
+}
+
+/// An IPv6 fragment behind a Hop-by-Hop Options header
+fn fragment_v6(id : u32, offset : usize, more : bool, next_header : u8, payload : &[u8]) -> Vec<u8> {
+    fragment_v6_behind(&[44, 0, 0x01, 0x04, 0x00, 0x00, 0x00, 0x00], id, offset, more, next_header, payload)
+}
+
+/// An IPv6 fragment behind the given Hop-by-Hop Options header, if any
+fn fragment_v6_behind(hop_by_hop : &[u8], id : u32, offset : usize, more : bool, next_header : u8,
+                      payload : &[u8]) -> Vec<u8> {
+    let payload_len = hop_by_hop.len() + 8 + payload.len();
+    let frag = offset as u16 | if more { 1 } else { 0 };
+    let first_header = if hop_by_hop.is_empty() { 44 } else { 0 };
+    let mut packet = vec![0x60, 0, 0, 0, (payload_len >> 8) as u8, payload_len as u8, first_header, 64];
+    packet.extend(CLIENT_V6.iter());
+    packet.extend(SERVER_V6.iter());
+    packet.extend(hop_by_hop.iter());
+    packet.extend([next_header, 0, (frag >> 8) as u8, frag as u8,
+                   (id >> 24) as u8, (id >> 16) as u8, (id >> 8) as u8, id as u8].iter());
+    packet.extend(payload.iter());
+    packet
+}
+
+fn push_v6(defrag : &mut IPv6Defragmenter, packet : &[u8], now : u64) -> Option<Vec<u8>> {
+    defrag.push(&ip::parse_ip(packet).unwrap(), now).map(|datagram| datagram.payload)
+}
+
+fn push(defrag : &mut IPv4Defragmenter, packet : &[u8], now : u64) -> Option<Vec<u8>> {
+    defrag.push(&ip::parse_ip(packet).unwrap(), now).map(|datagram| datagram.payload)
+}
+
+/// Feed fragments of (offset, letter, last) and return the datagram as letters per 8 bytes
+fn reassemble(policy : OverlapPolicy, fragments : &[(usize, &str, bool)]) -> String {
+    let mut defrag = IPv4Defragmenter::new(DefragConfig { policy: policy, ..DefragConfig::default() });
+    let mut result = None;
+    for &(offset, letters, last) in fragments.iter() {
+        let payload : Vec<u8> = letters.bytes().flat_map(|b| vec![b; 8]).collect();
+        result = push(&mut defrag, &fragment(1, offset, !last, &payload), 0);
+    }
+    result.unwrap().chunks(8).map(|chunk| chunk[0] as char).collect()
+}
+
+#[test]
+fn test_reassemble_tcp(){
+    let pseudo_header = IPv4PseudoHeader {
+        source_addr : 0xAC100001,
+        dest_addr   : 0xAC100002,
+        protocol    : 6,
+        tcp_len     : 0
+    };
+    let segment = TcpSegmentBuilder::new()
+        .src_port(4500)
+        .dest_port(179)
+        .seq_num(1000)
+        .ack_num(2000)
+        .flags(ACK | PSH)
+        .window(16384)
+        .data((0..100).collect::<Vec<u8>>())
+        .build(pseudo_header)
+        .unwrap();
+    let tcp = segment.as_bytestream();
+
+    let mut defrag = IPv4Defragmenter::new(DefragConfig::default());
+    assert_eq!(push(&mut defrag, &fragment(42, 96, false, &tcp[96..]), 0), None);
+    assert_eq!(push(&mut defrag, &fragment(42, 0, true, &tcp[..48]), 1), None);
+    assert_eq!(defrag.pending(), 1);
+    assert_eq!(defrag.memory(), tcp.len() - 48);
+
+    let datagram = defrag.push(&ip::parse_ip(&fragment(42, 48, true, &tcp[48..96])).unwrap(), 2).unwrap();
+    let packet = datagram.as_packet();
+    assert!(!packet.is_fragment());
+    let (payload, pseudo_header) = packet.tcp().unwrap();
+    assert_eq!(TcpSegment::parse(payload), segment);
+    assert!(segment.verify_checksum(pseudo_header));
+
+    assert_eq!(defrag.pending(), 0);
+    assert_eq!(defrag.memory(), 0);
+    assert_eq!(defrag.stats(), DefragStats { fragments: 3, reassembled: 1, ..DefragStats::default() });
+
+    // Whole datagrams pass straight through
+    let whole = fragment(43, 0, false, &tcp);
+    assert_eq!(push(&mut defrag, &whole, 3), Some(tcp));
+}
+
+#[test]
+fn test_overlap_policies(){
+    // A new fragment at the same offset as an earlier one
+    let same_offset = [(8, "A", false), (8, "C", false), (0, "B", false), (16, "D", true)];
+    // A new fragment starting before an earlier one
+    let lower_offset = [(8, "A", false), (0, "BB", false), (16, "D", true)];
+    // A new fragment starting after an earlier one
+    let higher_offset = [(0, "AA", false), (8, "C", false), (16, "D", true)];
+
+    let results = |policy| (reassemble(policy, &same_offset),
+                            reassemble(policy, &lower_offset),
+                            reassemble(policy, &higher_offset));
+    assert_eq!(results(OverlapPolicy::First), ("BAD".to_string(), "BAD".to_string(), "AAD".to_string()));
+    assert_eq!(results(OverlapPolicy::Last),  ("BCD".to_string(), "BBD".to_string(), "ACD".to_string()));
+    assert_eq!(results(OverlapPolicy::Bsd),   ("BAD".to_string(), "BBD".to_string(), "AAD".to_string()));
+    assert_eq!(results(OverlapPolicy::Linux), ("BCD".to_string(), "BBD".to_string(), "AAD".to_string()));
+}
+
+#[test]
+fn test_defrag_limits(){
+    let config = DefragConfig { timeout: 1000, max_memory: 24, policy: OverlapPolicy::First };
+    let mut defrag = IPv4Defragmenter::new(config);
+
+    assert_eq!(push(&mut defrag, &fragment(1, 0, true, &[1; 16]), 0), None);
+    assert_eq!(push(&mut defrag, &fragment(2, 0, true, &[2; 8]), 500), None);
+    assert_eq!(defrag.memory(), 24);
+
+    // Datagram 1 is the oldest and is evicted to make room
+    assert_eq!(push(&mut defrag, &fragment(3, 0, true, &[3; 8]), 600), None);
+    assert_eq!(defrag.pending(), 2);
+    assert_eq!(defrag.memory(), 16);
+
+    // Datagram 2 times out before its last fragment arrives
+    assert_eq!(push(&mut defrag, &fragment(2, 8, false, &[2; 4]), 1500), None);
+    assert_eq!(push(&mut defrag, &fragment(3, 8, false, &[3; 4]), 1550),
+               Some(vec![3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3]));
+
+    // Fragments that are not a multiple of 8 bytes, or would exceed 65535 bytes
+    assert_eq!(push(&mut defrag, &fragment(4, 0, true, &[4; 12]), 1600), None);
+    assert_eq!(push(&mut defrag, &fragment(5, 65520, false, &[5; 8]), 1600), None);
+    // A last fragment ending before data already received
+    assert_eq!(push(&mut defrag, &fragment(6, 16, true, &[6; 8]), 1600), None);
+    assert_eq!(push(&mut defrag, &fragment(6, 8, false, &[6; 4]), 1600), None);
+
+    let stats = defrag.stats();
+    assert_eq!((stats.evicted, stats.timed_out, stats.reassembled, stats.invalid), (1, 1, 1, 3));
+    // Only the late last fragment of datagram 2 is left
+    assert_eq!(defrag.pending(), 1);
+    assert_eq!(defrag.memory(), 4);
+}
+
+#[test]
+fn test_defrag_first_header_len(){
+    let mut defrag = IPv4Defragmenter::new(DefragConfig::default());
+    // The last fragment fits behind its own 20 byte header
+    assert_eq!(push(&mut defrag, &fragment(7, 65488, false, &[2; 8]), 0), None);
+    assert_eq!(defrag.pending(), 1);
+
+    // but not behind the 60 bytes of the first, whose data starts with 40 bytes of NOP options
+    let mut first = fragment(7, 0, true, &[1; 48]);
+    first[0] = 0x4F;
+    assert_eq!(push(&mut defrag, &first, 1), None);
+    assert_eq!(defrag.stats().invalid, 1);
+    assert_eq!((defrag.pending(), defrag.memory()), (0, 0));
+}
+
+#[test]
+fn test_reassemble_ipv6_tcp(){
+    let segment = TcpSegmentBuilder::new()
+        .src_port(50000)
+        .dest_port(443)
+        .seq_num(7)
+        .ack_num(9)
+        .flags(ACK)
+        .window(1024)
+        .data((0..120).collect::<Vec<u8>>())
+        .build(IPv6PseudoHeader {
+            source_addr : CLIENT_V6,
+            dest_addr   : SERVER_V6,
+            tcp_len     : 0,
+            next_header : 6
+        })
+        .unwrap();
+
+    // A Destination Options header in the fragmentable part, before the segment
+    let mut fragmentable = vec![6, 0, 0x01, 0x04, 0x00, 0x00, 0x00, 0x00];
+    fragmentable.extend(segment.as_bytestream());
+
+    let mut defrag = IPv6Defragmenter::new(DefragConfig::default());
+    assert_eq!(push_v6(&mut defrag, &fragment_v6(0x1234, 128, false, 60, &fragmentable[128..]), 0), None);
+    assert_eq!(push_v6(&mut defrag, &fragment_v6(0x1234, 0, true, 60, &fragmentable[..64]), 5), None);
+
+    let last = fragment_v6(0x1234, 64, true, 60, &fragmentable[64..128]);
+    let datagram = defrag.push(&ip::parse_ip(&last).unwrap(), 6).unwrap();
+    match datagram.header {
+        IpHeader::V6(ref header) => {
+            assert_eq!(header.extension_headers, vec![0, 60]);
+            assert_eq!(header.fragment, None);
+            assert_eq!(header.payload_len as usize, 8 + fragmentable.len());
+        },
+        _ => panic!("expected IPv6")
+    }
+    let packet = datagram.as_packet();
+    let (tcp, pseudo_header) = packet.tcp().unwrap();
+    assert_eq!(TcpSegment::parse(tcp), segment);
+    assert!(segment.verify_checksum(pseudo_header));
+    assert_eq!(defrag.stats().reassembled, 1);
+}
+
+#[test]
+fn test_ipv6_unfragmentable_len(){
+    let mut defrag = IPv6Defragmenter::new(DefragConfig::default());
+    // A last fragment without extension headers, ending at 65528
+    assert_eq!(push_v6(&mut defrag, &fragment_v6_behind(&[], 3, 65520, false, 6, &[2; 8]), 0), None);
+
+    // A first fragment behind 1008 bytes of Hop-by-Hop options, padded with PadN
+    let mut hop_by_hop = vec![44, 125];
+    for _ in 0..4 {
+        hop_by_hop.extend([0x01, 249].iter());
+        hop_by_hop.extend([0; 249].iter());
+    }
+    hop_by_hop.extend([0x01, 0].iter());
+    assert_eq!(push_v6(&mut defrag, &fragment_v6_behind(&hop_by_hop, 3, 0, true, 6, &[1; 8]), 1), None);
+    assert_eq!(defrag.stats().invalid, 1);
+    assert_eq!((defrag.pending(), defrag.memory()), (0, 0));
+}
+
+#[test]
+fn test_ipv6_overlap_rejected(){
+    let config = DefragConfig { timeout: 1000, ..DefragConfig::default() };
+    let mut defrag = IPv6Defragmenter::new(config);
+
+    assert_eq!(push_v6(&mut defrag, &fragment_v6(1, 0, true, 6, &[1; 16]), 0), None);
+    assert_eq!(push_v6(&mut defrag, &fragment_v6(1, 8, true, 6, &[2; 16]), 1), None);
+    assert_eq!(defrag.memory(), 0);
+    // The rest of the discarded datagram is dropped as it arrives
+    assert_eq!(push_v6(&mut defrag, &fragment_v6(1, 24, false, 6, &[3; 4]), 2), None);
+    assert_eq!(push_v6(&mut defrag, &fragment_v6(1, 16, true, 6, &[2; 8]), 3), None);
+
+    let stats = defrag.stats();
+    assert_eq!((stats.overlaps, stats.rejected, stats.reassembled), (1, 1, 0));
+
+    // Once the discarded datagram times out its identification can be reused
+    assert_eq!(push_v6(&mut defrag, &fragment_v6(1, 8, false, 6, &[5; 4]), 1000), None);
+    assert_eq!(push_v6(&mut defrag, &fragment_v6(1, 0, true, 6, &[4; 8]), 1001),
+               Some(vec![4, 4, 4, 4, 4, 4, 4, 4, 5, 5, 5, 5]));
+    assert_eq!(defrag.stats().timed_out, 0);
+}
+
+#[test]
+fn test_ipv6_atomic_fragment(){
+    let mut defrag = IPv6Defragmenter::new(DefragConfig::default());
+    assert_eq!(push_v6(&mut defrag, &fragment_v6(9, 0, true, 6, &[1; 8]), 0), None);
+
+    // Same identification, but processed in isolation
+    let atomic = fragment_v6(9, 0, false, 6, &[7; 20]);
+    let datagram = defrag.push(&ip::parse_ip(&atomic).unwrap(), 1).unwrap();
+    assert_eq!(datagram.protocol, 6);
+    assert_eq!(datagram.payload, vec![7; 20]);
+    assert_eq!(defrag.pending(), 1);
+    assert_eq!(defrag.stats().fragments, 1);
+
+    assert_eq!(push_v6(&mut defrag, &fragment_v6(9, 8, false, 6, &[2; 4]), 2),
+               Some(vec![1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2]));
+}
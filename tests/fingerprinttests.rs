@@ -0,0 +1,142 @@
+extern crate tcp_parser;
+use tcp_parser::TcpSegment;
+use tcp_parser::ip;
+use tcp_parser::fingerprint::{Fingerprint, SignatureDatabase, Signature, SignatureKind, DatabaseError, WindowSize,
+                              SignatureError, OptionLayout, DONT_FRAGMENT, NONZERO_ID, MUST_BE_ZERO};
+
+const DATABASE : &'static str = "
+; A few signatures of p0f.fp
+classes = win,unix,other
+
+[mtu]
+label = Ethernet or modem
+sig   = 1500
+
+[tcp:request]
+label = s:unix:Linux:3.11 and newer
+sig   = *:64:0:*:mss*20,10:mss,sok,ts,nop,ws:df,id+:0
+sig   = *:64:0:*:mss*20,7:mss,sok,ts,nop,ws:df,id+:0
+
+label = s:win:Windows:7 or 8
+sig   = *:128:0:*:8192,0:mss,nop,nop,sok:df,id+:0
+sig   = *:128:0:*:8192,8:mss,nop,ws,nop,nop,sok:df,id+:0
+
+label = g:win:Windows:XP
+sig   = *:128:0:*:%8192,*:mss,nop,ws,nop,nop,sok:df,id+:0
+
+[tcp:response]
+label = s:unix:Linux:3.x
+sig   = *:64:0:*:mss*10,0:mss,nop,nop,sok,nop,ws:df:0
+
+[http:request]
+label = s:!:Firefox:10.x or newer
+sig   = *:Host,User-Agent:Accept-Charset,Keep-Alive:Firefox/
+";
+
+/// A Windows SYN with the given window, in an IPv4 packet with Don't Fragment and a TTL of 120
+fn windows_syn(window : u16) -> Vec<u8> {
+    vec![0x45, 0, 0, 52, 0x12, 0x34, 0x40, 0, 120, 6, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2,
+         0xC0, 0x00, 0, 80, 0x4B, 0x3C, 0x2D, 0x1E, 0, 0, 0, 0, 0x80, 0x02, (window >> 8) as u8, window as u8,
+         0, 0, 0, 0, 2, 4, 5, 0xB4, 1, 3, 3, 8, 1, 1, 4, 2]
+}
+
+/// A Linux SYN with an MSS of 1440, in an IPv6 packet with a Hop-by-Hop header and a hop limit of 57
+fn linux_syn_v6() -> Vec<u8> {
+    let mut packet = vec![0x60, 0, 0, 0, 0, 48, 0, 57];
+    packet.extend([0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01].iter());
+    packet.extend([0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x02].iter());
+    packet.extend([6, 0, 0x01, 0x04, 0, 0, 0, 0].iter());
+    packet.extend([0xC0, 0x01, 0, 80, 0x1A, 0x2B, 0x3C, 0x4D, 0, 0, 0, 0, 0xA0, 0x02, 0x70, 0x80, 0, 0, 0, 0,
+                   2, 4, 5, 0xA0, 4, 2, 8, 10, 0x00, 0x9A, 0x3F, 0x21, 0, 0, 0, 0, 1, 3, 3, 7].iter());
+    packet
+}
+
+#[test]
+fn test_database(){
+    let database = SignatureDatabase::parse(DATABASE).unwrap();
+    assert_eq!(database.len(), 6);
+    let windows = &database.entries()[3];
+    assert_eq!(windows.kind, SignatureKind::Request);
+    assert_eq!((&windows.label.class[..], &windows.label.flavor[..]), ("win", "7 or 8"));
+    assert_eq!(windows.signature.window, WindowSize::Value(8192));
+    assert_eq!(windows.signature.scale, Some(8));
+    assert_eq!(windows.signature.layout, vec![OptionLayout::Mss, OptionLayout::Nop, OptionLayout::Ws,
+                                              OptionLayout::Nop, OptionLayout::Nop, OptionLayout::Sok]);
+    assert_eq!(windows.signature.quirks, DONT_FRAGMENT | NONZERO_ID);
+    assert_eq!(database.entries()[5].signature.to_string(), "*:64:0:*:mss*10,0:mss,nop,nop,sok,nop,ws:df:0");
+
+    let sig = Signature::parse("4:54+10:0:1460:mss*44,7:mss,sok,ts,nop,ws,eol+1:df,id+,ts1-:+").unwrap();
+    assert_eq!((sig.ttl, sig.bad_ttl, sig.payload), (64, false, Some(true)));
+    assert_eq!(sig.to_string(), "4:64:0:1460:mss*44,7:mss,sok,ts,nop,ws,eol+1:df,id+,ts1-:+");
+
+    assert_eq!(SignatureDatabase::parse("[tcp:request]\nsig = *:64:0:*:*,*::df:0"),
+               Err(DatabaseError::MissingLabel(2)));
+    let err = SignatureDatabase::parse("[tcp:request]\nlabel = s:unix:Linux:3.x\nsig = *:64:0:*:*,*:mss,fast:df:0").unwrap_err();
+    assert_eq!(err, DatabaseError::InvalidSignature(3, SignatureError::Field(5)));
+    assert_eq!(err.line(), 3);
+    assert_eq!(Signature::parse("4:64:0:*:mss*10,x:mss:df:0"), Err(SignatureError::Field(4)));
+    assert_eq!(Signature::parse("4:64+300:0:*:*,*::df:0"), Err(SignatureError::Field(1)));
+    assert_eq!(Signature::parse("4:64:0:*:*,*::df"), Err(SignatureError::FieldCount(7)));
+    assert_eq!(SignatureDatabase::parse("[tcp:response]\nlabel = x:unix:Linux:3.x"), Err(DatabaseError::InvalidLabel(2)));
+}
+
+#[test]
+fn test_identify(){
+    let database = SignatureDatabase::parse(DATABASE).unwrap();
+    let packet = windows_syn(8192);
+    let ip = ip::parse_ipv4(&packet).unwrap();
+    let fingerprint = Fingerprint::new(&TcpSegment::parse(ip.payload), Some(&ip.header)).unwrap();
+    assert_eq!(fingerprint.initial_ttl(), Some(128));
+    assert_eq!(fingerprint.signature().unwrap().to_string(), "4:128:0:1460:8192,8:mss,nop,ws,nop,nop,sok:df,id+:0");
+    let found = database.identify(&fingerprint).unwrap();
+    assert_eq!((&found.label.name[..], &found.label.flavor[..], found.distance), ("Windows", "7 or 8", Some(8)));
+
+    // Only the generic signature allows another window
+    let packet = windows_syn(16384);
+    let ip = ip::parse_ipv4(&packet).unwrap();
+    let fingerprint = Fingerprint::new(&TcpSegment::parse(ip.payload), Some(&ip.header)).unwrap();
+    let found = database.identify(&fingerprint).unwrap();
+    assert!(found.label.generic);
+    assert_eq!(found.label.flavor, "XP");
+
+    // The IP header no longer matters without it, but the TCP header still does
+    let fingerprint = Fingerprint::new(&TcpSegment::parse(ip.payload), None).unwrap();
+    assert_eq!(database.identify(&fingerprint).unwrap().distance, None);
+    assert_eq!(fingerprint.signature(), None);
+    let mut segment = TcpSegment::parse(ip.payload);
+    segment.data = b"GET /".to_vec();
+    assert!(database.identify(&Fingerprint::new(&segment, None).unwrap()).is_none());
+    segment.ctrl_flags = tcp_parser::ACK;
+    assert!(Fingerprint::new(&segment, None).is_none());
+}
+
+#[test]
+fn test_reserved_flag(){
+    let mut packet = windows_syn(8192);
+    packet[6] |= 0x80;
+    let ip = ip::parse_ipv4(&packet).unwrap();
+    let fingerprint = Fingerprint::new(&TcpSegment::parse(ip.payload), Some(&ip.header)).unwrap();
+    assert!(fingerprint.quirks.contains(MUST_BE_ZERO));
+    let signature = fingerprint.signature().unwrap().to_string();
+    assert_eq!(signature, "4:128:0:1460:8192,8:mss,nop,ws,nop,nop,sok:df,id+,0+:0");
+
+    let database = SignatureDatabase::parse(&format!("[tcp:request]\nlabel = s:win:Evil:1\nsig = {}", signature)).unwrap();
+    assert_eq!(database.identify(&fingerprint).unwrap().label.name, "Evil");
+    // Without the IP header the flag is not compared
+    let fingerprint = Fingerprint::new(&TcpSegment::parse(ip.payload), None).unwrap();
+    assert_eq!(database.identify(&fingerprint).unwrap().label.name, "Evil");
+}
+
+#[test]
+fn test_identify_ipv6(){
+    let database = SignatureDatabase::parse(DATABASE).unwrap();
+    let packet = linux_syn_v6();
+    let ip = ip::parse_ipv6(&packet).unwrap();
+    let (tcp, _) = ip.tcp().unwrap();
+    let fingerprint = Fingerprint::new(&TcpSegment::parse(tcp), Some(&ip.header)).unwrap();
+    // The Hop-by-Hop header is no IP option, and IPv6 has no df or id+ to compare
+    assert_eq!(fingerprint.olen, 0);
+    assert_eq!(fingerprint.signature().unwrap().to_string(), "6:64:0:1440:mss*20,7:mss,sok,ts,nop,ws::0");
+    let found = database.identify(&fingerprint).unwrap();
+    assert_eq!((&found.label.name[..], &found.label.flavor[..], found.distance), ("Linux", "3.11 and newer", Some(7)));
+}
@@ -0,0 +1,183 @@
+extern crate tcp_parser;
+use tcp_parser::{TcpSegment, TcpSegmentBuilder, IPv4PseudoHeader, IPv6PseudoHeader, SYN, ACK, PSH};
+use tcp_parser::ip::{self, IpHeader, IpParseError, IpPseudoHeader, VlanTag};
+
+const CLIENT_V6 : [u8; 16] = [0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01];
+const SERVER_V6 : [u8; 16] = [0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x02];
+const HOME_V6   : [u8; 16] = [0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x03];
+
+fn ipv4_packet(options : &[u8], frag : u16, protocol : u8, payload : &[u8]) -> Vec<u8> {
+    let header_len = 20 + options.len();
+    let total_len = header_len + payload.len();
+    let mut packet = vec![0x40 | (header_len / 4) as u8, 0, (total_len >> 8) as u8, total_len as u8,
+                          0x12, 0x34, (frag >> 8) as u8, frag as u8, 64, protocol, 0, 0,
+                          192, 168, 0, 1, 192, 168, 0, 2];
+    packet.extend(options.iter());
+    packet.extend(payload.iter());
+    packet
+}
+
+fn ipv6_packet(next_header : u8, payload : &[u8]) -> Vec<u8> {
+    let mut packet = vec![0x60, 0, 0, 0, (payload.len() >> 8) as u8, payload.len() as u8, next_header, 64];
+    packet.extend(CLIENT_V6.iter());
+    packet.extend(SERVER_V6.iter());
+    packet.extend(payload.iter());
+    packet
+}
+
+fn ethernet_frame(tags : &[u8], ether_type : u16, payload : &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x00, 0x1b, 0x21, 0x3a, 0x4c, 0x5d, 0x00, 0x1b, 0x21, 0x6e, 0x7f, 0x80];
+    frame.extend(tags.iter());
+    frame.push((ether_type >> 8) as u8);
+    frame.push(ether_type as u8);
+    frame.extend(payload.iter());
+    frame
+}
+
+fn segment<P : tcp_parser::PseudoHeader>(pseudo_header : P) -> TcpSegment {
+    TcpSegmentBuilder::new()
+        .src_port(49152)
+        .dest_port(80)
+        .seq_num(1)
+        .ack_num(1)
+        .flags(ACK | PSH)
+        .window(502)
+        .data(b"GET / HTTP/1.1\r\n\r\n".to_vec())
+        .build(pseudo_header)
+        .unwrap()
+}
+
+#[test]
+fn test_qinq_ipv4_options(){
+    let segment = segment(IPv4PseudoHeader {
+        source_addr : 0xC0A80001,
+        dest_addr   : 0xC0A80002,
+        protocol    : 6,
+        tcp_len     : 0
+    });
+    let tcp = segment.as_bytestream();
+
+    // Router alert option, then the minimum Ethernet frame is padded with zeroes
+    let packet = ipv4_packet(&[0x94, 0x04, 0x00, 0x00], 0x4000, 6, &tcp);
+    let mut frame = ethernet_frame(&[0x88, 0xa8, 0x00, 0x64, 0x81, 0x00, 0xa0, 0xc8], 0x0800, &packet);
+    frame.extend([0u8; 6].iter());
+
+    let eth = ip::parse_ethernet(&frame).unwrap();
+    assert_eq!(eth.vlan_tags, vec![VlanTag { tpid: 0x88a8, pcp: 0, dei: false, vid: 100 },
+                                   VlanTag { tpid: 0x8100, pcp: 5, dei: false, vid: 200 }]);
+    let packet = eth.ip().unwrap();
+    match packet.header {
+        IpHeader::V4(ref header) => {
+            assert_eq!(header.ihl, 6);
+            assert_eq!(header.options, vec![0x94, 0x04, 0x00, 0x00]);
+            assert!(header.dont_fragment);
+        },
+        _ => panic!("expected IPv4")
+    }
+
+    let (payload, pseudo_header) = ip::tcp_from_ethernet(&frame).unwrap();
+    assert_eq!(payload, &tcp[..]);
+    assert!(TcpSegment::parse(payload).verify_checksum(pseudo_header));
+}
+
+#[test]
+fn test_ipv6_extension_headers(){
+    // The pseudo-header uses the home address from the type 2 routing header
+    let segment = segment(IPv6PseudoHeader {
+        source_addr : CLIENT_V6,
+        dest_addr   : HOME_V6,
+        tcp_len     : 0,
+        next_header : 6
+    });
+
+    let mut payload = vec![43, 0, 0x01, 0x04, 0x00, 0x00, 0x00, 0x00];
+    payload.extend([60, 2, 2, 1, 0, 0, 0, 0].iter());
+    payload.extend(HOME_V6.iter());
+    payload.extend([6, 0, 0x01, 0x04, 0x00, 0x00, 0x00, 0x00].iter());
+    payload.extend(segment.as_bytestream());
+    let frame = ethernet_frame(&[], 0x86dd, &ipv6_packet(0, &payload));
+
+    let packet = ip::parse_ethernet(&frame).unwrap().ip().unwrap();
+    match packet.header {
+        IpHeader::V6(ref header) => {
+            assert_eq!(header.extension_headers, vec![0, 43, 60]);
+            assert_eq!(header.final_dest, Some(HOME_V6));
+        },
+        _ => panic!("expected IPv6")
+    }
+    assert_eq!(packet.protocol, 6);
+
+    let (tcp, pseudo_header) = packet.tcp().unwrap();
+    assert_eq!(pseudo_header, IpPseudoHeader::V6(IPv6PseudoHeader {
+        source_addr : CLIENT_V6,
+        dest_addr   : HOME_V6,
+        tcp_len     : tcp.len() as u32,
+        next_header : 6
+    }));
+    assert_eq!(TcpSegment::parse(tcp), segment);
+    assert!(segment.verify_checksum(pseudo_header));
+}
+
+#[test]
+fn test_fragments(){
+    let tcp = TcpSegmentBuilder::new().flags(SYN).build(IPv4PseudoHeader {
+        source_addr : 0xC0A80001,
+        dest_addr   : 0xC0A80002,
+        protocol    : 6,
+        tcp_len     : 0
+    }).unwrap().as_bytestream();
+
+    let packet = ipv4_packet(&[], 0x2000, 6, &tcp);
+    let packet = ip::parse_ip(&packet).unwrap();
+    assert!(packet.is_fragment());
+    assert_eq!(packet.tcp(), Err(IpParseError::Fragmented));
+
+    // Second IPv6 fragment: the walk stops after the fragment header
+    let mut payload = vec![6, 0, 0x00, 0xb8, 0xde, 0xad, 0xbe, 0xef];
+    payload.extend(tcp.iter());
+    let packet = ipv6_packet(44, &payload);
+    let packet = ip::parse_ipv6(&packet).unwrap();
+    assert_eq!(packet.protocol, 6);
+    assert_eq!(packet.payload, &tcp[..]);
+    match packet.header {
+        IpHeader::V6(ref header) => {
+            let frag = header.fragment.unwrap();
+            assert_eq!((frag.offset, frag.more_fragments, frag.id), (23, false, 0xdeadbeef));
+        },
+        _ => panic!("expected IPv6")
+    }
+    assert_eq!(packet.tcp(), Err(IpParseError::Fragmented));
+
+    // An atomic fragment is a whole datagram
+    payload[3] = 0;
+    let packet = ipv6_packet(44, &payload);
+    let packet = ip::parse_ipv6(&packet).unwrap();
+    assert!(!packet.is_fragment());
+    assert_eq!(packet.tcp().unwrap().0, &tcp[..]);
+}
+
+#[test]
+fn test_ip_errors(){
+    assert_eq!(ip::parse_ethernet(&[0; 10]), Err(IpParseError::Truncated(0)));
+
+    let arp = ethernet_frame(&[], 0x0806, &[0; 28]);
+    assert_eq!(ip::tcp_from_ethernet(&arp), Err(IpParseError::UnsupportedEtherType(0x0806)));
+
+    let udp = ethernet_frame(&[0x81, 0x00, 0x00, 0x01], 0x0800, &ipv4_packet(&[], 0, 17, &[0; 8]));
+    assert_eq!(ip::tcp_from_ethernet(&udp), Err(IpParseError::NotTcp(17)));
+
+    let mut short = ipv4_packet(&[], 0, 6, &[0; 20]);
+    short.truncate(30);
+    assert_eq!(ip::parse_ip(&short), Err(IpParseError::InvalidTotalLength(2)));
+    let frame = ethernet_frame(&[], 0x0800, &short);
+    assert_eq!(ip::tcp_from_ethernet(&frame), Err(IpParseError::InvalidTotalLength(16)));
+
+    assert_eq!(ip::parse_ip(&[0x45; 10]), Err(IpParseError::Truncated(0)));
+    assert_eq!(ip::parse_ip(&[0x55; 40]), Err(IpParseError::InvalidVersion(5)));
+    assert_eq!(ip::parse_ip(&ipv4_packet(&[], 0, 6, &[])[..]).map(|p| p.payload.len()), Ok(0));
+
+    let mut hop_by_hop = ipv6_packet(0, &[6, 1, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(ip::parse_ip(&hop_by_hop), Err(IpParseError::Truncated(40)));
+    hop_by_hop[0] = 0x40;
+    assert_eq!(ip::parse_ip(&hop_by_hop), Err(IpParseError::InvalidHeaderLength(0)));
+}
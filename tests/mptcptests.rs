@@ -0,0 +1,108 @@
+extern crate tcp_parser;
+use tcp_parser::{TcpSegment, TcpOpts};
+use tcp_parser::mptcp::{MptcpOption, MptcpAddr, DataSeq, DssMapping};
+
+fn round_trip(body: &[u8], expected: MptcpOption) {
+    let opt = MptcpOption::parse(body).unwrap();
+    assert_eq!(opt, expected);
+    assert_eq!(opt.to_bytes(), body);
+}
+
+#[test]
+fn test_mp_capable_syn(){
+    // SYN with MSS, SACK permitted and MP_CAPABLE v1 without keys
+    let tcp_data : Vec<u8> = vec![0xb1, 0x7c, 0x01, 0xbb, 0x6b, 0x9e,
+                                  0x25, 0x07, 0x00, 0x00, 0x00, 0x00, 0x80, 0x02,
+                                  0xff, 0xd7, 0x00, 0x00, 0x00, 0x00, 0x02, 0x04,
+                                  0x05, 0xb4, 0x04, 0x02, 0x01, 0x01, 0x1e, 0x04,
+                                  0x01, 0x81];
+
+    let segment = TcpSegment::parse(&tcp_data);
+    assert_eq!(segment.options[4], TcpOpts::Mptcp(MptcpOption::MpCapable {
+        version:        1,
+        flags:          0x81,
+        sender_key:     None,
+        receiver_key:   None,
+        data_len:       None,
+        checksum:       None
+    }));
+    assert_eq!(segment.as_bytestream(), tcp_data);
+}
+
+#[test]
+fn test_mp_capable_keys(){
+    round_trip(&[0x00, 0x81, 1, 2, 3, 4, 5, 6, 7, 8, 8, 7, 6, 5, 4, 3, 2, 1, 0x00, 0x10],
+               MptcpOption::MpCapable {
+                   version:        0,
+                   flags:          0x81,
+                   sender_key:     Some(0x0102030405060708),
+                   receiver_key:   Some(0x0807060504030201),
+                   data_len:       Some(16),
+                   checksum:       None
+               });
+    assert_eq!(MptcpOption::parse(&[0x00, 0x81, 1, 2, 3]), None);
+}
+
+#[test]
+fn test_mp_join(){
+    round_trip(&[0x11, 0x02, 0xde, 0xad, 0xbe, 0xef, 0x00, 0x00, 0x00, 0x2a],
+               MptcpOption::MpJoinSyn { backup: true, address_id: 2, receiver_token: 0xdeadbeef, sender_nonce: 42 });
+    round_trip(&[0x10, 0x02, 1, 2, 3, 4, 5, 6, 7, 8, 0x00, 0x00, 0x00, 0x2b],
+               MptcpOption::MpJoinSynAck { backup: false, address_id: 2, sender_hmac: 0x0102030405060708, sender_nonce: 43 });
+    round_trip(&[0x10, 0x00, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19],
+               MptcpOption::MpJoinAck { reserved: 0, sender_hmac: [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19] });
+    round_trip(&[0x1A, 0xBC, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19],
+               MptcpOption::MpJoinAck { reserved: 0xABC, sender_hmac: [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19] });
+}
+
+#[test]
+fn test_dss(){
+    round_trip(&[0x20, 0x05, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x20, 0x00,
+                 0x00, 0x00, 0x00, 0x01, 0x05, 0xa0, 0xbe, 0xef],
+               MptcpOption::Dss {
+                   data_fin:   false,
+                   data_ack:   Some(DataSeq::Short(0x1000)),
+                   mapping:    Some(DssMapping {
+                       dsn:            DataSeq::Short(0x2000),
+                       subflow_seq:    1,
+                       data_len:       1440,
+                       checksum:       Some(0xbeef)
+                   })
+               });
+    round_trip(&[0x20, 0x13, 1, 2, 3, 4, 5, 6, 7, 8],
+               MptcpOption::Dss {
+                   data_fin:   true,
+                   data_ack:   Some(DataSeq::Long(0x0102030405060708)),
+                   mapping:    None
+               });
+    assert_eq!(MptcpOption::parse(&[0x20, 0x01, 0x00, 0x00]), None);
+}
+
+#[test]
+fn test_addresses(){
+    round_trip(&[0x30, 0x03, 10, 0, 0, 2, 0x1f, 0x90, 1, 2, 3, 4, 5, 6, 7, 8],
+               MptcpOption::AddAddr {
+                   echo:       false,
+                   address_id: 3,
+                   address:    MptcpAddr::V4(0x0a000002),
+                   port:       Some(8080),
+                   hmac:       Some(0x0102030405060708)
+               });
+    round_trip(&[0x31, 0x04, 0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+               MptcpOption::AddAddr {
+                   echo:       true,
+                   address_id: 4,
+                   address:    MptcpAddr::V6([0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]),
+                   port:       None,
+                   hmac:       None
+               });
+    round_trip(&[0x40, 3, 4], MptcpOption::RemoveAddr { address_ids: vec![3, 4] });
+    round_trip(&[0x51], MptcpOption::MpPrio { backup: true, address_id: None });
+}
+
+#[test]
+fn test_fail_and_fastclose(){
+    round_trip(&[0x60, 0x00, 1, 2, 3, 4, 5, 6, 7, 8], MptcpOption::MpFail { dsn: 0x0102030405060708 });
+    round_trip(&[0x70, 0x00, 8, 7, 6, 5, 4, 3, 2, 1], MptcpOption::MpFastClose { receiver_key: 0x0807060504030201 });
+    round_trip(&[0x80, 0x01, 0xff], MptcpOption::Unknown(vec![0x80, 0x01, 0xff]));
+}
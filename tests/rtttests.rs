@@ -0,0 +1,109 @@
+extern crate tcp_parser;
+use tcp_parser::{TcpSegment, TcpSegmentBuilder, TcpCTRL, TcpOpts, IPv4PseudoHeader, ACK, PSH};
+use tcp_parser::rtt::{RttEstimator, RttSample, RttSource, RttSummary};
+
+use tcp_parser::stream::Direction::{ClientToServer, ServerToClient};
+
+fn segment(seq : u32, ack : u32, flags : TcpCTRL, timestamp : Option<(u32, u32)>, data : &[u8]) -> TcpSegment {
+    let mut builder = TcpSegmentBuilder::new()
+        .src_port(40000)
+        .dest_port(80)
+        .seq_num(seq)
+        .ack_num(ack)
+        .flags(flags)
+        .window(65535)
+        .data(data.to_vec());
+    if let Some((time, echo)) = timestamp {
+        builder = builder.option(TcpOpts::NOP).option(TcpOpts::NOP).option(TcpOpts::TimeStamp { time: time, echo: echo });
+    }
+    builder.build(IPv4PseudoHeader { source_addr: 1, dest_addr: 2, protocol: 6, tcp_len: 0 }).unwrap()
+}
+
+#[test]
+fn test_ack_samples_and_karn(){
+    let mut rtt = RttEstimator::new();
+    let data = [0u8; 100];
+    rtt.push(ClientToServer, &segment(1, 1, ACK | PSH, None, &data), 0);
+    rtt.push(ClientToServer, &segment(101, 1, ACK | PSH, None, &data), 10);
+    // One ACK covering both samples the latest segment
+    rtt.push(ServerToClient, &segment(1, 201, ACK, None, b""), 60);
+    assert_eq!(rtt.samples(ClientToServer), &[RttSample { time: 60, rtt: 50, source: RttSource::Ack }]);
+
+    // A retransmitted segment gives no sample, even when the ACK covers it
+    rtt.push(ClientToServer, &segment(201, 1, ACK | PSH, None, &data), 100);
+    rtt.push(ClientToServer, &segment(201, 1, ACK | PSH, None, &data), 1100);
+    rtt.push(ServerToClient, &segment(1, 301, ACK, None, b""), 1130);
+    assert_eq!(rtt.samples(ClientToServer).len(), 1);
+
+    // An ACK for new data after it samples again, and duplicate ACKs do not
+    rtt.push(ClientToServer, &segment(301, 1, ACK | PSH, None, &data), 1200);
+    rtt.push(ServerToClient, &segment(1, 401, ACK, None, b""), 1240);
+    rtt.push(ServerToClient, &segment(1, 401, ACK, None, b""), 1250);
+    assert_eq!(rtt.samples(ClientToServer).iter().map(|s| s.rtt).collect::<Vec<u64>>(), vec![50, 40]);
+    assert!(rtt.samples(ServerToClient).is_empty());
+
+    // RFC 6298: SRTT = R, RTTVAR = R/2, then SRTT = 7/8 SRTT + 1/8 R', RTTVAR = 3/4 RTTVAR + 1/4 |SRTT - R'|
+    assert_eq!(rtt.srtt(ClientToServer), Some(48.75));
+    assert_eq!(rtt.rttvar(ClientToServer), Some(21.25));
+    assert_eq!(rtt.rto(ClientToServer), Some(1000.0));
+    assert_eq!(rtt.srtt(ServerToClient), None);
+}
+
+#[test]
+fn test_timestamp_samples(){
+    let mut rtt = RttEstimator::new();
+    rtt.push(ClientToServer, &segment(1, 1, ACK | PSH, Some((100, 7)), b"a"), 0);
+    rtt.push(ClientToServer, &segment(2, 1, ACK | PSH, Some((101, 7)), b"b"), 5);
+    // The server echoes the first TSval late, e.g. delaying its ACK
+    rtt.push(ServerToClient, &segment(1, 2, ACK, Some((900, 100)), b""), 30);
+    rtt.push(ServerToClient, &segment(1, 2, ACK, Some((901, 100)), b""), 35);
+    rtt.push(ServerToClient, &segment(1, 3, ACK, Some((902, 101)), b""), 45);
+    let sources : Vec<(u64, RttSource)> = rtt.samples(ClientToServer).iter().map(|s| (s.rtt, s.source)).collect();
+    assert_eq!(sources, vec![(30, RttSource::Timestamp), (30, RttSource::Ack),
+                             (40, RttSource::Timestamp), (40, RttSource::Ack)]);
+    // Only timestamp samples feed the estimate once there are some
+    assert_eq!(rtt.srtt(ClientToServer), Some(31.25));
+
+    // The client's echo of the server's TSval measures the other direction
+    rtt.push(ClientToServer, &segment(3, 1, ACK, Some((102, 900)), b""), 50);
+    assert_eq!(rtt.samples(ServerToClient), &[RttSample { time: 50, rtt: 20, source: RttSource::Timestamp }]);
+
+    assert_eq!(rtt.summary(ServerToClient).map(|s| s.count), Some(1));
+
+    // Round trips of 1 to 100 milliseconds
+    let mut rtt = RttEstimator::new();
+    for i in 0..100 {
+        rtt.push(ClientToServer, &segment(1, 1, ACK, Some((i, 0)), b""), i as u64 * 1000);
+        rtt.push(ServerToClient, &segment(1, 1, ACK, Some((5000, i)), b""), i as u64 * 1000 + 100 - i as u64);
+    }
+    assert_eq!(rtt.summary(ClientToServer), Some(RttSummary { count: 100, min: 1, max: 100, p50: 50, p90: 90, p99: 99 }));
+    assert_eq!((rtt.percentile(ClientToServer, 0), rtt.percentile(ClientToServer, 100)), (Some(1), Some(100)));
+    assert_eq!(rtt.summary(ServerToClient), None);
+}
+
+#[test]
+fn test_reply_before_segment(){
+    // Captures merged from two interfaces may timestamp the reply before the segment it answers
+    let mut rtt = RttEstimator::new();
+    rtt.push(ClientToServer, &segment(1, 1, ACK | PSH, Some((100, 7)), b"a"), 1000);
+    rtt.push(ServerToClient, &segment(1, 2, ACK, Some((900, 100)), b""), 990);
+    assert_eq!(rtt.samples(ClientToServer), &[RttSample { time: 990, rtt: 0, source: RttSource::Timestamp },
+                                              RttSample { time: 990, rtt: 0, source: RttSource::Ack }]);
+    assert_eq!(rtt.srtt(ClientToServer), Some(0.0));
+}
+
+#[test]
+fn test_unechoed_timestamps(){
+    // A peer that never echoes, then echoes a TSval long dropped and the latest one
+    let mut rtt = RttEstimator::new();
+    for i in 0..2000 {
+        rtt.push(ClientToServer, &segment(1, 1, ACK, Some((i, 0)), b""), i as u64);
+    }
+    rtt.push(ServerToClient, &segment(1, 1, ACK, Some((900, 5)), b""), 2000);
+    assert_eq!(rtt.samples(ClientToServer), &[]);
+    rtt.push(ServerToClient, &segment(1, 1, ACK, Some((901, 1999)), b""), 2010);
+    assert_eq!(rtt.samples(ClientToServer), &[RttSample { time: 2010, rtt: 11, source: RttSource::Timestamp }]);
+    // Echoing a TSval drops those before it
+    rtt.push(ServerToClient, &segment(1, 1, ACK, Some((902, 1500)), b""), 2020);
+    assert_eq!(rtt.samples(ClientToServer).len(), 1);
+}
@@ -0,0 +1,71 @@
+extern crate tcp_parser;
+use tcp_parser::{TcpSegment, TcpSegmentBuilder, TcpOpts, SeqNum, IPv4PseudoHeader, ACK};
+use tcp_parser::sack::{SackScoreboard, SackEvent};
+
+use tcp_parser::stream::Direction::{ClientToServer, ServerToClient};
+
+/// An ACK from the server for data of the client
+fn ack(ack : u32, blocks : &[(u32, u32)]) -> TcpSegment {
+    let mut builder = TcpSegmentBuilder::new()
+        .src_port(80)
+        .dest_port(40000)
+        .ack_num(ack)
+        .flags(ACK)
+        .window(65535);
+    if !blocks.is_empty() {
+        builder = builder.option(TcpOpts::NOP).option(TcpOpts::NOP).option(TcpOpts::SAck(blocks.to_vec()));
+    }
+    builder.build(IPv4PseudoHeader { source_addr: 2, dest_addr: 1, protocol: 6, tcp_len: 0 }).unwrap()
+}
+
+fn ranges(ranges : &[(u32, u32)]) -> Vec<(SeqNum, SeqNum)> {
+    ranges.iter().map(|&(left, right)| (SeqNum(left), SeqNum(right))).collect()
+}
+
+#[test]
+fn test_scoreboard(){
+    let mut scoreboard = SackScoreboard::new();
+    assert_eq!(scoreboard.push(ServerToClient, &ack(1000, &[])), vec![]);
+    assert_eq!(scoreboard.push(ServerToClient, &ack(1000, &[(2000, 3000)])),
+               vec![SackEvent::Hole { left: SeqNum(1000), right: SeqNum(2000) }]);
+    // Blocks merge across ACKs, and only the new part of a hole is reported
+    assert_eq!(scoreboard.push(ServerToClient, &ack(1000, &[(4000, 5000), (2000, 3000)])),
+               vec![SackEvent::Hole { left: SeqNum(3000), right: SeqNum(4000) }]);
+    scoreboard.push(ServerToClient, &ack(1000, &[(3000, 3500), (2000, 3000)]));
+    assert_eq!(scoreboard.sacked(ClientToServer), ranges(&[(2000, 3500), (4000, 5000)]));
+    assert_eq!(scoreboard.holes(ClientToServer), ranges(&[(1000, 2000), (3500, 4000)]));
+
+    // The retransmission fills the first hole
+    assert_eq!(scoreboard.push(ServerToClient, &ack(3500, &[(4000, 5000)])), vec![]);
+    assert_eq!(scoreboard.cumulative_ack(ClientToServer), Some(SeqNum(3500)));
+    assert_eq!(scoreboard.holes(ClientToServer), ranges(&[(3500, 4000)]));
+    scoreboard.push(ServerToClient, &ack(5000, &[]));
+    assert!(scoreboard.sacked(ClientToServer).is_empty() && scoreboard.holes(ClientToServer).is_empty());
+    assert_eq!(scoreboard.holes_seen(ClientToServer), ranges(&[(1000, 2000), (3000, 4000)]));
+
+    // Nothing was reported about the data of the server
+    assert_eq!(scoreboard.cumulative_ack(ServerToClient), None);
+    assert!(scoreboard.holes_seen(ServerToClient).is_empty());
+}
+
+#[test]
+fn test_dsack(){
+    let mut scoreboard = SackScoreboard::new();
+    // Below the cumulative ACK (RFC 2883 section 4.1.1)
+    assert_eq!(scoreboard.push(ServerToClient, &ack(4000, &[(3000, 3500)])),
+               vec![SackEvent::DuplicateSack { left: SeqNum(3000), right: SeqNum(3500) }]);
+    // Within the second block (section 4.2.1), which is still SACKed
+    assert_eq!(scoreboard.push(ServerToClient, &ack(4000, &[(5000, 5500), (5000, 6000)])),
+               vec![SackEvent::DuplicateSack { left: SeqNum(5000), right: SeqNum(5500) },
+                    SackEvent::Hole { left: SeqNum(4000), right: SeqNum(5000) }]);
+    assert_eq!(scoreboard.sacked(ClientToServer), ranges(&[(5000, 6000)]));
+    assert_eq!(scoreboard.dsacks(ClientToServer), &ranges(&[(3000, 3500), (5000, 5500)])[..]);
+
+    // Blocks that make no sense, and blocks across the sequence number wraparound
+    assert_eq!(scoreboard.push(ServerToClient, &ack(4000, &[(7000, 6500), (6000, 6000)])),
+               vec![SackEvent::InvalidBlock { left: SeqNum(7000), right: SeqNum(6500) },
+                    SackEvent::InvalidBlock { left: SeqNum(6000), right: SeqNum(6000) }]);
+    let mut scoreboard = SackScoreboard::new();
+    scoreboard.push(ServerToClient, &ack(0xFFFFFF00, &[(0x100, 0x200)]));
+    assert_eq!(scoreboard.holes(ClientToServer), ranges(&[(0xFFFFFF00, 0x100)]));
+}
@@ -0,0 +1,48 @@
+extern crate tcp_parser;
+use tcp_parser::{SeqNum, TcpSegment, TcpSegmentBuilder, IPv4PseudoHeader, TcpSegmentRef, SYN, FIN, ACK};
+
+#[test]
+fn test_serial_arithmetic(){
+    let a = SeqNum(0xFFFFFFFE);
+    let b = a + 4;
+    assert_eq!(b, SeqNum(2));
+    assert_eq!(b - 4, a);
+    assert_eq!((b - a, a - b), (4, -4));
+    assert!(a.lt(b) && a.le(b) && b.gt(a) && b.ge(a));
+    assert!(!b.lt(a) && !a.gt(b));
+    assert!(a.le(a) && a.ge(a) && !a.lt(a) && !a.gt(a));
+
+    // Just under half the space ahead is still after, exactly half is undefined
+    assert!(SeqNum(0).lt(SeqNum(0x7FFFFFFF)));
+    assert!(SeqNum(0).gt(SeqNum(0x80000001)));
+    let (c, d) = (SeqNum(5), SeqNum(0x80000005));
+    assert!(!c.lt(d) && !c.gt(d) && !d.lt(c) && !d.gt(c));
+
+    assert!(SeqNum(0xFFFFFFF0).in_window(SeqNum(0xFFFFFFF0), 0x20));
+    assert!(SeqNum(0x0F).in_window(SeqNum(0xFFFFFFF0), 0x20));
+    assert!(!SeqNum(0x10).in_window(SeqNum(0xFFFFFFF0), 0x20));
+    assert!(!SeqNum(0xFFFFFFEF).in_window(SeqNum(0xFFFFFFF0), 0x20));
+    assert!(!SeqNum(7).in_window(SeqNum(7), 0));
+    assert_eq!(format!("{}", SeqNum::from(42)), "42");
+}
+
+#[test]
+fn test_segment_accessors(){
+    let segment = TcpSegmentBuilder::new()
+        .seq_num(0xFFFFFFFF)
+        .ack_num(1000)
+        .flags(SYN | FIN | ACK)
+        .data(b"abc".to_vec())
+        .build(IPv4PseudoHeader { source_addr: 1, dest_addr: 2, protocol: 6, tcp_len: 0 })
+        .unwrap();
+    assert_eq!((segment.seq(), segment.ack()), (SeqNum(0xFFFFFFFF), SeqNum(1000)));
+    assert_eq!(segment.seq_len(), 5);
+    assert_eq!(segment.end_seq(), SeqNum(4));
+    assert_eq!(segment.relative_seq(SeqNum(0xFFFFFFFF)), 0);
+    assert_eq!(segment.relative_ack(SeqNum(999)), 1);
+
+    let bytes = segment.as_bytestream();
+    let view = TcpSegmentRef::parse(&bytes).unwrap();
+    assert_eq!((view.seq(), view.ack()), (segment.seq(), segment.ack()));
+    assert_eq!(TcpSegment::parse(&bytes).end_seq(), SeqNum(4));
+}
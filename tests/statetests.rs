@@ -0,0 +1,103 @@
+extern crate tcp_parser;
+use tcp_parser::{TcpSegment, TcpSegmentBuilder, TcpCTRL, IPv4PseudoHeader, SYN, ACK, FIN, RST, PSH};
+use tcp_parser::state::{StateTracker, StateEvent, TcpState, InvalidReason};
+use tcp_parser::state::TcpState::*;
+
+use tcp_parser::stream::Direction::{ClientToServer, ServerToClient};
+
+fn segment(seq : u32, ack : u32, flags : TcpCTRL, data : &[u8]) -> TcpSegment {
+    TcpSegmentBuilder::new()
+        .src_port(40000)
+        .dest_port(80)
+        .seq_num(seq)
+        .ack_num(ack)
+        .flags(flags)
+        .window(65535)
+        .data(data.to_vec())
+        .build(IPv4PseudoHeader { source_addr: 1, dest_addr: 2, protocol: 6, tcp_len: 0 })
+        .unwrap()
+}
+
+fn states(tracker : &StateTracker) -> (TcpState, TcpState) {
+    (tracker.state(ClientToServer), tracker.state(ServerToClient))
+}
+
+#[test]
+fn test_handshake_and_close(){
+    let mut tracker = StateTracker::new();
+    assert_eq!(states(&tracker), (Closed, Listen));
+    assert_eq!(tracker.push(ClientToServer, &segment(100, 0, SYN, b"")), vec![
+        StateEvent::Transition { endpoint: ClientToServer, from: Closed, to: SynSent },
+        StateEvent::Transition { endpoint: ServerToClient, from: Listen, to: SynReceived }]);
+    tracker.push(ServerToClient, &segment(500, 101, SYN | ACK, b""));
+    assert_eq!(states(&tracker), (Established, SynReceived));
+    tracker.push(ClientToServer, &segment(101, 501, ACK | PSH, b"hello"));
+    assert_eq!(states(&tracker), (Established, Established));
+
+    // The server closes first, and the client keeps sending
+    assert_eq!(tracker.push(ServerToClient, &segment(501, 106, FIN | ACK, b"")), vec![
+        StateEvent::Transition { endpoint: ServerToClient, from: Established, to: FinWait1 },
+        StateEvent::Transition { endpoint: ClientToServer, from: Established, to: CloseWait },
+        StateEvent::HalfClosed(ServerToClient)]);
+    assert!(tracker.is_half_closed());
+    tracker.push(ClientToServer, &segment(106, 502, ACK | PSH, b"more"));
+    assert_eq!(states(&tracker), (CloseWait, FinWait2));
+    tracker.push(ClientToServer, &segment(110, 502, FIN | ACK, b""));
+    assert_eq!(states(&tracker), (LastAck, TimeWait));
+    assert!(!tracker.is_half_closed());
+    tracker.push(ServerToClient, &segment(502, 111, ACK, b""));
+    assert_eq!(states(&tracker), (Closed, TimeWait));
+
+    // The closed client has nothing more to send
+    assert_eq!(tracker.push(ClientToServer, &segment(111, 502, ACK, b"")), vec![
+        StateEvent::Invalid { direction: ClientToServer, state: Closed, reason: InvalidReason::SegmentAfterClose }]);
+    assert_eq!(format!("{} {}", TcpState::FinWait1, TcpState::SynReceived), "FIN-WAIT-1 SYN-RECEIVED");
+}
+
+#[test]
+fn test_simultaneous_open_and_close(){
+    let mut tracker = StateTracker::new();
+    tracker.push(ClientToServer, &segment(100, 0, SYN, b""));
+    // The server sent its SYN before the client's arrived
+    assert_eq!(tracker.push(ServerToClient, &segment(500, 0, SYN, b"")), vec![
+        StateEvent::SimultaneousOpen,
+        StateEvent::Transition { endpoint: ClientToServer, from: SynSent, to: SynReceived }]);
+    assert_eq!(states(&tracker), (SynReceived, SynReceived));
+    tracker.push(ClientToServer, &segment(100, 501, SYN | ACK, b""));
+    tracker.push(ServerToClient, &segment(500, 101, SYN | ACK, b""));
+    assert_eq!(states(&tracker), (Established, Established));
+
+    tracker.push(ClientToServer, &segment(101, 501, FIN | ACK, b""));
+    let events = tracker.push(ServerToClient, &segment(501, 101, FIN | ACK, b""));
+    assert!(events.contains(&StateEvent::SimultaneousClose));
+    assert_eq!(states(&tracker), (Closing, Closing));
+    tracker.push(ClientToServer, &segment(102, 502, ACK, b""));
+    tracker.push(ServerToClient, &segment(502, 102, ACK, b""));
+    assert_eq!(states(&tracker), (TimeWait, TimeWait));
+}
+
+#[test]
+fn test_midstream_and_invalid(){
+    let mut tracker = StateTracker::new();
+    let events = tracker.push(ServerToClient, &segment(7000, 3000, ACK | PSH, b"data"));
+    assert_eq!(events[0], StateEvent::Midstream);
+    assert_eq!(states(&tracker), (Established, Established));
+
+    assert_eq!(tracker.push(ClientToServer, &segment(3000, 7004, SYN, b"")), vec![
+        StateEvent::Invalid { direction: ClientToServer, state: Established, reason: InvalidReason::UnexpectedSyn }]);
+    tracker.push(ServerToClient, &segment(7004, 3000, FIN | ACK, b""));
+    assert_eq!(tracker.push(ServerToClient, &segment(7005, 3000, ACK, b"late")), vec![
+        StateEvent::Invalid { direction: ServerToClient, state: FinWait1, reason: InvalidReason::DataAfterFin }]);
+    // A retransmission of the FIN is fine
+    assert_eq!(tracker.push(ServerToClient, &segment(7000, 3000, FIN | ACK, b"data")), vec![]);
+
+    tracker.push(ClientToServer, &segment(3000, 7005, RST, b""));
+    assert_eq!(states(&tracker), (Closed, Closed));
+
+    // A SYN-ACK acknowledging something else than the SYN
+    let mut tracker = StateTracker::new();
+    tracker.push(ClientToServer, &segment(100, 0, SYN, b""));
+    assert_eq!(tracker.push(ServerToClient, &segment(500, 999, SYN | ACK, b"")), vec![
+        StateEvent::Invalid { direction: ServerToClient, state: SynReceived, reason: InvalidReason::AckMismatch }]);
+    assert_eq!(states(&tracker), (SynSent, SynReceived));
+}
@@ -0,0 +1,202 @@
+extern crate tcp_parser;
+use tcp_parser::{TcpSegment, TcpSegmentBuilder, TcpCTRL, IPv4PseudoHeader, SYN, ACK, FIN, RST, PSH};
+use tcp_parser::stream::{StreamReassembler, StreamConfig, StreamEvent, KeptEvent, Direction, OverlapPolicy};
+use std::io::Read;
+
+use tcp_parser::stream::Direction::{ClientToServer, ServerToClient};
+
+fn segment(seq : u32, ack : u32, flags : TcpCTRL, data : &[u8]) -> TcpSegment {
+    TcpSegmentBuilder::new()
+        .src_port(40000)
+        .dest_port(80)
+        .seq_num(seq)
+        .ack_num(ack)
+        .flags(flags)
+        .window(65535)
+        .data(data.to_vec())
+        .build(IPv4PseudoHeader { source_addr: 1, dest_addr: 2, protocol: 6, tcp_len: 0 })
+        .unwrap()
+}
+
+#[derive(Debug, PartialEq)]
+enum Event {
+    Data(Direction, Vec<u8>),
+    Gap(Direction, u64),
+    Fin(Direction),
+    Reset(Direction),
+    Conflict(Direction, u64, Vec<u8>, Vec<u8>)
+}
+
+fn event(direction : Direction, event : StreamEvent) -> Event {
+    match event {
+        StreamEvent::Data(data) => Event::Data(direction, data.to_vec()),
+        StreamEvent::Gap(len) => Event::Gap(direction, len),
+        StreamEvent::Fin => Event::Fin(direction),
+        StreamEvent::Reset => Event::Reset(direction),
+        StreamEvent::Conflict { offset, kept, discarded } =>
+            Event::Conflict(direction, offset, kept.to_vec(), discarded.to_vec())
+    }
+}
+
+fn push(streams : &mut StreamReassembler, events : &mut Vec<Event>, direction : Direction, segment : &TcpSegment) {
+    streams.push_with(direction, segment, |direction, e| events.push(event(direction, e)));
+}
+
+/// Buffer segments of (offset, data) behind a one byte hole, then fill it and read the stream back
+fn resolve(policy : OverlapPolicy, segments : &[(u32, &str)]) -> (String, u64) {
+    let mut streams = StreamReassembler::new(StreamConfig { policy: policy, ..StreamConfig::default() });
+    streams.push(ClientToServer, &segment(999, 0, SYN, b""));
+    for &(offset, data) in segments.iter() {
+        streams.push(ClientToServer, &segment(1000 + offset, 0, PSH, data.as_bytes()));
+    }
+    streams.push(ClientToServer, &segment(1000, 0, PSH, b"_"));
+
+    let mut data = String::new();
+    streams.reader(ClientToServer).read_to_string(&mut data).unwrap();
+    (data[1..].to_string(), streams.conflicts(ClientToServer))
+}
+
+#[test]
+fn test_reorder_and_overlap(){
+    let mut streams = StreamReassembler::new(StreamConfig::default());
+    let mut events = Vec::new();
+
+    push(&mut streams, &mut events, ClientToServer, &segment(1000, 0, SYN, b""));
+    push(&mut streams, &mut events, ServerToClient, &segment(5000, 1001, SYN | ACK, b""));
+    push(&mut streams, &mut events, ClientToServer, &segment(1001, 5001, ACK, b""));
+    assert!(events.is_empty());
+
+    // Out of order, then a retransmission overlapping both sides
+    push(&mut streams, &mut events, ClientToServer, &segment(1007, 5001, ACK | PSH, b"world"));
+    assert_eq!(streams.buffered(ClientToServer), 5);
+    push(&mut streams, &mut events, ClientToServer, &segment(1001, 5001, ACK | PSH, b"hello "));
+    push(&mut streams, &mut events, ClientToServer, &segment(1004, 5001, ACK | PSH, b"lo wXXXX!"));
+    push(&mut streams, &mut events, ServerToClient, &segment(5001, 1013, ACK | PSH, b"hi"));
+    push(&mut streams, &mut events, ClientToServer, &segment(1013, 5003, FIN | ACK, b""));
+    push(&mut streams, &mut events, ServerToClient, &segment(5003, 1014, RST, b""));
+
+    assert_eq!(events, vec![Event::Data(ClientToServer, b"hello ".to_vec()),
+                            Event::Data(ClientToServer, b"world".to_vec()),
+                            Event::Data(ClientToServer, b"!".to_vec()),
+                            Event::Data(ServerToClient, b"hi".to_vec()),
+                            Event::Fin(ClientToServer),
+                            Event::Reset(ServerToClient)]);
+    assert!(streams.is_closed(ClientToServer));
+    assert!(streams.is_closed(ServerToClient));
+    assert_eq!(streams.delivered(ClientToServer), 12);
+    assert_eq!(streams.buffered(ClientToServer), 0);
+}
+
+#[test]
+fn test_sequence_wraparound(){
+    let mut streams = StreamReassembler::new(StreamConfig::default());
+    streams.push(ClientToServer, &segment(0xFFFFFFFA, 0, SYN, b""));
+    streams.push(ClientToServer, &segment(0x00000002, 0, ACK, b"789"));
+    streams.push(ClientToServer, &segment(0xFFFFFFFB, 0, ACK, b"0123"));
+    streams.push(ClientToServer, &segment(0xFFFFFFFF, 0, ACK, b"456"));
+
+    let mut data = String::new();
+    streams.reader(ClientToServer).read_to_string(&mut data).unwrap();
+    assert_eq!(data, "0123456789");
+    assert_eq!(streams.available(ClientToServer), 0);
+}
+
+#[test]
+fn test_gaps(){
+    let mut streams = StreamReassembler::new(StreamConfig { max_buffer: 8, ..StreamConfig::default() });
+    let mut events = Vec::new();
+
+    // Picked up mid-stream: the first segment seen starts the sequence space
+    push(&mut streams, &mut events, ClientToServer, &segment(700, 300, ACK, b"abc"));
+    push(&mut streams, &mut events, ClientToServer, &segment(713, 300, ACK, b"klm"));
+    // The server acknowledging 713 means 703..713 was lost
+    push(&mut streams, &mut events, ServerToClient, &segment(300, 713, ACK, b""));
+    assert_eq!(events, vec![Event::Data(ClientToServer, b"abc".to_vec()),
+                            Event::Gap(ClientToServer, 10),
+                            Event::Data(ClientToServer, b"klm".to_vec())]);
+    events.clear();
+
+    // Too much buffered behind a hole
+    push(&mut streams, &mut events, ClientToServer, &segment(720, 300, ACK, b"tuvwx"));
+    push(&mut streams, &mut events, ClientToServer, &segment(730, 300, ACK, b"DEFG"));
+    assert_eq!(events, vec![Event::Gap(ClientToServer, 4),
+                            Event::Data(ClientToServer, b"tuvwx".to_vec())]);
+    assert_eq!(streams.buffered(ClientToServer), 4);
+    events.clear();
+
+    // Flushing at the end of the capture
+    push(&mut streams, &mut events, ClientToServer, &segment(740, 300, FIN | ACK, b""));
+    streams.flush_with(ClientToServer, |e| events.push(event(ClientToServer, e)));
+    assert_eq!(events, vec![Event::Gap(ClientToServer, 5),
+                            Event::Data(ClientToServer, b"DEFG".to_vec()),
+                            Event::Gap(ClientToServer, 6),
+                            Event::Fin(ClientToServer)]);
+    assert_eq!(streams.gaps(ClientToServer), &[(3, 10), (16, 4), (25, 5), (34, 6)]);
+}
+
+#[test]
+fn test_reader_events(){
+    let mut streams = StreamReassembler::new(StreamConfig::default());
+    streams.push(ClientToServer, &segment(700, 300, ACK, b"abc"));
+    streams.push(ClientToServer, &segment(713, 300, ACK, b"klm"));
+    streams.push(ClientToServer, &segment(713, 300, ACK, b"kXY"));
+    streams.push(ServerToClient, &segment(300, 713, ACK, b""));
+    streams.push(ClientToServer, &segment(720, 300, FIN | ACK, b"t"));
+    streams.flush(ClientToServer);
+
+    let mut data = String::new();
+    streams.reader(ClientToServer).read_to_string(&mut data).unwrap();
+    assert_eq!(data, "abcklmt");
+    assert_eq!(streams.take_events(), vec![
+        (ClientToServer, KeptEvent::Conflict { offset: 13, kept: b"klm".to_vec(), discarded: b"kXY".to_vec() }),
+        (ClientToServer, KeptEvent::Gap(10)),
+        (ClientToServer, KeptEvent::Gap(4)),
+        (ClientToServer, KeptEvent::Fin)]);
+    assert_eq!(streams.take_events(), vec![]);
+}
+
+#[test]
+fn test_overlap_policies(){
+    // New data at the same offset as buffered data, but shorter
+    let same_start = [(1, "AAAA"), (1, "BB")];
+    // New data starting before buffered data and ending inside it
+    let before = [(2, "AAAA"), (1, "BBB")];
+    // New data starting inside buffered data and ending after it
+    let after = [(1, "AAA"), (2, "BBBB")];
+    // New data covering buffered data on both sides
+    let covering = [(2, "AA"), (1, "BBBB")];
+
+    let expected = [(OverlapPolicy::First,   ["AAAA", "BAAAA", "AAABB", "BAAB"]),
+                    (OverlapPolicy::Last,    ["BBAA", "BBBAA", "ABBBB", "BBBB"]),
+                    (OverlapPolicy::Bsd,     ["AAAA", "BBBAA", "AAABB", "BBBB"]),
+                    (OverlapPolicy::Linux,   ["BBAA", "BBBAA", "AAABB", "BBBB"]),
+                    (OverlapPolicy::Windows, ["AAAA", "BAAAA", "AAABB", "BBBB"]),
+                    (OverlapPolicy::Solaris, ["AAAA", "BAAAA", "ABBBB", "BBBB"]),
+                    (OverlapPolicy::HpUx,    ["AAAA", "BBBAA", "ABBBB", "BBBB"])];
+    for &(policy, ref streams) in expected.iter() {
+        let results = [resolve(policy, &same_start), resolve(policy, &before),
+                       resolve(policy, &after), resolve(policy, &covering)];
+        for (result, stream) in results.iter().zip(streams.iter()) {
+            assert_eq!((policy, &result.0[..], result.1), (policy, *stream, 1));
+        }
+    }
+}
+
+#[test]
+fn test_conflict_events(){
+    let mut streams = StreamReassembler::new(StreamConfig { history: 8, ..StreamConfig::default() });
+    let mut events = Vec::new();
+
+    push(&mut streams, &mut events, ClientToServer, &segment(0, 0, SYN, b""));
+    push(&mut streams, &mut events, ClientToServer, &segment(6, 0, PSH, b"EVIL"));
+    push(&mut streams, &mut events, ClientToServer, &segment(1, 0, PSH, b"hello"));
+    // Identical retransmissions are not conflicts
+    push(&mut streams, &mut events, ClientToServer, &segment(4, 0, PSH, b"lo"));
+    push(&mut streams, &mut events, ClientToServer, &segment(3, 0, PSH, b"LLo good"));
+
+    assert_eq!(events, vec![Event::Data(ClientToServer, b"hello".to_vec()),
+                            Event::Data(ClientToServer, b"EVIL".to_vec()),
+                            Event::Conflict(ClientToServer, 2, b"lloEVIL".to_vec(), b"LLo goo".to_vec()),
+                            Event::Data(ClientToServer, b"d".to_vec())]);
+    assert_eq!(streams.conflicts(ClientToServer), 1);
+}
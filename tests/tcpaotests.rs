@@ -0,0 +1,73 @@
+extern crate tcp_parser;
+use tcp_parser::{TcpSegment, IPv4PseudoHeader, TcpOpts};
+use tcp_parser::tcp_ao::{self, MasterKeyTuple, MacAlgorithm};
+use tcp_parser::util::U8ToU32;
+
+fn mkt(algorithm: MacAlgorithm, include_options: bool) -> MasterKeyTuple {
+    MasterKeyTuple {
+        send_id:            0x54,
+        recv_id:            0x54,
+        master_key:         b"testvector".to_vec(),
+        algorithm:          algorithm,
+        include_options:    include_options
+    }
+}
+
+#[test]
+fn test_syn_hmac_sha1(){
+    let tcp_data : Vec<u8> = vec![0xc0, 0x00, 0x00, 0xb3, 0xfb, 0xfb,
+                                  0xab, 0x5a, 0x00, 0x00, 0x00, 0x00, 0xa0, 0x02,
+                                  0xfa, 0xf0, 0x11, 0x11, 0x00, 0x00, 0x02, 0x04,
+                                  0x05, 0xb4, 0x1d, 0x10, 0x54, 0x54, 0xea, 0xfc,
+                                  0x86, 0x8a, 0x59, 0x45, 0xf2, 0xea, 0xca, 0x83,
+                                  0x5b, 0x32];
+
+    let header = IPv4PseudoHeader {
+        source_addr:    [10, 11, 12, 13].iter().to_u32().unwrap(),
+        dest_addr:      [10, 11, 12, 14].iter().to_u32().unwrap(),
+        protocol:       6,
+        tcp_len:        tcp_data.len() as u16
+    };
+
+    let segment = TcpSegment::parse(&tcp_data);
+    assert_eq!(segment.options[1], TcpOpts::AuthOption {
+        key_id:         0x54,
+        rnext_key_id:   0x54,
+        mac:            vec![0xea, 0xfc, 0x86, 0x8a, 0x59, 0x45, 0xf2, 0xea, 0xca, 0x83, 0x5b, 0x32]
+    });
+    assert_eq!(segment.as_bytestream(), tcp_data);
+
+    // The destination ISN is ignored on a SYN
+    assert!(tcp_ao::verify(&segment, header.clone(), &mkt(MacAlgorithm::HmacSha1_96, true), 0xfbfbab5a, 0x1234, 0));
+    assert!(!tcp_ao::verify(&segment, header.clone(), &mkt(MacAlgorithm::AesCmac128_96, true), 0xfbfbab5a, 0, 0));
+    assert!(!tcp_ao::verify(&segment, header, &mkt(MacAlgorithm::HmacSha1_96, true), 0xfbfbab5a, 0, 1));
+}
+
+#[test]
+fn test_ack_aes_cmac(){
+    let mut tcp_data : Vec<u8> = vec![0x00, 0xb3, 0xc0, 0x00, 0x11, 0xc1,
+                                      0x42, 0x61, 0xfb, 0xfb, 0xab, 0x5b, 0xc0, 0x18,
+                                      0x20, 0x00, 0x22, 0x22, 0x00, 0x00, 0x01, 0x01,
+                                      0x08, 0x0a, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00,
+                                      0x00, 0x02, 0x1d, 0x10, 0x54, 0x54, 0x37, 0x3d,
+                                      0x8a, 0x07, 0x5e, 0x35, 0xa0, 0x6c, 0x4f, 0x0d,
+                                      0x95, 0xd9, 0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x20,
+                                      0x77, 0x6f, 0x72, 0x6c, 0x64];
+
+    let header = IPv4PseudoHeader {
+        source_addr:    [10, 11, 12, 14].iter().to_u32().unwrap(),
+        dest_addr:      [10, 11, 12, 13].iter().to_u32().unwrap(),
+        protocol:       6,
+        tcp_len:        tcp_data.len() as u16
+    };
+
+    let segment = TcpSegment::parse(&tcp_data);
+    assert!(tcp_ao::verify(&segment, header.clone(), &mkt(MacAlgorithm::AesCmac128_96, true), 0x11c14260, 0xfbfbab5a, 0));
+    assert!(!tcp_ao::verify(&segment, header.clone(), &mkt(MacAlgorithm::AesCmac128_96, true), 0x11c14260, 0, 0));
+    assert!(!tcp_ao::verify(&segment, header.clone(), &mkt(MacAlgorithm::AesCmac128_96, false), 0x11c14260, 0xfbfbab5a, 0));
+
+    // Same segment, MAC computed without covering the timestamp option
+    tcp_data[36..48].copy_from_slice(&[0x9f, 0x76, 0x35, 0xe5, 0xcc, 0x2f, 0x46, 0x2e, 0xd6, 0x05, 0xb7, 0xa1]);
+    let segment = TcpSegment::parse(&tcp_data);
+    assert!(tcp_ao::verify(&segment, header, &mkt(MacAlgorithm::AesCmac128_96, false), 0x11c14260, 0xfbfbab5a, 0));
+}
@@ -1,5 +1,5 @@
 extern crate tcp_parser;
-use tcp_parser::{TcpSegment,IPv4PseudoHeader,TcpCTRL, TcpOpts, SYN, ACK, FIN, RST}; 
+use tcp_parser::{TcpSegment, TcpSegmentRef, TcpSegmentBuilder, TcpBuildError, IPv4PseudoHeader, IPv6PseudoHeader, TcpCTRL, TcpOpts, TcpParseError, SYN, ACK, FIN, RST, PSH}; 
 use tcp_parser::util::{U8ToU16, U8ToU32, U32ToU8, U16ToU8, U32ToU16};
 
 #[test]
@@ -13,14 +13,15 @@ fn test_syn(){
         tcp_len     : tcp_data.len() as u16
     };
 
-    let parse_res =  TcpSegment::parse(&tcp_data);
-    //assert!(parse_res.is_ok());
-    //let segment = parse_res.unwrap();
-    let segment = parse_res;
+    let parse_res =  TcpSegment::try_parse(&tcp_data);
+    assert!(parse_res.is_ok());
+    let segment = parse_res.unwrap();
     assert_eq!(segment.src_port, 38772);
     assert_eq!(segment.dest_port, 80);
     assert_eq!(segment.window, 24800);
-    assert_eq!(TcpCTRL::from_bits(segment.ctrl_flags).unwrap(), SYN);
+    assert_eq!(segment.ctrl_flags, SYN);
+    assert!(segment.is_syn() && !segment.is_syn_ack());
+    assert_eq!(format!("{}", segment.ctrl_flags), "S");
     assert_eq!(segment.checksum, 0x5128);
     assert_eq!(segment.seq_num, 0x040cb9a0);
     assert_eq!(segment.ack_num, 0x00000000);
@@ -68,7 +69,9 @@ fn test_syn_ack(){
     assert_eq!(segment.src_port, 80);
     assert_eq!(segment.dest_port, 38582);
     assert_eq!(segment.window, 28960);
-    assert_eq!(TcpCTRL::from_bits(segment.ctrl_flags).unwrap(), SYN | ACK);
+    assert_eq!(segment.ctrl_flags, SYN | ACK);
+    assert!(segment.is_syn_ack() && !segment.is_syn());
+    assert_eq!(format!("{}", segment.ctrl_flags), "S.");
     assert_eq!(segment.checksum, 0xe97a);
     assert_eq!(segment.seq_num, 0xa5ca6022);
     assert_eq!(segment.ack_num, 0xf2f4031d);
@@ -111,7 +114,9 @@ fn test_fin_ack(){
     assert_eq!(segment.src_port, 41520);
     assert_eq!(segment.dest_port, 80);
     assert_eq!(segment.window, 511);
-    assert_eq!(TcpCTRL::from_bits(segment.ctrl_flags).unwrap(), ACK | FIN);
+    assert_eq!(segment.ctrl_flags, ACK | FIN);
+    assert!(segment.is_fin() && !segment.is_pure_ack());
+    assert_eq!(format!("{}", segment.ctrl_flags), "F.");
     assert_eq!(segment.checksum, 0x8bbc);
     assert_eq!(segment.seq_num, 0x30_F2_AD_35);
     assert_eq!(segment.ack_num, 0xCE_1B_58_05);
@@ -152,7 +157,9 @@ fn test_ack_rst(){
     assert_eq!(segment.src_port, 38656);
     assert_eq!(segment.dest_port, 443);
     assert_eq!(segment.window, 297);
-    assert_eq!(TcpCTRL::from_bits(segment.ctrl_flags).unwrap(), RST | ACK);
+    assert_eq!(segment.ctrl_flags, RST | ACK);
+    assert!(segment.is_rst());
+    assert_eq!(format!("{}", segment.ctrl_flags), "R.");
     assert_eq!(segment.checksum, 0xb16c);
     assert_eq!(segment.seq_num, 0xCC_0F_70_DB);
     assert_eq!(segment.ack_num, 0x73_A3_00_E0);
@@ -173,3 +180,283 @@ fn test_ack_rst(){
     assert_eq!(segment.calculate_checksum(header), segment.checksum);
     assert_eq!(segment.as_bytestream(), tcp_data);
 }
+
+#[test]
+fn test_try_parse_truncated_header(){
+    let tcp_data : Vec<u8> = vec![0x97, 0x00, 0x01, 0xbb, 0xcc, 0x0f, 0x70, 0xdb, 0x73, 0xa3];
+
+    assert_eq!(TcpSegment::try_parse(&tcp_data), Err(TcpParseError::InvalidLength(10)));
+}
+
+#[test]
+fn test_try_parse_bad_data_offset(){
+    let mut tcp_data : Vec<u8> = vec![0x97, 0x00, 0x01, 0xbb, 0xcc, 0x0f,
+                                      0x70, 0xdb, 0x73, 0xa3, 0x00, 0xe0, 0x40, 0x14,
+                                      0x01, 0x29, 0xb1, 0x6c, 0x00, 0x00];
+
+    assert_eq!(TcpSegment::try_parse(&tcp_data), Err(TcpParseError::InvalidDataOffset(12)));
+
+    tcp_data[12] = 0x80;
+    assert_eq!(TcpSegment::try_parse(&tcp_data), Err(TcpParseError::DataOffsetOverflow(12)));
+
+    tcp_data[12] = 0x5E;
+    assert_eq!(TcpSegment::try_parse(&tcp_data), Err(TcpParseError::InvalidReserved(12)));
+}
+
+#[test]
+fn test_parse_lenient_reserved(){
+    let tcp_data : Vec<u8> = vec![0x97, 0x00, 0x01, 0xbb, 0xcc, 0x0f,
+                                  0x70, 0xdb, 0x73, 0xa3, 0x00, 0xe0, 0x5E, 0x14,
+                                  0x01, 0x29, 0xb1, 0x6c, 0x00, 0x00];
+
+    let segment = TcpSegment::try_parse_lenient(&tcp_data).unwrap();
+    assert_eq!(segment.reserved, 0b111);
+    assert_eq!(segment.ctrl_flags, ACK | RST);
+
+    let view = TcpSegmentRef::parse(&tcp_data).unwrap();
+    assert_eq!(view.reserved(), 0b111);
+    assert_eq!(view.to_owned(), segment);
+}
+
+#[test]
+#[should_panic(expected = "InvalidReserved(12)")]
+fn test_parse_reserved(){
+    let tcp_data : Vec<u8> = vec![0x97, 0x00, 0x01, 0xbb, 0xcc, 0x0f,
+                                  0x70, 0xdb, 0x73, 0xa3, 0x00, 0xe0, 0x5E, 0x14,
+                                  0x01, 0x29, 0xb1, 0x6c, 0x00, 0x00];
+    TcpSegment::parse(&tcp_data);
+}
+
+#[test]
+fn test_try_parse_bad_option(){
+    // NOP, NOP, then a timestamp claiming length 9
+    let tcp_data : Vec<u8> = vec![0x97, 0x00, 0x01, 0xbb, 0xcc, 0x0f,
+                                  0x70, 0xdb, 0x73, 0xa3, 0x00, 0xe0, 0x80, 0x14,
+                                  0x01, 0x29, 0xb1, 0x6c, 0x00, 0x00, 0x01, 0x01,
+                                  0x08, 0x09, 0x01, 0xb5, 0xf3, 0x30, 0xb1, 0xd7,
+                                  0xa2, 0xdc];
+
+    let err = TcpSegment::try_parse(&tcp_data).unwrap_err();
+    assert_eq!(err, TcpParseError::InvalidOption(22));
+    assert_eq!(err.offset(), 22);
+}
+
+#[test]
+fn test_segment_ref(){
+    let tcp_data: Vec<u8> =vec![0xa2, 0x30, 0x00, 0x50, 0x30, 0xf2,
+                                0xad, 0x35, 0xce, 0x1b, 0x58, 0x05, 0x80, 0x11,
+                                0x01, 0xff, 0x8b, 0xbc, 0x00, 0x00, 0x01, 0x01,
+                                0x08, 0x0a, 0x01, 0x3d, 0x39, 0x49, 0x82, 0xe8,
+                                0x29, 0xdf, 0xde, 0xad];
+
+    let segment = TcpSegmentRef::parse(&tcp_data).unwrap();
+    assert_eq!(segment.src_port(), 41520);
+    assert_eq!(segment.dest_port(), 80);
+    assert_eq!(segment.seq_num(), 0x30_F2_AD_35);
+    assert_eq!(segment.ack_num(), 0xCE_1B_58_05);
+    assert_eq!(segment.data_off(), 8);
+    assert_eq!(segment.ctrl_flags(), ACK | FIN);
+    assert_eq!(segment.window(), 511);
+    assert_eq!(segment.checksum(), 0x8bbc);
+    assert_eq!(segment.urg_ptr(), 0);
+    assert_eq!(segment.data(), &[0xde, 0xad]);
+
+    let opts_expected = vec![
+        TcpOpts::NOP,
+        TcpOpts::NOP,
+        TcpOpts::TimeStamp{time: 20789577, echo: 2196253151}
+    ];
+    assert_eq!(segment.options().collect::<Vec<_>>(), opts_expected);
+
+    assert_eq!(segment.to_owned(), TcpSegment::parse(&tcp_data));
+    assert_eq!(TcpSegmentRef::parse(&tcp_data[..10]), Err(TcpParseError::InvalidLength(10)));
+}
+
+#[test]
+fn test_unknown_option_and_padding(){
+    let tcp_data : Vec<u8> = vec![0x97, 0x00, 0x01, 0xbb, 0xcc, 0x0f,
+                                  0x70, 0xdb, 0x73, 0xa3, 0x00, 0xe0, 0xa0, 0x10,
+                                  0x01, 0x29, 0x00, 0x00, 0x00, 0x00, 0x01, 0x01,
+                                  0x08, 0x0a, 0x01, 0xb5, 0xf3, 0x30, 0xb1, 0xd7,
+                                  0xa2, 0xdc, 0xfd, 0x04, 0xf9, 0x89, 0x00, 0x00,
+                                  0x00, 0x00];
+
+    let segment = TcpSegment::try_parse(&tcp_data).unwrap();
+    let opts_expected = vec![
+        TcpOpts::NOP,
+        TcpOpts::NOP,
+        TcpOpts::TimeStamp{time: 28701488, echo: 2983699164},
+        TcpOpts::Unknown{kind: 253, data: vec![0xf9, 0x89]},
+        TcpOpts::END,
+        TcpOpts::Padding(vec![0, 0, 0])
+    ];
+
+    assert_eq!(segment.options, opts_expected);
+    assert_eq!(segment.as_bytestream(), tcp_data);
+}
+
+#[test]
+fn test_md5_signature(){
+    let tcp_data : Vec<u8> = vec![0x00, 0xb3, 0xc0, 0x01, 0x00, 0x00,
+                                  0x10, 0x00, 0x00, 0x00, 0x20, 0x00, 0xa0, 0x18,
+                                  0x40, 0x00, 0x12, 0x34, 0x00, 0x00, 0x01, 0x01,
+                                  0x13, 0x12, 0xbf, 0xe5, 0x95, 0xc7, 0xf5, 0xbc,
+                                  0xe2, 0x6b, 0x30, 0xdd, 0x99, 0xc0, 0xa9, 0x74,
+                                  0xbe, 0xeb, 0x68, 0x65, 0x6c, 0x6c, 0x6f];
+
+    let header = IPv4PseudoHeader {
+        source_addr:    [10, 0, 0, 1].iter().to_u32().unwrap(),
+        dest_addr:      [10, 0, 0, 2].iter().to_u32().unwrap(),
+        protocol:       6,
+        tcp_len:        tcp_data.len() as u16
+    };
+
+    let segment = TcpSegment::parse(&tcp_data);
+    let sig = [0xbf, 0xe5, 0x95, 0xc7, 0xf5, 0xbc, 0xe2, 0x6b,
+               0x30, 0xdd, 0x99, 0xc0, 0xa9, 0x74, 0xbe, 0xeb];
+    assert_eq!(segment.options, vec![TcpOpts::NOP, TcpOpts::NOP, TcpOpts::Md5Signature(sig)]);
+    assert_eq!(segment.md5_signature(), Some(sig));
+
+    assert_eq!(segment.calculate_md5_signature(header.clone(), b"secret"), sig);
+    assert!(segment.verify_md5_signature(header.clone(), b"secret"));
+    assert!(!segment.verify_md5_signature(header, b"wrong"));
+    assert_eq!(segment.as_bytestream(), tcp_data);
+}
+
+#[test]
+fn test_fast_open_cookie(){
+    let tcp_data : Vec<u8> = vec![0xd4, 0x31, 0x00, 0x50, 0x1e, 0x2a,
+                                  0x8c, 0x41, 0x00, 0x00, 0x00, 0x00, 0x90, 0x02,
+                                  0xfa, 0xf0, 0x00, 0x00, 0x00, 0x00, 0x02, 0x04,
+                                  0x05, 0xb4, 0x01, 0x01, 0x22, 0x0a, 0x8c, 0x1f,
+                                  0x3e, 0x77, 0x0a, 0x4d, 0x52, 0x10, 0x47, 0x45,
+                                  0x54, 0x20, 0x2f, 0x20, 0x48, 0x54, 0x54, 0x50,
+                                  0x2f, 0x31, 0x2e, 0x31, 0x0d, 0x0a];
+    let cookie = vec![0x8c, 0x1f, 0x3e, 0x77, 0x0a, 0x4d, 0x52, 0x10];
+
+    let segment = TcpSegment::parse(&tcp_data);
+    assert_eq!(segment.options[3], TcpOpts::FastOpenCookie(cookie.clone()));
+    assert_eq!(segment.fast_open_cookie(), Some(&cookie[..]));
+    assert!(segment.has_fast_open_data());
+    assert_eq!(segment.as_bytestream(), tcp_data);
+
+    let tcp_data : Vec<u8> = vec![0xd4, 0x31, 0x00, 0x50, 0x1e, 0x2a,
+                                  0x8c, 0x41, 0x00, 0x00, 0x00, 0x00, 0x90, 0x02,
+                                  0xfa, 0xf0, 0x00, 0x00, 0x00, 0x00, 0x02, 0x04,
+                                  0x05, 0xb4, 0xfe, 0x0c, 0xf9, 0x89, 0x8c, 0x1f,
+                                  0x3e, 0x77, 0x0a, 0x4d, 0x52, 0x10];
+
+    let segment = TcpSegment::parse(&tcp_data);
+    assert_eq!(segment.options[1], TcpOpts::FastOpenCookieExperimental(cookie.clone()));
+    assert_eq!(segment.fast_open_cookie(), Some(&cookie[..]));
+    assert!(!segment.has_fast_open_data());
+    assert_eq!(segment.as_bytestream(), tcp_data);
+}
+
+#[test]
+fn test_flags_display(){
+    assert_eq!(format!("{}", ACK), ".");
+    assert_eq!(format!("{}", FIN | PSH | ACK), "FP.");
+    assert_eq!(format!("{}", TcpCTRL::empty()), "none");
+    assert_eq!(TcpCTRL::from_bits_truncate(0xF012), SYN | ACK);
+}
+
+#[test]
+fn test_builder(){
+    let tcp_data : Vec<u8> = vec![151, 116, 0, 80, 4, 12, 185, 160, 0, 0, 0, 0, 160, 2, 96, 224, 81, 40, 0, 0, 2, 4, 4, 216, 4, 2, 8, 10, 1, 49, 10, 120, 0, 0, 0, 0, 1, 3, 3, 7];
+
+    let header = IPv4PseudoHeader {
+        source_addr : [192, 168, 2, 29].iter().to_u32().unwrap(),
+        dest_addr   : [184, 150, 186, 93].iter().to_u32().unwrap(),
+        protocol    : 6,
+        tcp_len     : 0
+    };
+
+    let segment = TcpSegmentBuilder::new()
+        .src_port(38772)
+        .dest_port(80)
+        .seq_num(0x040cb9a0)
+        .flags(SYN)
+        .window(24800)
+        .options(vec![
+            TcpOpts::MSS(1240),
+            TcpOpts::SAckPermitted,
+            TcpOpts::TimeStamp { time: 19991160, echo: 0 },
+            TcpOpts::NOP,
+            TcpOpts::WindowScale(7)
+        ])
+        .build(header.clone())
+        .unwrap();
+
+    assert_eq!(segment, TcpSegment::parse(&tcp_data));
+    assert_eq!(segment.as_bytestream(), tcp_data);
+
+    let segment = TcpSegmentBuilder::new()
+        .flags(SYN)
+        .option(TcpOpts::MSS(1460))
+        .option(TcpOpts::WindowScale(7))
+        .data(b"data")
+        .build(header.clone())
+        .unwrap();
+
+    assert_eq!(segment.data_off, 7);
+    assert_eq!(segment.options[2..], [TcpOpts::END]);
+    assert_eq!(TcpSegment::parse(segment.as_bytestream()), segment);
+
+    let sack = TcpOpts::SAck(vec![(1, 2), (3, 4), (5, 6), (7, 8)]);
+    let time = TcpOpts::TimeStamp { time: 1, echo: 2 };
+    assert_eq!(TcpSegmentBuilder::new().option(time).option(sack).build(header.clone()),
+               Err(TcpBuildError::OptionsTooLong(44)));
+
+    // Too long for the one byte length field of the option itself
+    let unknown = TcpOpts::Unknown { kind: 253, data: (0..300).map(|_| 0).collect() };
+    assert_eq!(TcpSegmentBuilder::new().option(unknown).build(header.clone()),
+               Err(TcpBuildError::OptionsTooLong(302)));
+    let sack = TcpOpts::SAck((0..40).map(|i| (i, i + 1)).collect());
+    assert_eq!(TcpSegmentBuilder::new().option(sack).build(header),
+               Err(TcpBuildError::OptionsTooLong(322)));
+}
+
+#[test]
+fn test_ipv6_checksum(){
+    // A Linux handshake captured on a veth link, with checksum offload disabled
+    let client = [0xfd, 0x10, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01];
+    let server = [0xfd, 0x10, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x02];
+
+    let syn : Vec<u8> = vec![0x9f, 0x0e, 0x00, 0x50, 0x68, 0x5f,
+                             0x03, 0x2a, 0x00, 0x00, 0x00, 0x00, 0xa0, 0x02,
+                             0xfd, 0x20, 0xbf, 0x35, 0x00, 0x00, 0x02, 0x04,
+                             0x05, 0xa0, 0x04, 0x02, 0x08, 0x0a, 0xa1, 0xda,
+                             0xe4, 0xd3, 0x00, 0x00, 0x00, 0x00, 0x01, 0x03,
+                             0x03, 0x0a];
+
+    let syn_ack : Vec<u8> = vec![0x00, 0x50, 0x9f, 0x0e, 0x16, 0xf1,
+                                 0x2f, 0x3f, 0x68, 0x5f, 0x03, 0x2b, 0xa0, 0x12,
+                                 0xfb, 0x04, 0xf3, 0xd9, 0x00, 0x00, 0x02, 0x04,
+                                 0x05, 0xa0, 0x04, 0x02, 0x08, 0x0a, 0x7e, 0x32,
+                                 0x09, 0x04, 0xa1, 0xda, 0xe4, 0xd3, 0x01, 0x03,
+                                 0x03, 0x0a];
+
+    let header = IPv6PseudoHeader {
+        source_addr:    client,
+        dest_addr:      server,
+        tcp_len:        syn.len() as u32,
+        next_header:    6
+    };
+    let segment = TcpSegment::parse(&syn);
+    assert_eq!(segment.calculate_checksum(header.clone()), 0xbf35);
+    assert!(segment.verify_checksum(header.clone()));
+
+    let reply = IPv6PseudoHeader {
+        source_addr:    server,
+        dest_addr:      client,
+        tcp_len:        syn_ack.len() as u32,
+        next_header:    6
+    };
+    let segment = TcpSegment::parse(&syn_ack);
+    assert!(segment.verify_checksum(reply.clone()));
+
+    let mut other = reply;
+    other.dest_addr[15] = 0x03;
+    assert!(!segment.verify_checksum(other));
+}
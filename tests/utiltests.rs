@@ -64,3 +64,29 @@ mod test_u32_to_u8 {
     }
 }
 
+
+mod test_u8_to_u64 {
+    use tcp_parser::util::U8ToU64;
+
+    #[test]
+    fn test_invalid_length(){
+        let x : [u8; 7] = [1,2,3,4,5,6,7];
+        assert_eq!(x.iter().to_u64(), None);
+    }
+
+    #[test]
+    fn test_works() {
+        let x : [u8; 8] = [0xde, 0xad, 0xbe, 0xef, 0xca, 0xfe, 0xba, 0xbe];
+        assert_eq!(x.iter().to_u64(), Some(0xdeadbeefcafebabe));
+    }
+}
+
+mod test_u64_to_u8 {
+    use tcp_parser::util::U64ToU8;
+
+    #[test]
+    fn test_it_works(){
+        let x : u64 = 0xDEADBEEFCAFEBABE;
+        assert_eq!(x.to_u8(), [0xDE, 0xAD, 0xBE, 0xEF, 0xCA, 0xFE, 0xBA, 0xBE]);
+    }
+}
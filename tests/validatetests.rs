@@ -0,0 +1,71 @@
+extern crate tcp_parser;
+use tcp_parser::{TcpSegment, TcpOpts};
+use tcp_parser::validate::{self, OptionValidator, OptionWarning};
+
+use tcp_parser::stream::Direction::{ClientToServer, ServerToClient};
+
+/// A segment of the given flags whose options area is `options`, a multiple of four bytes
+fn segment(flags : u8, options : &[u8]) -> Vec<u8> {
+    let mut bytes = vec![0x30, 0x39, 0, 80, 0, 0, 0, 1, 0, 0, 0, 1, (5 + options.len() as u8 / 4) << 4, flags,
+                         1, 0, 0, 0, 0, 0];
+    bytes.extend(options.iter().cloned());
+    bytes
+}
+
+#[test]
+fn test_validate(){
+    let mut ack = TcpSegment::parse(segment(0x10, &[]));
+    assert_eq!(validate::validate(&ack), vec![]);
+    ack.options = vec![TcpOpts::NOP, TcpOpts::WindowScale(15), TcpOpts::SAckPermitted, TcpOpts::SAckPermitted,
+                       TcpOpts::SAck(vec![(1, 2), (3, 4), (5, 6), (7, 8), (9, 10)]), TcpOpts::END,
+                       TcpOpts::Padding(vec![1, 0, 0])];
+    assert_eq!(validate::validate(&ack), vec![OptionWarning::SynOnly(3),
+                                              OptionWarning::ShiftTooLarge(15),
+                                              OptionWarning::SynOnly(4),
+                                              OptionWarning::SynOnly(4),
+                                              OptionWarning::Duplicate(4),
+                                              OptionWarning::SackBlocks(5),
+                                              OptionWarning::NonZeroPadding]);
+
+    // The same options are fine on a SYN, and an MSS hidden after END is found
+    let syn = TcpSegment::parse(segment(0x02, &[2, 4, 5, 0xB4, 1, 3, 3, 7, 0, 2, 4, 5, 0xB4, 0, 0, 0]));
+    assert_eq!(validate::validate(&syn), vec![OptionWarning::OptionAfterEnd]);
+}
+
+#[test]
+fn test_validate_bytes(){
+    // A SACK of length 11 does not parse, but its length is still reported
+    let bytes = segment(0x10, &[2, 3, 5, 5, 11, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0]);
+    assert!(TcpSegment::try_parse(&bytes).is_err());
+    assert_eq!(validate::validate_bytes(&bytes), vec![OptionWarning::BadLength { kind: 2, len: 3 },
+                                                      OptionWarning::BadLength { kind: 5, len: 11 }]);
+    let bytes = segment(0x10, &[1, 1, 5, 10, 0, 0, 0, 1, 0, 0, 0, 2]);
+    assert_eq!(validate::validate_bytes(&bytes), vec![]);
+    assert_eq!(validate::validate_bytes(&bytes[..10]), vec![]);
+
+    // Reserved bits do not hide the options
+    let mut bytes = segment(0x10, &[2, 4, 5, 0xB4]);
+    bytes[12] |= 0x0E;
+    assert_eq!(validate::validate_bytes(&bytes), vec![OptionWarning::SynOnly(2)]);
+}
+
+#[test]
+fn test_missing_timestamp(){
+    let timestamp = [1, 1, 8, 10, 0, 0, 0, 1, 0, 0, 0, 0];
+    let mut validator = OptionValidator::new();
+    validator.push(ClientToServer, &TcpSegment::parse(segment(0x02, &timestamp)));
+    assert_eq!(validator.push(ClientToServer, &TcpSegment::parse(segment(0x10, &[]))), vec![]);
+    validator.push(ServerToClient, &TcpSegment::parse(segment(0x12, &timestamp)));
+    assert!(validator.timestamps_negotiated());
+    assert_eq!(validator.push(ClientToServer, &TcpSegment::parse(segment(0x10, &timestamp))), vec![]);
+    assert_eq!(validator.push(ServerToClient, &TcpSegment::parse(segment(0x10, &[]))),
+               vec![OptionWarning::MissingTimestamp]);
+    // Resets may leave out timestamps (RFC 7323 section 3.2)
+    assert_eq!(validator.push(ServerToClient, &TcpSegment::parse(segment(0x04, &[]))), vec![]);
+
+    // Not negotiated when only one end offered them
+    let mut validator = OptionValidator::new();
+    validator.push(ClientToServer, &TcpSegment::parse(segment(0x02, &timestamp)));
+    validator.push(ServerToClient, &TcpSegment::parse(segment(0x12, &[])));
+    assert_eq!(validator.push(ClientToServer, &TcpSegment::parse(segment(0x10, &[]))), vec![]);
+}
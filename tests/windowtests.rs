@@ -0,0 +1,80 @@
+extern crate tcp_parser;
+use tcp_parser::{TcpSegment, TcpSegmentBuilder, TcpCTRL, TcpOpts, IPv4PseudoHeader, SYN, ACK, RST};
+use tcp_parser::window::{WindowTracker, WindowEvent, ZeroWindow};
+
+use tcp_parser::stream::Direction::{ClientToServer, ServerToClient};
+
+fn segment(flags : TcpCTRL, window : u16, scale : Option<u8>) -> TcpSegment {
+    let mut builder = TcpSegmentBuilder::new()
+        .src_port(40000)
+        .dest_port(80)
+        .flags(flags)
+        .window(window);
+    if let Some(shift) = scale {
+        builder = builder.option(TcpOpts::NOP).option(TcpOpts::WindowScale(shift));
+    }
+    builder.build(IPv4PseudoHeader { source_addr: 1, dest_addr: 2, protocol: 6, tcp_len: 0 }).unwrap()
+}
+
+#[test]
+fn test_negotiation(){
+    let mut tracker = WindowTracker::new();
+    assert_eq!(tracker.push(ClientToServer, &segment(SYN, 64240, Some(8)), 0), vec![]);
+    assert_eq!(tracker.shift(ClientToServer), None);
+    // A shift above 14 is capped
+    assert_eq!(tracker.push(ServerToClient, &segment(SYN | ACK, 65160, Some(15)), 1),
+               vec![WindowEvent::ShiftTooLarge { direction: ServerToClient, shift: 15 }]);
+    assert_eq!((tracker.shift(ClientToServer), tracker.shift(ServerToClient)), (Some(8), Some(14)));
+
+    // SYN windows are never scaled
+    assert_eq!(tracker.effective_window(ServerToClient, &segment(SYN | ACK, 65160, Some(15))), 65160);
+    assert_eq!(tracker.effective_window(ClientToServer, &segment(ACK, 502, None)), 502 * 256);
+    assert_eq!(tracker.effective_window(ServerToClient, &segment(ACK, 4, None)), 4 << 14);
+
+    // A retransmitted SYN changing its offer, and an offer outside a SYN
+    assert_eq!(tracker.push(ClientToServer, &segment(SYN, 64240, Some(7)), 2),
+               vec![WindowEvent::ShiftChanged { direction: ClientToServer, first: Some(8), second: Some(7) }]);
+    assert_eq!(tracker.push(ClientToServer, &segment(ACK, 502, Some(2)), 3),
+               vec![WindowEvent::ShiftOutsideSyn(ClientToServer)]);
+    assert_eq!(tracker.shift(ClientToServer), Some(8));
+
+    // Scaling needs both ends to offer it
+    let mut tracker = WindowTracker::new();
+    tracker.push(ClientToServer, &segment(SYN, 64240, Some(8)), 0);
+    assert_eq!(tracker.push(ServerToClient, &segment(SYN | ACK, 8192, None), 1),
+               vec![WindowEvent::ScalingRefused { offered_by: ClientToServer }]);
+    assert_eq!(tracker.effective_window(ClientToServer, &segment(ACK, 502, None)), 502);
+
+    // Without the handshake, windows are taken as they are
+    let mut tracker = WindowTracker::new();
+    assert_eq!(tracker.push(ServerToClient, &segment(ACK, 502, None), 0), vec![WindowEvent::HandshakeMissed]);
+    assert_eq!(tracker.shift(ServerToClient), None);
+    assert_eq!(tracker.effective_window(ServerToClient, &segment(ACK, 502, None)), 502);
+}
+
+#[test]
+fn test_zero_windows(){
+    let mut tracker = WindowTracker::new();
+    tracker.push(ClientToServer, &segment(SYN, 64240, Some(7)), 0);
+    tracker.push(ServerToClient, &segment(SYN | ACK, 65160, Some(7)), 1);
+    assert_eq!(tracker.push(ServerToClient, &segment(ACK, 0, None), 100), vec![WindowEvent::ZeroWindowStart(ServerToClient)]);
+    assert_eq!(tracker.push(ServerToClient, &segment(ACK, 0, None), 200), vec![]);
+    assert_eq!(tracker.push(ClientToServer, &segment(ACK, 100, None), 250), vec![]);
+    // A RST does not advertise a window
+    assert_eq!(tracker.push(ServerToClient, &segment(RST, 10, None), 260), vec![]);
+    assert_eq!(tracker.push(ServerToClient, &segment(ACK, 10, None), 300), vec![WindowEvent::ZeroWindowEnd(ServerToClient, 200)]);
+    tracker.push(ServerToClient, &segment(ACK, 0, None), 400);
+
+    assert_eq!(tracker.zero_windows(ServerToClient), &[ZeroWindow { start: 100, end: Some(300) },
+                                                       ZeroWindow { start: 400, end: None }]);
+    assert!(tracker.zero_windows(ClientToServer).is_empty());
+}
+
+#[test]
+fn test_zero_window_reopened_earlier(){
+    // A reordered capture may reopen the window before it closed
+    let mut tracker = WindowTracker::new();
+    tracker.push(ServerToClient, &segment(ACK, 0, None), 500);
+    assert_eq!(tracker.push(ServerToClient, &segment(ACK, 10, None), 450), vec![WindowEvent::ZeroWindowEnd(ServerToClient, 0)]);
+    assert_eq!(tracker.zero_windows(ServerToClient), &[ZeroWindow { start: 500, end: Some(450) }]);
+}